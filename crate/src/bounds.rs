@@ -0,0 +1,211 @@
+/*
+ * Bounding volumes
+ *
+ * Aabb and BoundingSphere are in the mesh's local space, set once when the mesh is created
+ * (for gltf, from the POSITION accessor's min/max, see add_gltf_primitive)
+ *
+ * WorldBounds is derived from those and the WorldTransform via update_world_bounds_sys
+ * and is what culling (and anything else that cares about where things are) should use
+ */
+use crate::prelude::*;
+use nalgebra_glm::{Vec3, Vec4, Mat4};
+
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    // Arvo's method - same result as transforming all 8 corners, without doing that
+    pub fn transform(&self, mat: &Mat4) -> Aabb {
+        let translation = Vec3::new(mat[(0, 3)], mat[(1, 3)], mat[(2, 3)]);
+        let mut min = translation;
+        let mut max = translation;
+
+        for i in 0..3 {
+            for j in 0..3 {
+                let a = mat[(i, j)] * self.min[j];
+                let b = mat[(i, j)] * self.max[j];
+                min[i] += a.min(b);
+                max[i] += a.max(b);
+            }
+        }
+
+        Aabb { min, max }
+    }
+
+    // slab test, returns the distance along the ray to where it enters the box (0 if it starts inside)
+    // distance is in units of direction, i.e. world units if it's normalized
+    pub fn intersect_ray(&self, origin: &Vec3, direction: &Vec3) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::MAX;
+
+        for i in 0..3 {
+            // division by zero gives infinity, which is exactly what the slab test wants
+            let inv = 1.0 / direction[i];
+            let t0 = (self.min[i] - origin[i]) * inv;
+            let t1 = (self.max[i] - origin[i]) * inv;
+
+            // min/max ignore the NaN from 0 * infinity (origin exactly on a parallel slab)
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn transform(&self, mat: &Mat4) -> BoundingSphere {
+        let center = mat.transform_point(&self.center.into()).coords;
+
+        // non-uniform scale makes it an ellipsoid, so take the biggest axis
+        let scale = (0..3)
+            .map(|i| mat.fixed_slice::<3, 1>(0, i).norm())
+            .fold(0.0f32, f32::max);
+
+        BoundingSphere {
+            center,
+            radius: self.radius * scale,
+        }
+    }
+}
+
+impl From<&Aabb> for BoundingSphere {
+    fn from(aabb: &Aabb) -> Self {
+        Self {
+            center: aabb.center(),
+            radius: aabb.extents().norm(),
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct WorldBounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+    // what these were computed from, to skip the work when nothing moved
+    pub(crate) transform: Mat4,
+}
+
+impl WorldBounds {
+    pub fn new(aabb: &Aabb, sphere: &BoundingSphere, transform: &Mat4) -> Self {
+        Self {
+            aabb: aabb.transform(transform),
+            sphere: sphere.transform(transform),
+            transform: transform.clone(),
+        }
+    }
+}
+
+// planes point inwards, xyz is the normal and w is the distance
+#[derive(Clone, Debug)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Gribb/Hartmann, works for both perspective and orthographic
+    pub fn new(view_projection: &Mat4) -> Self {
+        let row = |i:usize| view_projection.row(i).transpose();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        let mut planes = [
+            r3 + r0, // left
+            r3 - r0, // right
+            r3 + r1, // bottom
+            r3 - r1, // top
+            r3 + r2, // near
+            r3 - r2, // far
+        ];
+
+        for plane in planes.iter_mut() {
+            let len = plane.xyz().norm();
+            if len > 0.0 {
+                *plane /= len;
+            }
+        }
+
+        Self { planes }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| {
+            plane.xyz().dot(&sphere.center) + plane.w >= -sphere.radius
+        })
+    }
+
+    // the "positive vertex" test, can have false positives near the corners but never false negatives
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let p = Vec3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.xyz().dot(&p) + plane.w >= 0.0
+        })
+    }
+
+    // sphere first since it's cheaper, aabb is tighter
+    pub fn intersects(&self, bounds: &WorldBounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+// skinned meshes are never culled, since their bounds are only for the bind pose
+// morphed meshes are, their bounds already include every target (see gltf/primitive.rs)
+// and entities without bounds are always drawn
+pub(crate) fn is_culled(frustum: Option<&Frustum>, entity: EntityId, mesh: &Mesh, world_bounds: &View<WorldBounds>) -> bool {
+    match (frustum, world_bounds.get(entity)) {
+        (Some(frustum), Ok(bounds)) if mesh.skin_joints.is_empty() => !frustum.intersects(bounds),
+        _ => false
+    }
+}
+
+// updated every frame by render_sys
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullingStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+impl AwsmRenderer {
+    // None if there's no active camera
+    pub fn camera_frustum(&self) -> Option<Frustum> {
+        self.camera.get_active_dyn().map(|camera| {
+            let view_projection:Mat4 = (camera.projection() * camera.view()).cast();
+            Frustum::new(&view_projection)
+        })
+    }
+}
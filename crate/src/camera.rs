@@ -1,12 +1,14 @@
 pub mod arc_ball;
 pub mod screen_static;
 pub mod traits;
+pub mod scene_camera;
 
 use crate::renderer::AwsmRenderer;
-use self::{arc_ball::ArcBall, screen_static::ScreenStatic};
+use self::{arc_ball::ArcBall, screen_static::ScreenStatic, scene_camera::SceneCameraView};
 use awsm_web::webgl::{Id, WebGl2Renderer, BufferUsage};
 use crate::prelude::*;
 use traits::CameraBase;
+use nalgebra_glm::Mat4;
 use crate::constants::UBO_CAMERA;
 use shipyard_scenegraph::math::nalgebra_common::*;
 
@@ -19,7 +21,9 @@ pub struct Camera {
 
 pub enum CameraKind {
     ArcBall(ArcBall),
-    ScreenStatic(ScreenStatic)
+    ScreenStatic(ScreenStatic),
+    // follows a SceneCamera entity, see set_scene_camera_active()
+    Scene(SceneCameraView),
 }
 
 
@@ -47,6 +51,9 @@ impl Camera {
                 CameraKind::ScreenStatic(camera) => {
                     camera
                 }
+                CameraKind::Scene(camera) => {
+                    camera
+                }
             }
         })
     }
@@ -62,6 +69,9 @@ impl AwsmRenderer {
                 CameraKind::ScreenStatic(camera) => {
                     camera.update_viewport(width, height);
                 }
+                CameraKind::Scene(camera) => {
+                    camera.update_viewport(width, height);
+                }
             }
         }
 
@@ -88,6 +98,13 @@ impl AwsmRenderer {
                     camera.view_projection_direction_inverse().write_to_vf32(&mut self.camera.scratch_buffer[48..64]);
                     camera.position().write_to_vf32(&mut self.camera.scratch_buffer[64..]);
                 }
+                CameraKind::Scene(camera) => {
+                    camera.view().write_to_vf32(&mut self.camera.scratch_buffer[0..16]);
+                    camera.projection().write_to_vf32(&mut self.camera.scratch_buffer[16..32]);
+                    camera.view_projection_inverse().write_to_vf32(&mut self.camera.scratch_buffer[32..48]);
+                    camera.view_projection_direction_inverse().write_to_vf32(&mut self.camera.scratch_buffer[48..64]);
+                    camera.position().write_to_vf32(&mut self.camera.scratch_buffer[64..]);
+                }
             }
 
             gl.upload_uniform_buffer_f32(
@@ -103,4 +120,23 @@ impl AwsmRenderer {
             Ok(false)
         }
     }
+
+    // temporarily replaces the view and projection, e.g. for rendering from a light's point of view
+    // the next call to update_camera_ubo() restores the real camera
+    pub(crate) fn upload_camera_ubo_override(&mut self, view: &Mat4, projection: &Mat4) -> Result<()> {
+        let gl = &mut self.gl;
+
+        self.camera.scratch_buffer[0..16].copy_from_slice(view.as_slice());
+        self.camera.scratch_buffer[16..32].copy_from_slice(projection.as_slice());
+
+        gl.upload_uniform_buffer_f32(
+            self.camera.buffer_id,
+            &self.camera.scratch_buffer,
+            BufferUsage::DynamicDraw,
+        )?;
+
+        gl.activate_uniform_buffer_loc(self.camera.buffer_id, UBO_CAMERA);
+
+        Ok(())
+    }
 }
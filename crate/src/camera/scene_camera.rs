@@ -0,0 +1,183 @@
+/*
+ * Cameras that live in the scene, e.g. imported from glTF
+ *
+ * SceneCamera is just the projection, as a component on an entity
+ * the view comes from that entity's WorldTransform (looking down -Z, same as glTF)
+ * so animating or parenting the entity moves the camera
+ *
+ * Any of them can be made the active camera via renderer.set_scene_camera_active()
+ */
+use crate::prelude::*;
+use crate::camera::{CameraKind, traits::*};
+use nalgebra::{Matrix4, Vector3, Vector4};
+use nalgebra_glm::Mat4;
+
+#[derive(Component, Clone, Debug)]
+pub enum SceneCamera {
+    Perspective {
+        // vertical field of view, in radians
+        yfov: f64,
+        // None is the viewport's
+        aspect_ratio: Option<f64>,
+        znear: f64,
+        // None is infinite
+        zfar: Option<f64>,
+    },
+    Orthographic {
+        // half the width and height of the view volume
+        xmag: f64,
+        ymag: f64,
+        znear: f64,
+        zfar: f64,
+    }
+}
+
+// the active camera, following a SceneCamera entity
+#[derive(Clone, Debug)]
+pub struct SceneCameraView {
+    pub entity: EntityId,
+    pub camera: SceneCamera,
+    view: Matrix4<f64>,
+    projection: Matrix4<f64>,
+    view_projection_inverse: Matrix4<f64>,
+    view_projection_direction_inverse: Matrix4<f64>,
+    position: Vector3<f64>,
+}
+
+impl SceneCamera {
+    pub fn projection(&self, width: u32, height: u32) -> Matrix4<f64> {
+        match self {
+            Self::Perspective { yfov, aspect_ratio, znear, zfar } => {
+                let aspect_ratio = aspect_ratio.unwrap_or(width.max(1) as f64 / height.max(1) as f64);
+
+                match zfar {
+                    Some(zfar) => Matrix4::new_perspective(aspect_ratio, *yfov, *znear, *zfar),
+                    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#infinite-perspective-projection
+                    None => {
+                        let f = 1.0 / (yfov * 0.5).tan();
+                        Matrix4::new(
+                            f / aspect_ratio, 0.0, 0.0, 0.0,
+                            0.0, f, 0.0, 0.0,
+                            0.0, 0.0, -1.0, -2.0 * znear,
+                            0.0, 0.0, -1.0, 0.0,
+                        )
+                    }
+                }
+            },
+            Self::Orthographic { xmag, ymag, znear, zfar } => {
+                Matrix4::new_orthographic(-xmag, *xmag, -ymag, *ymag, *znear, *zfar)
+            }
+        }
+    }
+}
+
+impl SceneCameraView {
+    pub fn new(entity: EntityId, camera: SceneCamera, width: u32, height: u32) -> Self {
+        let mut view = Self {
+            entity,
+            projection: camera.projection(width, height),
+            camera,
+            view: Matrix4::identity(),
+            view_projection_inverse: Matrix4::identity(),
+            view_projection_direction_inverse: Matrix4::identity(),
+            position: Vector3::zeros(),
+        };
+
+        view.update_matrices();
+        view
+    }
+
+    // scale is ignored, only the position and orientation matter
+    pub fn update_world_transform(&mut self, world_transform: &Matrix4<f64>) {
+        let axis = |index: usize| -> Vector4<f64> {
+            let column = world_transform.column(index).xyz().normalize();
+            Vector4::new(column.x, column.y, column.z, 0.0)
+        };
+
+        self.position = world_transform.column(3).xyz();
+
+        let camera_transform = Matrix4::from_columns(&[
+            axis(0),
+            axis(1),
+            axis(2),
+            Vector4::new(self.position.x, self.position.y, self.position.z, 1.0),
+        ]);
+
+        self.view = camera_transform.try_inverse().unwrap_or_else(Matrix4::identity);
+        self.update_matrices();
+    }
+
+    fn update_matrices(&mut self) {
+        self.view_projection_inverse = (self.projection * self.view).try_inverse().unwrap_or_else(Matrix4::identity);
+
+        // for skybox...
+        let mut view_direction_only = self.view.clone();
+        view_direction_only.m14 = 0.0;
+        view_direction_only.m24 = 0.0;
+        view_direction_only.m34 = 0.0;
+
+        self.view_projection_direction_inverse = (self.projection * view_direction_only).try_inverse().unwrap_or_else(Matrix4::identity);
+    }
+}
+
+impl CameraBase for SceneCameraView {
+    fn position(&self) -> Vector3<f64> {
+        self.position
+    }
+
+    fn view(&self) -> &Matrix4<f64> {
+        &self.view
+    }
+    fn projection(&self) -> &Matrix4<f64> {
+        &self.projection
+    }
+
+    fn view_projection_inverse(&self) -> &Matrix4<f64> {
+        &self.view_projection_inverse
+    }
+
+    fn view_projection_direction_inverse(&self) -> &Matrix4<f64> {
+        &self.view_projection_direction_inverse
+    }
+
+    fn update_viewport(&mut self, width: u32, height: u32) {
+        self.projection = self.camera.projection(width, height);
+        self.update_matrices();
+    }
+}
+
+impl AwsmRenderer {
+    // the entity must have a SceneCamera
+    // it stays the active camera until renderer.camera.active is changed again
+    pub fn set_scene_camera_active(&mut self, world: &World, entity: EntityId) -> Result<()> {
+        let (scene_cameras, world_transforms) = world.borrow::<(View<SceneCamera>, View<WorldTransform>)>()?;
+
+        let camera = scene_cameras.get(entity).map_err(|_| anyhow!("entity doesn't have a SceneCamera"))?.clone();
+        let (_, _, width, height) = self.gl.get_viewport();
+
+        let mut view = SceneCameraView::new(entity, camera, width, height);
+
+        if let Ok(world_transform) = world_transforms.get(entity) {
+            let world_transform:&Mat4 = &world_transform;
+            view.update_world_transform(&world_transform.cast());
+        }
+
+        self.camera.active = Some(CameraKind::Scene(view));
+
+        Ok(())
+    }
+
+    // follows the active scene camera's entity, if there is one
+    // must be before anything reads the camera for this frame
+    pub(crate) fn update_scene_camera(&mut self, world_transforms: &View<WorldTransform>) -> Result<()> {
+        if let Some(CameraKind::Scene(view)) = self.camera.active.as_mut() {
+            // e.g. the entity was deleted, just leave it where it was
+            if let Ok(world_transform) = world_transforms.get(view.entity) {
+                let world_transform:&Mat4 = &world_transform;
+                view.update_world_transform(&world_transform.cast());
+            }
+        }
+
+        Ok(())
+    }
+}
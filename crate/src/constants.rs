@@ -1,3 +1,2 @@
 pub const UBO_CAMERA:u32 = 0;
-pub const UBO_LIGHTS:u32 = 1;
 
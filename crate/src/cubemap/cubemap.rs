@@ -17,6 +17,7 @@ pub struct CubeMap {
     pub fbo: Id,
     pub img_texture_id: Id,
     pub cubemap_texture_id: Id,
+    pub face_size: u32,
 }
 
 impl CubeMap {
@@ -54,8 +55,6 @@ impl CubeMap {
         gl.gl.generate_mipmap(TextureTarget::CubeMap as u32);
         gl.gl.awsm_texture_set_min_filter(TextureTarget::CubeMap, TextureMinFilter::LinearMipMapLinear);
 
-        log::warn!("TODO for materials, show cubemap as IBL reflectance ... might not be exactly here though"); 
-
         //restore things
         gl.resize(ResizeStrategy::Viewport(viewport_before.0, viewport_before.1, viewport_before.2, viewport_before.3));
         gl.release_texture_target(TextureTarget::CubeMap);
@@ -68,6 +67,7 @@ impl CubeMap {
             fbo,
             img_texture_id,
             cubemap_texture_id,
+            face_size: face_size as u32,
         })
     }
 
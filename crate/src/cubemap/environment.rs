@@ -0,0 +1,231 @@
+// Image-based lighting, prefiltered from a cubemap
+// follows the approach in the Khronos sample viewer:
+// https://github.com/KhronosGroup/glTF-Sample-Viewer/blob/master/source/ibl_sampler.js
+use crate::prelude::*;
+use awsm_web::webgl::{WebGlTextureSource, TextureTarget, TextureOptions, PixelInternalFormat, PixelDataFormat, DataType, TextureWrapTarget, TextureWrapMode, PartialWebGlTextures, TextureMinFilter, TextureMagFilter, WebGl2Renderer, FrameBufferTarget, FrameBufferAttachment, FrameBufferTextureTarget, ResizeStrategy, BeginMode, GlToggle};
+use nalgebra_glm::Mat3;
+use web_sys::WebGl2RenderingContext;
+
+use super::cubemap::{CubeMap, empty_cubemap_texture};
+
+const LAMBERTIAN_SIZE:u32 = 64;
+const LAMBERTIAN_SAMPLE_COUNT:i32 = 2048;
+const SPECULAR_SIZE:u32 = 256;
+const GGX_SAMPLE_COUNT:i32 = 1024;
+const CHARLIE_SAMPLE_COUNT:i32 = 64;
+const LUT_SIZE:u32 = 512;
+const LUT_SAMPLE_COUNT:i32 = 512;
+const LOD_BIAS:f32 = 0.0;
+
+// must match the defines in ibl_filtering.frag
+#[derive(Clone, Copy, Debug)]
+enum Distribution {
+    Lambertian = 0,
+    Ggx = 1,
+    Charlie = 2,
+}
+
+// owned by the renderer like the skybox, not a world unique
+// mesh_program() reads it for the IBL key flag, and swapping it has to go through set_environment
+// so the meshes get recompiled
+#[derive(Clone, Debug)]
+pub struct Environment {
+    pub fbo: Id,
+    // diffuse irradiance
+    pub lambertian_texture_id: Id,
+    // prefiltered specular, roughness goes up with each mip
+    pub ggx_texture_id: Id,
+    pub charlie_texture_id: Id,
+    // brdf lookup tables
+    pub ggx_lut_texture_id: Id,
+    pub charlie_lut_texture_id: Id,
+    pub mip_count: u32,
+    pub intensity: f32,
+    pub rotation: Mat3,
+}
+
+impl Environment {
+    pub fn new(renderer: &mut AwsmRenderer, cubemap: &CubeMap) -> Result<Self> {
+        let lambertian_texture_id = empty_cubemap_texture(renderer, LAMBERTIAN_SIZE, false)?;
+        let ggx_texture_id = empty_cubemap_texture(renderer, SPECULAR_SIZE, true)?;
+        let charlie_texture_id = empty_cubemap_texture(renderer, SPECULAR_SIZE, true)?;
+        let ggx_lut_texture_id = empty_lut_texture(renderer, LUT_SIZE)?;
+        let charlie_lut_texture_id = empty_lut_texture(renderer, LUT_SIZE)?;
+        let fbo = renderer.gl.create_framebuffer()?;
+
+        let mip_count = (SPECULAR_SIZE as f32).log2().floor() as u32 + 1;
+
+        let program_id = renderer.shaders.programs.ibl_filtering;
+        let gl = &mut renderer.gl;
+        let viewport_before = gl.get_viewport();
+
+        // generating mipmaps is just a quick way to allocate the whole chain
+        // each level is then overwritten with the filtered result
+        for texture_id in [ggx_texture_id, charlie_texture_id] {
+            let texture = gl.get_texture(texture_id)?;
+            gl.gl.awsm_bind_texture(TextureTarget::CubeMap, texture);
+            gl.gl.generate_mipmap(TextureTarget::CubeMap as u32);
+        }
+
+        gl.toggle(GlToggle::Blend, false);
+        gl.toggle(GlToggle::DepthTest, false);
+        gl.activate_program(program_id)?;
+        gl.activate_texture_sampler_name(cubemap.cubemap_texture_id, "u_cubemap")?;
+        gl.upload_uniform_fval_name("u_width", cubemap.face_size as f32)?;
+        gl.upload_uniform_fval_name("u_lod_bias", LOD_BIAS)?;
+
+        gl.upload_uniform_ival_name("u_is_generating_lut", 0)?;
+        filter_cubemap(gl, fbo, lambertian_texture_id, LAMBERTIAN_SIZE, 0, Distribution::Lambertian, 0.0, LAMBERTIAN_SAMPLE_COUNT)?;
+
+        for mip in 0..mip_count {
+            let roughness = mip as f32 / (mip_count - 1) as f32;
+            let size = SPECULAR_SIZE >> mip;
+            filter_cubemap(gl, fbo, ggx_texture_id, size, mip, Distribution::Ggx, roughness, GGX_SAMPLE_COUNT)?;
+            filter_cubemap(gl, fbo, charlie_texture_id, size, mip, Distribution::Charlie, roughness, CHARLIE_SAMPLE_COUNT)?;
+        }
+
+        gl.upload_uniform_ival_name("u_is_generating_lut", 1)?;
+        render_lut(gl, fbo, ggx_lut_texture_id, Distribution::Ggx)?;
+        render_lut(gl, fbo, charlie_lut_texture_id, Distribution::Charlie)?;
+
+        //restore things
+        gl.resize(ResizeStrategy::Viewport(viewport_before.0, viewport_before.1, viewport_before.2, viewport_before.3));
+        gl.release_texture_target(TextureTarget::CubeMap);
+        gl.release_texture_target(TextureTarget::Texture2d);
+        gl.release_framebuffer(FrameBufferTarget::FrameBuffer);
+
+        Ok(Self {
+            fbo,
+            lambertian_texture_id,
+            ggx_texture_id,
+            charlie_texture_id,
+            ggx_lut_texture_id,
+            charlie_lut_texture_id,
+            mip_count,
+            intensity: 1.0,
+            rotation: Mat3::identity(),
+        })
+    }
+
+    // expects the mesh program to already be active
+    // only needed once per program switch, see render_sys
+    pub(crate) fn upload_uniforms(&self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.upload_uniform_ival_name("u_mip_count", self.mip_count as i32)?;
+        gl.upload_uniform_fval_name("u_env_intensity", self.intensity)?;
+        gl.upload_uniform_mat_3_name("u_env_rotation", &self.rotation.as_slice())?;
+
+        gl.activate_texture_sampler_name(self.lambertian_texture_id, "u_lambertian_env_sampler")?;
+        gl.activate_texture_sampler_name(self.ggx_texture_id, "u_ggx_env_sampler")?;
+        gl.activate_texture_sampler_name(self.ggx_lut_texture_id, "u_ggx_lut")?;
+        // only used with sheen, otherwise they're compiled out and there's no location
+        let _ = gl.activate_texture_sampler_name(self.charlie_texture_id, "u_charlie_env_sampler");
+        let _ = gl.activate_texture_sampler_name(self.charlie_lut_texture_id, "u_charlie_lut");
+
+        Ok(())
+    }
+}
+
+impl AwsmRenderer {
+    // IBL is a shader key flag, so existing meshes need to be recompiled
+    // like the skybox, the caller owns the gl resources (e.g. to toggle it back on later)
+    pub fn set_environment(&mut self, world: &World, environment: Option<Environment>) -> Result<()> {
+        let changed = self.environment.is_some() != environment.is_some();
+
+        self.environment = environment;
+
+        if changed {
+            self.recompile_mesh_programs(world)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn environment(&self) -> Option<&Environment> {
+        self.environment.as_ref()
+    }
+}
+
+impl DestroyWithGl for Environment {
+    fn destroy(&mut self, mut gl:&mut WebGl2Renderer) -> Result<()> {
+        gl.delete_framebuffer(self.fbo)?;
+        gl.delete_texture(self.lambertian_texture_id)?;
+        gl.delete_texture(self.ggx_texture_id)?;
+        gl.delete_texture(self.charlie_texture_id)?;
+        gl.delete_texture(self.ggx_lut_texture_id)?;
+        gl.delete_texture(self.charlie_lut_texture_id)?;
+
+        Ok(())
+    }
+}
+
+fn filter_cubemap(gl: &mut WebGl2Renderer, fbo: Id, texture_id: Id, size: u32, mip: u32, distribution: Distribution, roughness: f32, sample_count: i32) -> Result<()> {
+    gl.upload_uniform_ival_name("u_distribution", distribution as i32)?;
+    gl.upload_uniform_fval_name("u_roughness", roughness)?;
+    gl.upload_uniform_ival_name("u_sample_count", sample_count)?;
+    gl.resize(ResizeStrategy::ViewportSize(size, size));
+
+    for i in 0..6 {
+        let target = CubeMap::target_from_index(i)?;
+        attach_color_target(gl, fbo, texture_id, target, mip)?;
+
+        gl.upload_uniform_ival_name("u_current_face", i as i32)?;
+
+        //fullscreen triangle
+        gl.draw_arrays(BeginMode::Triangles, 0, 3);
+    }
+
+    Ok(())
+}
+
+fn render_lut(gl: &mut WebGl2Renderer, fbo: Id, texture_id: Id, distribution: Distribution) -> Result<()> {
+    gl.upload_uniform_ival_name("u_distribution", distribution as i32)?;
+    gl.upload_uniform_ival_name("u_sample_count", LUT_SAMPLE_COUNT)?;
+    gl.resize(ResizeStrategy::ViewportSize(LUT_SIZE, LUT_SIZE));
+
+    attach_color_target(gl, fbo, texture_id, FrameBufferTextureTarget::Texture2d, 0)?;
+
+    //fullscreen triangle
+    gl.draw_arrays(BeginMode::Triangles, 0, 3);
+
+    Ok(())
+}
+
+// assign_framebuffer_texture_2d always targets mip 0, so go direct
+fn attach_color_target(gl: &mut WebGl2Renderer, fbo: Id, texture_id: Id, target: FrameBufferTextureTarget, mip: u32) -> Result<()> {
+    gl.bind_framebuffer(fbo, FrameBufferTarget::FrameBuffer)?;
+    let texture = gl.get_texture(texture_id)?;
+    gl.gl.framebuffer_texture_2d(
+        FrameBufferTarget::FrameBuffer as u32,
+        FrameBufferAttachment::Color0 as u32,
+        target as u32,
+        Some(texture),
+        mip as i32
+    );
+
+    Ok(())
+}
+
+fn empty_lut_texture(renderer: &mut AwsmRenderer, size: u32) -> Result<Id> {
+    let gl = &mut renderer.gl;
+    let id = gl.create_texture()?;
+
+    gl.assign_texture(
+        id,
+        TextureTarget::Texture2d,
+        &TextureOptions{
+            internal_format: PixelInternalFormat::Rgba32f,
+            data_format: PixelDataFormat::Rgba,
+            data_type: DataType::Float,
+            cube_face: None,
+        },
+        Some(|gl:&WebGl2RenderingContext| {
+            gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::S, TextureWrapMode::ClampToEdge);
+            gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::T, TextureWrapMode::ClampToEdge);
+            gl.awsm_texture_set_min_filter(TextureTarget::Texture2d, TextureMinFilter::Linear);
+            gl.awsm_texture_set_mag_filter(TextureTarget::Texture2d, TextureMagFilter::Linear);
+        }),
+        &WebGlTextureSource::EmptyBufferView(size, size, 1)
+    )?;
+
+    Ok(id)
+}
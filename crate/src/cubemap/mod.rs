@@ -1,2 +1,3 @@
 pub mod cubemap;
-pub mod skybox;
\ No newline at end of file
+pub mod skybox;
+pub mod environment;
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::{prelude::*, animation::clip::AnimationClip};
+use crate::{prelude::*, animation::clip::AnimationClip, bounds::Aabb};
 use super::loader::GltfResource;
 use awsm_web::{webgl::{
     BufferData,
@@ -11,7 +11,7 @@ use awsm_web::{webgl::{
 }, data::TypedData};
 use gltf::{buffer, accessor};
 use std::borrow::Cow;
-use nalgebra_glm::{Vec2, Vec3, Quat};
+use nalgebra_glm::{Vec2, Vec3, Quat, Mat4};
 
 impl AwsmRenderer {
     // see https://github.com/KhronosGroup/glTF-Sample-Viewer/blob/78e6453306923f1c0df3220d45a2e0656b80c326/source/gltf/accessor.js#L30
@@ -128,6 +128,44 @@ pub fn gltf_accessor_to_vec3s(res: &GltfResource, accessor: &accessor::Accessor)
     Ok(out)
 }
 
+pub fn gltf_accessor_to_mat4s(res: &GltfResource, accessor: &accessor::Accessor) -> Result<Vec<Mat4>> {
+    if accessor.dimensions() != accessor::Dimensions::Mat4 {
+        bail!("wrong accessor type for strongly-typed mat4");
+    }
+    let buffer = gltf_accessor_data(res, accessor)?;
+    let mut out = Vec::with_capacity(buffer.len() / (accessor.data_type().size() * accessor.dimensions().multiplicity()));
+
+    // gltf matrices are column-major, same as nalgebra
+    let mut stack = Mat4::identity();
+    let mut idx:usize = 0;
+
+    gltf_accessor_buffer_with_f32(accessor, &buffer, |value| {
+        stack.as_mut_slice()[idx] = value;
+        if idx == 15 {
+            idx = 0;
+            out.push(stack.clone())
+        } else {
+            idx += 1;
+        }
+    })?;
+
+    Ok(out)
+}
+
+// from the accessor's min/max, which is required for POSITION (and morph target POSITION)
+pub fn gltf_accessor_to_aabb(accessor: &accessor::Accessor) -> Option<Aabb> {
+    let to_vec3 = |value: serde_json::Value| -> Option<Vec3> {
+        let values = value.as_array()?;
+        Some(Vec3::new(
+            values.get(0)?.as_f64()? as f32,
+            values.get(1)?.as_f64()? as f32,
+            values.get(2)?.as_f64()? as f32,
+        ))
+    };
+
+    Some(Aabb::new(to_vec3(accessor.min()?)?, to_vec3(accessor.max()?)?))
+}
+
 pub fn gltf_accessor_to_vec2s(res: &GltfResource, accessor: &accessor::Accessor) -> Result<Vec<Vec2>> {
     if accessor.dimensions() != accessor::Dimensions::Vec2 {
         bail!("wrong accessor type for strongly-typed vec2");
@@ -163,6 +201,21 @@ pub fn gltf_accessor_to_scalars(res: &GltfResource, accessor: &accessor::Accesso
     Ok(out)
 }
 
+// exact, unlike going through f32
+pub fn gltf_accessor_to_indices(res: &GltfResource, accessor: &accessor::Accessor) -> Result<Vec<u32>> {
+    if accessor.dimensions() != accessor::Dimensions::Scalar {
+        bail!("wrong accessor type for indices");
+    }
+    let buffer = gltf_accessor_data(res, accessor)?;
+
+    Ok(match accessor.data_type() {
+        accessor::DataType::U8 => buffer.iter().map(|value| *value as u32).collect(),
+        accessor::DataType::U16 => buffer.chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as u32).collect(),
+        accessor::DataType::U32 => buffer.chunks_exact(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect(),
+        data_type => bail!("invalid index type {:?}", data_type)
+    })
+}
+
 pub fn gltf_accessor_to_chunks(res: &GltfResource, accessor: &accessor::Accessor, chunk_size: usize) -> Result<Vec<Vec<f32>>> {
     if accessor.dimensions() != accessor::Dimensions::Scalar {
         bail!("wrong accessor type for strongly-typed scalar");
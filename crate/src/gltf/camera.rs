@@ -0,0 +1,41 @@
+/*
+ * Cameras
+ *
+ * Each node that references a camera gets a SceneCamera component on its entity
+ * which can then be made active via renderer.set_scene_camera_active()
+ */
+use crate::{prelude::*, camera::scene_camera::SceneCamera};
+use gltf::camera::Projection;
+use rustc_hash::FxHashMap;
+use shipyard::*;
+use super::loader::GltfResource;
+
+pub fn add_gltf_cameras(world: &World, res: &GltfResource, gltf_entities: &FxHashMap<usize, EntityId>) -> Result<()> {
+    let (entities, mut scene_cameras) = world.borrow::<(EntitiesViewMut, ViewMut<SceneCamera>)>()?;
+
+    for node in res.gltf.nodes() {
+        let (entity, camera) = match (gltf_entities.get(&node.index()), node.camera()) {
+            (Some(entity), Some(camera)) => (entity, camera),
+            _ => continue
+        };
+
+        let camera = match camera.projection() {
+            Projection::Perspective(perspective) => SceneCamera::Perspective {
+                yfov: perspective.yfov() as f64,
+                aspect_ratio: perspective.aspect_ratio().map(|aspect_ratio| aspect_ratio as f64),
+                znear: perspective.znear() as f64,
+                zfar: perspective.zfar().map(|zfar| zfar as f64),
+            },
+            Projection::Orthographic(orthographic) => SceneCamera::Orthographic {
+                xmag: orthographic.xmag() as f64,
+                ymag: orthographic.ymag() as f64,
+                znear: orthographic.znear() as f64,
+                zfar: orthographic.zfar() as f64,
+            },
+        };
+
+        entities.add_component(*entity, &mut scene_cameras, camera);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+/*
+ * EXT_mesh_gpu_instancing
+ *
+ * The gltf crate drops node extensions it doesn't know about, so this is read from the raw json
+ * at load time and kept on the GltfResource, keyed by node index
+ *
+ * When populating, each instance becomes its own child entity of the mesh node
+ * and the renderer batches them back into a single instanced draw (see renderer/instancing.rs)
+ */
+use crate::prelude::*;
+use gltf::scene::Transform;
+use nalgebra_glm::{Vec3, Quat};
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+use super::{
+    loader::GltfResource,
+    accessor::{gltf_accessor_to_vec3s, gltf_accessor_to_quats},
+};
+
+pub const EXTENSION_NAME:&'static str = "EXT_mesh_gpu_instancing";
+
+// accessor indices
+#[derive(Clone, Debug, Default)]
+pub struct GltfMeshGpuInstancing {
+    pub translation: Option<usize>,
+    pub rotation: Option<usize>,
+    pub scale: Option<usize>,
+}
+
+pub(super) fn parse_mesh_gpu_instancing(document: &gltf::Document, json: &[u8]) -> Result<FxHashMap<usize, GltfMeshGpuInstancing>> {
+    let mut out = FxHashMap::default();
+
+    // don't bother re-parsing the json if it's not there
+    if !document.extensions_used().any(|name| name == EXTENSION_NAME) {
+        return Ok(out);
+    }
+
+    let root:Value = serde_json::from_slice(json)?;
+
+    if let Some(nodes) = root.get("nodes").and_then(|nodes| nodes.as_array()) {
+        for (index, node) in nodes.iter().enumerate() {
+            if let Some(attributes) = node.get("extensions").and_then(|ext| ext.get(EXTENSION_NAME)).and_then(|ext| ext.get("attributes")) {
+                let get_accessor = |name:&str| attributes.get(name).and_then(|value| value.as_u64()).map(|value| value as usize);
+
+                out.insert(index, GltfMeshGpuInstancing {
+                    translation: get_accessor("TRANSLATION"),
+                    rotation: get_accessor("ROTATION"),
+                    scale: get_accessor("SCALE"),
+                });
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+impl GltfMeshGpuInstancing {
+    // one local transform per instance, applied on top of the node's own transform
+    pub fn transforms(&self, res: &GltfResource) -> Result<Vec<Transform>> {
+        let get_accessor = |index:usize| {
+            res.gltf.accessors().nth(index).ok_or_else(|| anyhow!("missing instancing accessor {}", index))
+        };
+
+        let translations = match self.translation {
+            Some(index) => Some(gltf_accessor_to_vec3s(res, &get_accessor(index)?)?),
+            None => None
+        };
+        let rotations = match self.rotation {
+            Some(index) => Some(gltf_accessor_to_quats(res, &get_accessor(index)?)?),
+            None => None
+        };
+        let scales = match self.scale {
+            Some(index) => Some(gltf_accessor_to_vec3s(res, &get_accessor(index)?)?),
+            None => None
+        };
+
+        // all attributes must have the same count
+        let len = [
+            translations.as_ref().map(|x| x.len()),
+            rotations.as_ref().map(|x| x.len()),
+            scales.as_ref().map(|x| x.len()),
+        ].into_iter().flatten().max().unwrap_or(0);
+
+        let mut out = Vec::with_capacity(len);
+
+        for index in 0..len {
+            let translation = translations.as_ref().and_then(|x| x.get(index).cloned()).unwrap_or_else(Vec3::zeros);
+            // normalized integer rotations are just a uniform scale of the float values
+            // so normalizing takes care of them too
+            let rotation = rotations.as_ref().and_then(|x| x.get(index).cloned()).map(|q| q.normalize()).unwrap_or_else(Quat::identity);
+            let scale = scales.as_ref().and_then(|x| x.get(index).cloned()).unwrap_or_else(|| Vec3::new(1.0, 1.0, 1.0));
+
+            out.push(Transform::Decomposed {
+                translation: [translation.x, translation.y, translation.z],
+                rotation: [rotation.coords.x, rotation.coords.y, rotation.coords.z, rotation.coords.w],
+                scale: [scale.x, scale.y, scale.z],
+            });
+        }
+
+        Ok(out)
+    }
+}
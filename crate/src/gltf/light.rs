@@ -0,0 +1,53 @@
+/*
+ * KHR_lights_punctual
+ *
+ * Each node that references a light gets a Light component on its entity
+ * the position and direction (-Z) both come from its WorldTransform like any other light
+ */
+use crate::{prelude::*, light::Light};
+use gltf::khr_lights_punctual::Kind;
+use nalgebra_glm::Vec3;
+use rustc_hash::FxHashMap;
+use shipyard::*;
+use super::loader::GltfResource;
+
+pub fn add_gltf_lights(world: &World, res: &GltfResource, gltf_entities: &FxHashMap<usize, EntityId>) -> Result<()> {
+    let (entities, mut lights) = world.borrow::<(EntitiesViewMut, ViewMut<Light>)>()?;
+
+    for node in res.gltf.nodes() {
+        let (entity, light) = match (gltf_entities.get(&node.index()), node.light()) {
+            (Some(entity), Some(light)) => (entity, light),
+            _ => continue
+        };
+
+        let color = Vec3::from(light.color());
+        let intensity = light.intensity();
+        // undefined is unlimited, i.e. <= 0.0, which is then cut off by intensity (see Light::effective_range)
+        let range = light.range().unwrap_or(0.0);
+
+        let light = match light.kind() {
+            Kind::Directional => Light::Directional {
+                direction: None,
+                color,
+                intensity
+            },
+            Kind::Point => Light::Point {
+                color,
+                intensity,
+                range
+            },
+            Kind::Spot { inner_cone_angle, outer_cone_angle } => Light::Spot {
+                direction: None,
+                color,
+                intensity,
+                range,
+                inner_cone_cos: inner_cone_angle.cos(),
+                outer_cone_cos: outer_cone_angle.cos(),
+            },
+        };
+
+        entities.add_component(*entity, &mut lights, light);
+    }
+
+    Ok(())
+}
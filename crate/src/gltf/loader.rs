@@ -11,12 +11,17 @@
 use awsm_web::data::ArrayBufferExt;
 use web_sys::HtmlImageElement;
 use awsm_web::loaders::{fetch::fetch_url, image::{load as load_image, load_u8 as load_image_u8}};
-use gltf::{Gltf, Document, buffer, image, Error as GltfError};
+use gltf::{Gltf, Glb, Document, buffer, image, Error as GltfError};
+use rustc_hash::FxHashMap;
 use std::future::Future;
 use futures::future::try_join_all;
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::{prelude::*, image::ImageLoader};
+use super::{
+    instancing::{GltfMeshGpuInstancing, parse_mesh_gpu_instancing},
+    material_extensions::{GltfMaterialExtensions, parse_material_extensions},
+};
 
 
 
@@ -26,7 +31,11 @@ type DataResult = Result<Vec<u8>>;
 pub struct GltfResource {
     pub gltf: Document,
     pub buffers: Vec<Vec<u8>>,
-    pub images: Vec<ImageLoader>
+    pub images: Vec<ImageLoader>,
+    // EXT_mesh_gpu_instancing, keyed by node index
+    pub mesh_gpu_instancing: FxHashMap<usize, GltfMeshGpuInstancing>,
+    // KHR_materials_clearcoat and KHR_materials_sheen, keyed by material index
+    pub material_extensions: FxHashMap<usize, GltfMaterialExtensions>,
 }
 
 pub enum GltfFileType {
@@ -50,18 +59,25 @@ pub fn load_gltf(url:&str, file_type: Option<GltfFileType>) -> impl Future<Outpu
         };
 
         async move {
-            let Gltf { document, blob } = match file_type {
+            let (Gltf { document, blob }, mesh_gpu_instancing, material_extensions) = match file_type {
                 GltfFileType::Json => { 
                     let text = fetch_url(&url).await?.text().await?;
                     let bytes:&[u8] = text.as_bytes();
-                    Gltf::from_slice(bytes)
+                    let gltf = Gltf::from_slice(bytes)?;
+                    let mesh_gpu_instancing = parse_mesh_gpu_instancing(&gltf.document, bytes)?;
+                    let material_extensions = parse_material_extensions(&gltf.document, bytes)?;
+                    (gltf, mesh_gpu_instancing, material_extensions)
                 },
                 GltfFileType::Glb => {
                     let bytes = fetch_url(&url).await?.array_buffer().await?.to_vec_u8();
-                    Gltf::from_slice(&bytes)
+                    let gltf = Gltf::from_slice(&bytes)?;
+                    let json = Glb::from_slice(&bytes)?.json;
+                    let mesh_gpu_instancing = parse_mesh_gpu_instancing(&gltf.document, &json)?;
+                    let material_extensions = parse_material_extensions(&gltf.document, &json)?;
+                    (gltf, mesh_gpu_instancing, material_extensions)
                 },
                 _ => return Err(Error::GltfLoad.into())
-            }?;
+            };
 
 
             let base_path = get_base_path(&url);
@@ -75,7 +91,7 @@ pub fn load_gltf(url:&str, file_type: Option<GltfFileType>) -> impl Future<Outpu
 
             //info!("loaded {} images", image_data.len());
 
-            Ok(GltfResource{ gltf: document, buffers, images })
+            Ok(GltfResource{ gltf: document, buffers, images, mesh_gpu_instancing, material_extensions })
         }
     };
 
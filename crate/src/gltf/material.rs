@@ -1,9 +1,10 @@
 use gltf::material::AlphaMode as GltfAlphaMode;
 
 use crate::prelude::*;
-use crate::renderer::material::{Material, PbrMaterial, TextureInfo};
+use crate::renderer::material::{Material, PbrMaterial, PbrClearcoat, PbrSheen, PbrSpecular, PbrTransmission, PbrVolume, PbrIridescence, TextureInfo, TextureTransform};
 use super::populate::GltfPopulateContext;
 use super::loader::GltfResource;
+use super::material_extensions::GltfTextureRef;
 
 impl AwsmRenderer {
     pub(super) fn gltf_set_material_texture_uniforms(&mut self, world: &World, res: &GltfResource, ctx: &mut GltfPopulateContext, material: &mut PbrMaterial, gltf_material: &gltf::Material) -> Result<()> {
@@ -23,31 +24,138 @@ impl AwsmRenderer {
         if let Some(info) = gltf_metallic_roughness.base_color_texture() {
             material.base_color_texture = Some(TextureInfo {
                     id: self.gltf_get_texture(res, ctx, &info.texture())?,
-                    uv_index: info.tex_coord()
+                    uv_index: gltf_tex_coord(&info),
+                    transform: gltf_texture_transform(&info),
             });
         }
 
         if let Some(info) = gltf_metallic_roughness.metallic_roughness_texture() {
             material.metallic_roughness_texture = Some(TextureInfo {
                     id: self.gltf_get_texture(res, ctx, &info.texture())?,
-                    uv_index: info.tex_coord()
+                    uv_index: gltf_tex_coord(&info),
+                    transform: gltf_texture_transform(&info),
             });
         }
 
+        // not supported by the gltf crate, see gltf/material_extensions.rs
+        let extensions = gltf_material.index().and_then(|index| res.material_extensions.get(&index));
+
         if let Some(info) = gltf_material.normal_texture() {
+            // the gltf crate doesn't expose KHR_texture_transform on these
+            let transform_ref = extensions.and_then(|extensions| extensions.normal_texture.as_ref());
             material.normal_texture = Some(TextureInfo {
                     id: self.gltf_get_texture(res, ctx, &info.texture())?,
-                    uv_index: info.tex_coord()
+                    uv_index: transform_ref.map(|tex| tex.tex_coord).unwrap_or_else(|| info.tex_coord()),
+                    transform: transform_ref.and_then(|tex| tex.transform.clone()),
             });
         }
 
+        if let Some(info) = gltf_material.occlusion_texture() {
+            let transform_ref = extensions.and_then(|extensions| extensions.occlusion_texture.as_ref());
+            material.occlusion_texture = Some(TextureInfo {
+                    id: self.gltf_get_texture(res, ctx, &info.texture())?,
+                    uv_index: transform_ref.map(|tex| tex.tex_coord).unwrap_or_else(|| info.tex_coord()),
+                    transform: transform_ref.and_then(|tex| tex.transform.clone()),
+            });
+            material.occlusion_strength = Some(info.strength());
+        }
+
         if let Some(info) = gltf_material.emissive_texture() {
             material.emissive_texture = Some(TextureInfo {
                     id: self.gltf_get_texture(res, ctx, &info.texture())?,
-                    uv_index: info.tex_coord()
+                    uv_index: gltf_tex_coord(&info),
+                    transform: gltf_texture_transform(&info),
+            });
+        }
+
+        if let Some(specular) = gltf_material.specular() {
+            material.specular = Some(PbrSpecular {
+                factor: specular.specular_factor(),
+                texture: match specular.specular_texture() {
+                    Some(info) => Some(TextureInfo {
+                        id: self.gltf_get_texture(res, ctx, &info.texture())?,
+                        uv_index: gltf_tex_coord(&info),
+                        transform: gltf_texture_transform(&info),
+                    }),
+                    None => None
+                },
+                color_factor: specular.specular_color_factor().into(),
+                color_texture: match specular.specular_color_texture() {
+                    Some(info) => Some(TextureInfo {
+                        id: self.gltf_get_texture(res, ctx, &info.texture())?,
+                        uv_index: gltf_tex_coord(&info),
+                        transform: gltf_texture_transform(&info),
+                    }),
+                    None => None
+                },
+            });
+        }
+
+        if let Some(transmission) = gltf_material.transmission() {
+            material.transmission = Some(PbrTransmission {
+                factor: transmission.transmission_factor(),
+                texture: match transmission.transmission_texture() {
+                    Some(info) => Some(TextureInfo {
+                        id: self.gltf_get_texture(res, ctx, &info.texture())?,
+                        uv_index: gltf_tex_coord(&info),
+                        transform: gltf_texture_transform(&info),
+                    }),
+                    None => None
+                },
+            });
+        }
+
+        if let Some(volume) = gltf_material.volume() {
+            material.volume = Some(PbrVolume {
+                thickness_factor: volume.thickness_factor(),
+                thickness_texture: match volume.thickness_texture() {
+                    Some(info) => Some(TextureInfo {
+                        id: self.gltf_get_texture(res, ctx, &info.texture())?,
+                        uv_index: gltf_tex_coord(&info),
+                        transform: gltf_texture_transform(&info),
+                    }),
+                    None => None
+                },
+                attenuation_distance: volume.attenuation_distance(),
+                attenuation_color: volume.attenuation_color().into(),
             });
         }
 
+        material.ior = gltf_material.ior();
+
+        if let Some(extensions) = extensions {
+            if let Some(clearcoat) = &extensions.clearcoat {
+                material.clearcoat = Some(PbrClearcoat {
+                    factor: clearcoat.factor,
+                    texture: self.gltf_texture_ref_info(res, ctx, clearcoat.texture.as_ref())?,
+                    roughness_factor: clearcoat.roughness_factor,
+                    roughness_texture: self.gltf_texture_ref_info(res, ctx, clearcoat.roughness_texture.as_ref())?,
+                    normal_texture: self.gltf_texture_ref_info(res, ctx, clearcoat.normal_texture.as_ref())?,
+                    normal_texture_scale: clearcoat.normal_texture.as_ref().and_then(|tex| tex.scale),
+                });
+            }
+
+            if let Some(sheen) = &extensions.sheen {
+                material.sheen = Some(PbrSheen {
+                    color_factor: sheen.color_factor.into(),
+                    color_texture: self.gltf_texture_ref_info(res, ctx, sheen.color_texture.as_ref())?,
+                    roughness_factor: sheen.roughness_factor,
+                    roughness_texture: self.gltf_texture_ref_info(res, ctx, sheen.roughness_texture.as_ref())?,
+                });
+            }
+
+            if let Some(iridescence) = &extensions.iridescence {
+                material.iridescence = Some(PbrIridescence {
+                    factor: iridescence.factor,
+                    texture: self.gltf_texture_ref_info(res, ctx, iridescence.texture.as_ref())?,
+                    ior: iridescence.ior,
+                    thickness_minimum: iridescence.thickness_minimum,
+                    thickness_maximum: iridescence.thickness_maximum,
+                    thickness_texture: self.gltf_texture_ref_info(res, ctx, iridescence.thickness_texture.as_ref())?,
+                });
+            }
+        }
+
         if gltf_material.double_sided() {
             material.double_sided = true;
         }
@@ -55,4 +163,34 @@ impl AwsmRenderer {
 
         Ok(())
     }
+
+    fn gltf_texture_ref_info(&mut self, res: &GltfResource, ctx: &mut GltfPopulateContext, tex: Option<&GltfTextureRef>) -> Result<Option<TextureInfo>> {
+        let tex = match tex {
+            Some(tex) => tex,
+            None => return Ok(None)
+        };
+
+        let texture = res.gltf.textures().nth(tex.index).ok_or_else(|| anyhow!("no texture at index {}", tex.index))?;
+
+        Ok(Some(TextureInfo {
+            id: self.gltf_get_texture(res, ctx, &texture)?,
+            uv_index: tex.tex_coord,
+            transform: tex.transform.clone(),
+        }))
+    }
+}
+
+// KHR_texture_transform can override the texCoord
+fn gltf_tex_coord(info: &gltf::texture::Info) -> u32 {
+    info.texture_transform()
+        .and_then(|transform| transform.tex_coord())
+        .unwrap_or_else(|| info.tex_coord())
+}
+
+fn gltf_texture_transform(info: &gltf::texture::Info) -> Option<TextureTransform> {
+    info.texture_transform().map(|transform| TextureTransform {
+        offset: transform.offset().into(),
+        rotation: transform.rotation(),
+        scale: transform.scale().into(),
+    })
 }
@@ -0,0 +1,168 @@
+/*
+ * KHR_materials_clearcoat, KHR_materials_sheen, KHR_materials_iridescence
+ *
+ * The gltf crate drops material extensions it doesn't know about, so these are read from the raw json
+ * at load time and kept on the GltfResource, keyed by material index
+ *
+ * Same for KHR_texture_transform on the normal and occlusion textures, which the crate doesn't expose
+ *
+ * Textures are kept as indices, they're created when populating (see gltf/material.rs)
+ */
+use crate::{prelude::*, renderer::material::TextureTransform};
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+
+pub const CLEARCOAT_EXTENSION_NAME:&'static str = "KHR_materials_clearcoat";
+pub const SHEEN_EXTENSION_NAME:&'static str = "KHR_materials_sheen";
+pub const IRIDESCENCE_EXTENSION_NAME:&'static str = "KHR_materials_iridescence";
+const TEXTURE_TRANSFORM_EXTENSION_NAME:&'static str = "KHR_texture_transform";
+
+#[derive(Clone, Debug, Default)]
+pub struct GltfMaterialExtensions {
+    pub clearcoat: Option<GltfClearcoat>,
+    pub sheen: Option<GltfSheen>,
+    pub iridescence: Option<GltfIridescence>,
+    // only set if they have a KHR_texture_transform
+    pub normal_texture: Option<GltfTextureRef>,
+    pub occlusion_texture: Option<GltfTextureRef>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GltfClearcoat {
+    pub factor: f32,
+    pub texture: Option<GltfTextureRef>,
+    pub roughness_factor: f32,
+    pub roughness_texture: Option<GltfTextureRef>,
+    pub normal_texture: Option<GltfTextureRef>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GltfSheen {
+    pub color_factor: [f32;3],
+    pub color_texture: Option<GltfTextureRef>,
+    pub roughness_factor: f32,
+    pub roughness_texture: Option<GltfTextureRef>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GltfIridescence {
+    pub factor: f32,
+    pub texture: Option<GltfTextureRef>,
+    pub ior: f32,
+    pub thickness_minimum: f32,
+    pub thickness_maximum: f32,
+    pub thickness_texture: Option<GltfTextureRef>,
+}
+
+// a textureInfo
+#[derive(Clone, Debug)]
+pub struct GltfTextureRef {
+    pub index: usize,
+    // with the KHR_texture_transform override already applied
+    pub tex_coord: u32,
+    // only for normal textures
+    pub scale: Option<f32>,
+    pub transform: Option<TextureTransform>,
+}
+
+pub(super) fn parse_material_extensions(document: &gltf::Document, json: &[u8]) -> Result<FxHashMap<usize, GltfMaterialExtensions>> {
+    let mut out = FxHashMap::default();
+
+    // don't bother re-parsing the json if they're not there
+    if !document.extensions_used().any(|name| name == CLEARCOAT_EXTENSION_NAME || name == SHEEN_EXTENSION_NAME || name == IRIDESCENCE_EXTENSION_NAME || name == TEXTURE_TRANSFORM_EXTENSION_NAME) {
+        return Ok(out);
+    }
+
+    let root:Value = serde_json::from_slice(json)?;
+
+    if let Some(materials) = root.get("materials").and_then(|materials| materials.as_array()) {
+        for (index, material) in materials.iter().enumerate() {
+            let normal_texture = get_texture_ref(material, "normalTexture").filter(|tex| tex.transform.is_some());
+            let occlusion_texture = get_texture_ref(material, "occlusionTexture").filter(|tex| tex.transform.is_some());
+
+            let extensions = match material.get("extensions") {
+                Some(extensions) => extensions,
+                None => {
+                    if normal_texture.is_some() || occlusion_texture.is_some() {
+                        out.insert(index, GltfMaterialExtensions { normal_texture, occlusion_texture, ..Default::default() });
+                    }
+                    continue;
+                }
+            };
+
+            let clearcoat = extensions.get(CLEARCOAT_EXTENSION_NAME).map(|ext| GltfClearcoat {
+                factor: get_f32(ext, "clearcoatFactor").unwrap_or(0.0),
+                texture: get_texture_ref(ext, "clearcoatTexture"),
+                roughness_factor: get_f32(ext, "clearcoatRoughnessFactor").unwrap_or(0.0),
+                roughness_texture: get_texture_ref(ext, "clearcoatRoughnessTexture"),
+                normal_texture: get_texture_ref(ext, "clearcoatNormalTexture"),
+            });
+
+            let sheen = extensions.get(SHEEN_EXTENSION_NAME).map(|ext| GltfSheen {
+                color_factor: get_f32_array(ext, "sheenColorFactor").unwrap_or([0.0; 3]),
+                color_texture: get_texture_ref(ext, "sheenColorTexture"),
+                roughness_factor: get_f32(ext, "sheenRoughnessFactor").unwrap_or(0.0),
+                roughness_texture: get_texture_ref(ext, "sheenRoughnessTexture"),
+            });
+
+            let iridescence = extensions.get(IRIDESCENCE_EXTENSION_NAME).map(|ext| GltfIridescence {
+                factor: get_f32(ext, "iridescenceFactor").unwrap_or(0.0),
+                texture: get_texture_ref(ext, "iridescenceTexture"),
+                ior: get_f32(ext, "iridescenceIor").unwrap_or(1.3),
+                thickness_minimum: get_f32(ext, "iridescenceThicknessMinimum").unwrap_or(100.0),
+                thickness_maximum: get_f32(ext, "iridescenceThicknessMaximum").unwrap_or(400.0),
+                thickness_texture: get_texture_ref(ext, "iridescenceThicknessTexture"),
+            });
+
+            if clearcoat.is_some() || sheen.is_some() || iridescence.is_some() || normal_texture.is_some() || occlusion_texture.is_some() {
+                out.insert(index, GltfMaterialExtensions { clearcoat, sheen, iridescence, normal_texture, occlusion_texture });
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn get_f32(value: &Value, name: &str) -> Option<f32> {
+    value.get(name).and_then(|value| value.as_f64()).map(|value| value as f32)
+}
+
+fn get_f32_array<const N: usize>(value: &Value, name: &str) -> Option<[f32;N]> {
+    let values = value.get(name)?.as_array()?;
+    if values.len() != N {
+        return None;
+    }
+
+    let mut out = [0.0; N];
+    for (index, value) in values.iter().enumerate() {
+        out[index] = value.as_f64()? as f32;
+    }
+
+    Some(out)
+}
+
+fn get_texture_ref(value: &Value, name: &str) -> Option<GltfTextureRef> {
+    let info = value.get(name)?;
+    let index = info.get("index")?.as_u64()? as usize;
+    let tex_coord = info.get("texCoord").and_then(|value| value.as_u64()).unwrap_or(0) as u32;
+
+    let transform = info.get("extensions").and_then(|ext| ext.get(TEXTURE_TRANSFORM_EXTENSION_NAME));
+
+    Some(GltfTextureRef {
+        index,
+        tex_coord: transform
+            .and_then(|transform| transform.get("texCoord"))
+            .and_then(|value| value.as_u64())
+            .map(|value| value as u32)
+            .unwrap_or(tex_coord),
+        scale: get_f32(info, "scale"),
+        transform: transform.map(|transform| {
+            let default = TextureTransform::default();
+            TextureTransform {
+                offset: get_f32_array::<2>(transform, "offset").map(|offset| offset.into()).unwrap_or(default.offset),
+                rotation: get_f32(transform, "rotation").unwrap_or(default.rotation),
+                scale: get_f32_array::<2>(transform, "scale").map(|scale| scale.into()).unwrap_or(default.scale),
+            }
+        }),
+    })
+}
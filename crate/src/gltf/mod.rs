@@ -6,4 +6,8 @@ pub mod animation;
 pub mod primitive;
 pub mod skin;
 pub mod material;
+pub mod material_extensions;
 pub mod texture;
+pub mod instancing;
+pub mod light;
+pub mod camera;
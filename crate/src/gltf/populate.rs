@@ -1,7 +1,7 @@
 use crate::{
     prelude::*, 
     gltf::component::GltfPrimitive, 
-    animation::clip::AnimationClip, light::Light,
+    animation::clip::AnimationClip,
 };
 use anyhow::bail;
 use gltf::{Semantic, mesh::Mode, scene::Transform, animation::{Sampler, Property}, Node};
@@ -18,6 +18,8 @@ use super::{
         convert_data_type, gltf_accessor_to_chunks,
     },
     animation::add_gltf_animations,
+    light::add_gltf_lights,
+    camera::add_gltf_cameras,
     skin::GltfSkinInfo
 };
 use awsm_web::webgl::{
@@ -86,21 +88,31 @@ impl AwsmRenderer {
             }
         }
 
-        // TODO - iterate over gltf lights and turn into light components
-
-        let existing_light_len = match world.borrow::<View<Light>>() {
-            Err(_) => 0,
-            Ok(light) => light.iter().count() as u32
-        };
-        self.update_max_lights(world, self.lights.max_lights.max(existing_light_len))?;
+        // KHR_lights_punctual
+        add_gltf_lights(world, res, &gltf_entities)?;
 
+        // cameras aren't made active automatically, see set_scene_camera_active()
+        add_gltf_cameras(world, res, &gltf_entities)?;
 
         // add mesh components, creating primitive children as-needed
         for (node_index, entity) in gltf_entities.iter() {
             if let Some(gltf_node) = doc.nodes().nth(*node_index) {
                 if let Some(mesh) = gltf_node.mesh() {
-                    for primitive in mesh.primitives() {
-                        self.add_gltf_primitive(world, res, &mut ctx, &gltf_node, *entity, &mesh, &primitive)?;
+                    match res.mesh_gpu_instancing.get(node_index) {
+                        None => {
+                            for primitive in mesh.primitives() {
+                                self.add_gltf_primitive(world, res, &mut ctx, &gltf_node, *entity, &mesh, &primitive)?;
+                            }
+                        },
+                        // each instance gets its own child, sharing the same primitives
+                        Some(instancing) => {
+                            for transform in instancing.transforms(res)? {
+                                let instance_entity = add_child(world, Some(*entity), Some(transform), |_| Ok(()))?;
+                                for primitive in mesh.primitives() {
+                                    self.add_gltf_primitive(world, res, &mut ctx, &gltf_node, instance_entity, &mesh, &primitive)?;
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -3,6 +3,8 @@ use crate::{
     gltf::component::GltfPrimitive, 
     animation::clip::AnimationClip,
     renderer::shaders::{ShaderKey, SkinTarget, VertexColor, VertexColorSize},
+    renderer::instancing::MAX_VERTEX_ATTRIBUTES,
+    bounds::{Aabb, BoundingSphere},
 };
 use anyhow::bail;
 use gltf::{Semantic, mesh::Mode, scene::Transform, animation::{Sampler, Property}};
@@ -18,6 +20,8 @@ use super::{
         gltf_accessor_data,
         gltf_accessor_buffer_with_f32,
         convert_data_type, gltf_accessor_to_chunks,
+        gltf_accessor_to_aabb,
+        gltf_accessor_to_indices,
     },
     animation::add_gltf_animations,
     skin::GltfSkinInfo,
@@ -66,17 +70,21 @@ impl AwsmRenderer {
             mesh: Mesh,
             material: Material,
             mesh_morph_weights: Option<MeshMorphWeights>,
+            aabb: Option<Aabb>,
+            geometry: Option<MeshGeometry>,
         };
 
         let data_to_add = if let Some(prim_entity) = prim_entity {
             log::info!("primitive already exists: (mesh: {}, prim: {})", mesh.index(), primitive.index());
-            let (entities, mut meshes, mut mesh_morph_weights, mut materials) 
-                    = world.borrow::<(EntitiesViewMut, ViewMut<Mesh>, ViewMut<MeshMorphWeights>, ViewMut<Material>)>()?;
+            let (entities, mut meshes, mut mesh_morph_weights, mut materials, aabbs, geometries) 
+                    = world.borrow::<(EntitiesViewMut, ViewMut<Mesh>, ViewMut<MeshMorphWeights>, ViewMut<Material>, View<Aabb>, View<MeshGeometry>)>()?;
 
             DataToAdd {
                 mesh: meshes.get(prim_entity)?.clone(),
                 mesh_morph_weights: mesh_morph_weights.get(prim_entity).ok().cloned(),
                 material: materials.get(prim_entity)?.clone(),
+                aabb: aabbs.get(prim_entity).ok().cloned(),
+                geometry: geometries.get(prim_entity).ok().cloned(),
             }
         } else {
 
@@ -94,6 +102,7 @@ impl AwsmRenderer {
             let mut skin_weight_map:FxHashMap<u32, u32> = FxHashMap::default();
             let mut texture_coords_map:FxHashMap<u32, u32> = FxHashMap::default();
             let mut color_map:FxHashMap<u32, VertexColor> = FxHashMap::default();
+            let mut aabb:Option<Aabb> = None;
 
             for (semantic, accessor) in primitive.attributes() {
                 match semantic {
@@ -104,6 +113,7 @@ impl AwsmRenderer {
                         buffer_ids.push(data.buffer_id.clone());
                         vao_data.push(data);
                         shader_key.position_attribute_loc = Some(dynamic_loc);
+                        aabb = gltf_accessor_to_aabb(&accessor);
                     },
                     Semantic::Normals => {
                         //log::info!("NORMALS");
@@ -163,7 +173,7 @@ impl AwsmRenderer {
             }
 
             if let Some(skin_info) = ctx.get_skin_info(mesh_node)? {
-                shader_key.n_skin_joints = skin_info.joint_entities.len() as u8
+                shader_key.set_skin_joints(skin_info.joint_entities.len());
             }
 
             for (key, joint_loc) in skin_joint_map {
@@ -176,6 +186,19 @@ impl AwsmRenderer {
             for (weight_index, morph_target) in primitive.morph_targets().enumerate() {
 
                 if let Some(accessor) = morph_target.positions() {
+                    // morph targets are displacements, so grow the bounds as if each were fully applied
+                    // (weights are in [0,1] for gltf animations, so summing the extents is conservative)
+                    match gltf_accessor_to_aabb(&accessor) {
+                        Some(target_aabb) => {
+                            if let Some(aabb) = aabb.as_mut() {
+                                aabb.min += target_aabb.min.inf(&Vec3::zeros());
+                                aabb.max += target_aabb.max.sup(&Vec3::zeros());
+                            }
+                        },
+                        // without the target's extents there's nothing safe to cull against
+                        None => aabb = None
+                    }
+
                     let data = self.upload_accessor_to_vao_data(res, &accessor, NameOrLoc::Loc(dynamic_loc), Some(BufferTarget::ArrayBuffer))?;
                     buffer_ids.push(data.buffer_id);
                     vao_data.push(data);
@@ -252,7 +275,7 @@ impl AwsmRenderer {
             material.set_shader_key(&mut shader_key);
 
             // just to pre-compile
-            let program_id = self.mesh_program(shader_key.clone(), self.lights.max_lights)?;
+            let program_id = self.mesh_program(shader_key.clone())?;
 
             self.gl.assign_vertex_array(
                 vao_id,
@@ -261,8 +284,20 @@ impl AwsmRenderer {
             )?;
 
           
+            // skinned and morphed meshes need per-entity uniforms, so they're never instanced
+            let instance_attribute_loc = if shader_key.skin_targets.is_empty() 
+                && shader_key.morph_targets.is_empty() 
+                && dynamic_loc + 4 <= MAX_VERTEX_ATTRIBUTES {
+                    Some(dynamic_loc)
+                } else {
+                    None
+                };
+          
             let mesh = Mesh{
                 vao_id,
+                instance_attribute_loc,
+                // set once it has an entity
+                primitive: None,
                 buffer_ids,
                 shader_key,
                 program_id,
@@ -272,6 +307,12 @@ impl AwsmRenderer {
                         skin_info.joint_entities.clone()
                     }
                 },
+                skin_inverse_bind_mats: match ctx.get_skin_info(mesh_node)? {
+                    None => Vec::new(),
+                    Some(skin_info) => {
+                        skin_info.inverse_bind_mats.clone()
+                    }
+                },
                 draw_strategy: match primitive.indices() {
                     Some(indices) => {
                         DrawStrategy::Elements { 
@@ -294,10 +335,18 @@ impl AwsmRenderer {
                 }
             };
 
+            let geometry = if self.config.retain_mesh_geometry {
+                gltf_primitive_geometry(res, primitive)?
+            } else {
+                None
+            };
+
             DataToAdd {
                 mesh,
                 material: Material::Pbr(material),
                 mesh_morph_weights,
+                aabb,
+                geometry,
             }
 
 
@@ -307,6 +356,9 @@ impl AwsmRenderer {
         // to the parent mesh node
         super::populate::add_child(world, Some(mesh_entity), None, {
             move |entity| {
+                let mut data_to_add = data_to_add;
+                data_to_add.mesh.primitive = Some(prim_entity.unwrap_or(entity));
+
                 let (entities, mut gltf_prims, mut meshes, mut mesh_morph_weights, mut materials) 
                         = world.borrow::<(EntitiesViewMut, ViewMut<GltfPrimitive>, ViewMut<Mesh>, ViewMut<MeshMorphWeights>, ViewMut<Material> )>()?;
 
@@ -324,6 +376,16 @@ impl AwsmRenderer {
                     entities.add_component(entity, &mut mesh_morph_weights, m);
                 }
 
+                if let Some(geometry) = data_to_add.geometry {
+                    let mut geometries = world.borrow::<ViewMut<MeshGeometry>>()?;
+                    entities.add_component(entity, &mut geometries, geometry);
+                }
+
+                if let Some(aabb) = data_to_add.aabb {
+                    let (mut aabbs, mut bounding_spheres) = world.borrow::<(ViewMut<Aabb>, ViewMut<BoundingSphere>)>()?;
+                    entities.add_component(entity, (&mut aabbs, &mut bounding_spheres), (aabb, BoundingSphere::from(&aabb)));
+                }
+
                 Ok(()) 
             }
         })?;
@@ -332,6 +394,68 @@ impl AwsmRenderer {
     }
 }
 
+fn gltf_primitive_geometry(res: &GltfResource, primitive: &gltf::mesh::Primitive) -> Result<Option<MeshGeometry>> {
+    let positions = match primitive.get(&Semantic::Positions) {
+        Some(accessor) => gltf_accessor_to_vec3s(res, &accessor)?,
+        None => return Ok(None)
+    };
+
+    let normals = match primitive.get(&Semantic::Normals) {
+        Some(accessor) => Some(gltf_accessor_to_vec3s(res, &accessor)?),
+        None => None
+    };
+
+    let uvs = match primitive.get(&Semantic::TexCoords(0)) {
+        Some(accessor) => {
+            let mut uvs = gltf_accessor_to_vec2s(res, &accessor)?;
+            if accessor.normalized() {
+                let max = match accessor.data_type() {
+                    gltf::accessor::DataType::U8 => u8::MAX as f32,
+                    gltf::accessor::DataType::U16 => u16::MAX as f32,
+                    _ => 1.0
+                };
+                for uv in uvs.iter_mut() {
+                    *uv /= max;
+                }
+            }
+            Some(uvs)
+        },
+        None => None
+    };
+
+    let indices:Vec<u32> = match primitive.indices() {
+        Some(accessor) => gltf_accessor_to_indices(res, &accessor)?,
+        None => (0..positions.len() as u32).collect()
+    };
+
+    let triangles = match primitive.mode() {
+        Mode::Triangles => {
+            indices.chunks_exact(3).map(|chunk| [chunk[0], chunk[1], chunk[2]]).collect()
+        },
+        // every other triangle flips, to keep the winding consistent
+        Mode::TriangleStrip => {
+            (2..indices.len()).map(|i| {
+                if i % 2 == 0 {
+                    [indices[i - 2], indices[i - 1], indices[i]]
+                } else {
+                    [indices[i - 1], indices[i - 2], indices[i]]
+                }
+            }).collect()
+        },
+        Mode::TriangleFan => {
+            (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect()
+        },
+        _ => Vec::new()
+    };
+
+    Ok(Some(MeshGeometry::new(MeshGeometryData {
+        positions,
+        normals,
+        uvs,
+        triangles,
+    })))
+}
+
 fn convert_mode(mode: Mode) -> BeginMode {
     match mode {
         Mode::Points => BeginMode::Points,
@@ -13,6 +13,7 @@ use super::{
         gltf_accessor_to_scalars,
         gltf_accessor_to_vec3s,
         gltf_accessor_to_quats, 
+        gltf_accessor_to_mat4s,
         gltf_accessor_data,
         gltf_accessor_buffer_with_f32,
         convert_data_type, gltf_accessor_to_chunks,
@@ -35,7 +36,8 @@ use nalgebra_glm::{Vec3, Quat, Mat4};
 use shipyard_scenegraph::prelude::*;
 
 pub struct GltfSkinInfo {
-    pub joint_entities: Vec<EntityId>
+    pub joint_entities: Vec<EntityId>,
+    pub inverse_bind_mats: Vec<Mat4>,
 }
 
 impl AwsmRenderer {
@@ -50,25 +52,29 @@ impl AwsmRenderer {
         let (entities, mut mesh_skin_joints) 
             = world.borrow::<(EntitiesViewMut, ViewMut<MeshSkinJoint>)>()?;
 
-        let get_inverse_bind_mat = || -> Mat4 {
-            log::warn!("TODO - get real inverse bind mat");
-            Mat4::identity()
+        // if not supplied, they're all identity matrices (i.e. already in bind pose)
+        let mut inverse_bind_mats = match skin.inverse_bind_matrices() {
+            Some(accessor) => gltf_accessor_to_mat4s(res, &accessor)?,
+            None => Vec::new()
         };
+        inverse_bind_mats.resize(skin.joints().len(), Mat4::identity());
 
         let mut joint_entities:Vec<EntityId> = Vec::with_capacity(skin.joints().len());
 
+        // the joint itself only tracks its transform, it may be shared with other skins
         for joint_node in skin.joints() {
-            let entity = *entity_lookup.get(&joint_node.index()).unwrap_ext();
-            entities.add_component(entity, &mut mesh_skin_joints, MeshSkinJoint {
-                inverse_bind_mat: get_inverse_bind_mat(),
-                world_transform: Mat4::identity() 
-            });
+            let entity = *entity_lookup.get(&joint_node.index()).ok_or_else(|| anyhow!("missing joint node {}", joint_node.index()))?;
+            if !mesh_skin_joints.contains(entity) {
+                entities.add_component(entity, &mut mesh_skin_joints, MeshSkinJoint {
+                    joint_mat: Mat4::identity() 
+                });
+            }
             joint_entities.push(entity);
         }
-        log::info!("{}", skin.joints().len());
 
         Ok(GltfSkinInfo {  
-            joint_entities
+            joint_entities,
+            inverse_bind_mats,
         })
     }
 }
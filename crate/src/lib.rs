@@ -11,4 +11,6 @@ pub mod animation;
 pub mod debug;
 pub mod cubemap;
 pub mod image;
-pub mod util;
\ No newline at end of file
+pub mod util;
+pub mod bounds;
+pub mod raycast;
\ No newline at end of file
@@ -1,36 +1,49 @@
 /*
- * Lighting....
+ * Lighting
  *
- * TL;DR - max_lights needs to be known ahead of compiling the shaders
- * changing that is a pretty expensive operation
+ * Lights are just components, add and remove them whenever
+ * there's no limit baked into the shaders, so it never needs a recompile
  *
- * however, once max_lights is set, lights can be turned on/off up to that limit
- * without incurring a new shader compilation cost. 
- * The cost of unused lights is merely gpu calculation (whether branching or unnecessary math)
- *
- * so at the end of the day, practically speaking:
- *
- * 1. call renderer.update_max_lights *rarely* (this is handled automatically in gltf populate)
- * 2. add/remove Light components up to that limit whenever 
- *    2a. this becomes active_len in internally
- *    2b. active_len ultimately becomes the enabled flag for the shader (see fragment.rs)
- *
- * Things will be tuned better when they are equivilent, since it will avoid unncessary computation
+ * Every frame, all the lights are packed into a float texture (LIGHTS_PER_ROW to a row)
+ * and assigned to clusters of the view frustum (see light/cluster.rs)
+ * so each fragment only evaluates the lights that can actually reach it
  */
+pub mod shadow;
+pub mod cluster;
+
 use crate::renderer::AwsmRenderer;
-use awsm_web::webgl::{Id, WebGl2Renderer, BufferUsage};
+use awsm_web::{
+    data::TypedData,
+    webgl::{
+        Id,
+        WebGl2Renderer,
+        TextureTarget,
+        TextureOptions,
+        PixelInternalFormat,
+        PixelDataFormat,
+        DataType,
+        TextureWrapTarget,
+        TextureWrapMode,
+        TextureMinFilter,
+        TextureMagFilter,
+        WebGlTextureSource,
+        PartialWebGlTextures,
+        UniformType,
+    }
+};
+use js_sys::Float32Array;
 use nalgebra::Isometry3;
 use nalgebra_glm::{Vec3, Mat4};
+use web_sys::WebGl2RenderingContext;
 use crate::prelude::*;
-use crate::constants::UBO_LIGHTS;
 use shipyard_scenegraph::math::nalgebra_common::*;
-
-const DEFAULT_MAX_LIGHTS:u32 = 0;
+use cluster::{LightClusters, LightBounds};
 
 #[derive(Component, Clone, Debug)]
 pub enum Light {
     Directional {
-        direction: Vec3,
+        // None is the WorldTransform's -Z, see world_direction()
+        direction: Option<Vec3>,
         color: Vec3,
         intensity: f32,
     },
@@ -40,7 +53,8 @@ pub enum Light {
         range: f32,
     },
     Spot {
-        direction: Vec3,
+        // None is the WorldTransform's -Z, see world_direction()
+        direction: Option<Vec3>,
         color: Vec3,
         intensity: f32,
         range: f32,
@@ -49,141 +63,221 @@ pub enum Light {
     }
 }
 
+// below this, inverse-square falloff is lost in an 8-bit channel anyway
+const LIGHT_CUTOFF:f32 = 1.0 / 256.0;
+
+impl Light {
+    // how far the light reaches, None for directional lights which are everywhere
+    // an unlimited range is cut off where the falloff drops below LIGHT_CUTOFF
+    // this is also the range the shader attenuates with, so it fades out instead of stopping at a cluster edge
+    pub fn effective_range(&self) -> Option<f32> {
+        match self {
+            Light::Directional { .. } => None,
+            Light::Point { color, intensity, range } | Light::Spot { color, intensity, range, .. } => {
+                if *range > 0.0 {
+                    Some(*range)
+                } else {
+                    Some((intensity * color.max() / LIGHT_CUTOFF).max(0.0).sqrt())
+                }
+            }
+        }
+    }
+
+    // the override if there is one, otherwise forward from the world rotation (-Z, same as glTF)
+    // so animated and parented lights point where their entity does
+    // None for point lights
+    pub fn world_direction(&self, world_transform: &Mat4) -> Option<Vec3> {
+        match self {
+            Light::Point { .. } => None,
+            Light::Directional { direction, .. } | Light::Spot { direction, .. } => {
+                let direction = match direction {
+                    Some(direction) => *direction,
+                    None => world_transform.transform_vector(&Vec3::new(0.0, 0.0, -1.0)),
+                };
+                Some(direction.normalize())
+            }
+        }
+    }
+}
+
 pub struct Lights {
-    pub(crate) buffer_id: Id,
+    pub(crate) data_texture_id: Id,
     pub(crate) scratch_buffer:Vec<f32>,
-    pub(crate) max_lights: u32,
+    pub(crate) clusters: LightClusters,
 }
 
-// Sizes needed to crate the backing data for the UBO
-const SIZE_ACTIVE_LEN:usize = 4; // 1 but with padding
+// 4 rgba texels
 const SIZE_PER_LIGHT:usize = 16;
+// wrapped like the cluster indices, so it's 1024 texels wide
+// and the 2048 rows WebGl2 guarantees are far more lights than the clusters could reference
+pub(crate) const LIGHTS_PER_ROW:usize = 256;
 
 impl Lights {
     pub fn new(gl: &mut WebGl2Renderer) -> Result<Self> {
-        gl.hardcoded_ubo_locations.insert("ubo_lights".to_string(), UBO_LIGHTS);  
-
-        let buffer_id = gl.create_buffer()?;
-
         Ok(Self {
-            buffer_id,
+            data_texture_id: gl.create_texture()?,
             scratch_buffer: Vec::new(),
-            max_lights: DEFAULT_MAX_LIGHTS,
+            clusters: LightClusters::new(gl)?,
         })
     }
 
-    // See the layout in UboLight in structs.glsl
-    // and convert_ubo_light in light.glsl
+    // expects the program to already be active
+    // the deferred lighting pass only needs the light data, not the clusters
+    pub(crate) fn upload_uniforms(&self, gl: &mut WebGl2Renderer, clustered: bool) -> Result<()> {
+        gl.activate_texture_sampler_name(self.data_texture_id, "u_light_data")?;
+
+        if clustered {
+            gl.activate_texture_sampler_name(self.clusters.clusters_texture_id, "u_light_clusters")?;
+            gl.activate_texture_sampler_name(self.clusters.indices_texture_id, "u_light_indices")?;
+            gl.upload_uniform_fvec_name("u_light_cluster_params", UniformType::Vector4, &self.clusters.params)?;
+        }
+
+        Ok(())
+    }
+
+    // See get_light() in light.glsl for the layout
     pub(crate) fn write_direction(&mut self, n_light: usize, direction: &Vec3) {
-        let offset = 4 + (n_light * SIZE_PER_LIGHT);
+        let offset = n_light * SIZE_PER_LIGHT;
         let target = &mut self.scratch_buffer[offset..offset+3];
         direction.write_to_vf32(target);
     }
     pub(crate) fn write_range(&mut self, n_light: usize, range: f32) {
-        let offset = (4 + (n_light * SIZE_PER_LIGHT)) + 3;
+        let offset = (n_light * SIZE_PER_LIGHT) + 3;
         self.scratch_buffer[offset] = range;
     }
     pub(crate) fn write_color(&mut self, n_light: usize, color: &Vec3) {
-        let offset = (4 + (n_light * SIZE_PER_LIGHT)) + 4;
+        let offset = (n_light * SIZE_PER_LIGHT) + 4;
         let target = &mut self.scratch_buffer[offset..offset+3];
         color.write_to_vf32(target);
     }
     pub(crate) fn write_intensity(&mut self, n_light: usize, intensity: f32) {
-        let offset = (4 + (n_light * SIZE_PER_LIGHT)) + 7;
+        let offset = (n_light * SIZE_PER_LIGHT) + 7;
         self.scratch_buffer[offset] = intensity;
     }
     pub(crate) fn write_position(&mut self, n_light: usize, position: &Vec3) {
-        let offset = (4 + (n_light * SIZE_PER_LIGHT)) + 8;
+        let offset = (n_light * SIZE_PER_LIGHT) + 8;
         let target = &mut self.scratch_buffer[offset..offset+3];
         position.write_to_vf32(target);
     }
     pub(crate) fn write_type(&mut self, n_light: usize, light_type: f32) {
-        let offset = (4 + (n_light * SIZE_PER_LIGHT)) + 11;
+        let offset = (n_light * SIZE_PER_LIGHT) + 11;
         self.scratch_buffer[offset] = light_type;
     }
     pub(crate) fn write_cone_cos(&mut self, n_light: usize, inner: f32, outer: f32) {
-        let offset = (4 + (n_light * SIZE_PER_LIGHT)) + 12;
+        let offset = (n_light * SIZE_PER_LIGHT) + 12;
         self.scratch_buffer[offset] = inner;
         self.scratch_buffer[offset+1] = outer;
     }
 }
 
-impl AwsmRenderer {
-    pub fn update_max_lights<'a>(&mut self, world: &World, new_max_lights: u32) -> Result<()> {
-
-        let old_max_lights = self.lights.max_lights;
-        self.lights.max_lights = new_max_lights; 
-
-        if old_max_lights != self.lights.max_lights {
-            log::info!("old max lights: {}, new max lights: {}, existing lights: {}", old_max_lights, self.lights.max_lights, new_max_lights);
-            self.recompile_mesh_programs_max_lights(world, self.lights.max_lights)?;
-        }
-
-        Ok(())
+impl DestroyWithGl for Lights {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.delete_texture(self.data_texture_id)?;
+        self.clusters.destroy(gl)
     }
+}
 
-    pub fn update_lights_ubo<'a>(&mut self, transform_lights: impl Iterator<Item = (&'a WorldTransform, &'a Light)>) -> Result<()> {
-
-
-        let buffer_len = self.lights.scratch_buffer.len();
-        let max_buffer_len = SIZE_ACTIVE_LEN + (self.lights.max_lights as usize * SIZE_PER_LIGHT);
-
-        if buffer_len < max_buffer_len {
-            self.lights.scratch_buffer = std::iter::repeat(0.0).take(max_buffer_len).collect();
-        }
-
+impl AwsmRenderer {
+    // must be called before render_shadow_maps(), since the clusters need the camera's viewport
+    pub fn update_lights<'a>(&mut self, transform_lights: impl Iterator<Item = (&'a WorldTransform, &'a Light)>) -> Result<()> {
         let gl = &mut self.gl;
         let lights = &mut self.lights;
-        let mut active_len = 0usize;
+        let mut bounds:Vec<LightBounds> = Vec::new();
+
+        lights.scratch_buffer.clear();
 
         for (i, (transform, light)) in transform_lights.enumerate() {
-            active_len += 1;
-
-            let has_position = match light {
-                Light::Point { .. } => true,
-                Light::Spot { .. } => true,
-                Light::Directional { .. } => false,
-            };
-
-            if has_position {
-                let transform:&Mat4 = &transform;
-                let position:Isometry3<f32> = nalgebra::convert_unchecked(*transform);
-                lights.write_position(i, &position.translation.vector);
+            lights.scratch_buffer.resize((i + 1) * SIZE_PER_LIGHT, 0.0);
+
+            let transform:&Mat4 = &transform;
+            let position:Isometry3<f32> = nalgebra::convert_unchecked(*transform);
+            let position = position.translation.vector;
+
+            if let Some(direction) = light.world_direction(transform) {
+                lights.write_direction(i, &direction);
             }
 
             match light {
-                Light::Directional { direction, color, intensity } => {
-                    lights.write_direction(i, direction);
+                Light::Directional { color, intensity, .. } => {
                     lights.write_color(i, color);
                     lights.write_intensity(i, *intensity);
                     lights.write_type(i, 0.0);
                 },
-                Light::Point { color, intensity, range } => {
+                Light::Point { color, intensity, .. } => {
+                    lights.write_position(i, &position);
                     lights.write_color(i, color);
                     lights.write_intensity(i, *intensity);
-                    lights.write_range(i, *range);
+                    lights.write_range(i, light.effective_range().unwrap_or_default());
                     lights.write_type(i, 1.0);
                 },
-                Light::Spot { direction, color, intensity, range, inner_cone_cos, outer_cone_cos } => {
-                    lights.write_direction(i, direction);
+                Light::Spot { color, intensity, inner_cone_cos, outer_cone_cos, .. } => {
+                    lights.write_position(i, &position);
                     lights.write_color(i, color);
                     lights.write_intensity(i, *intensity);
-                    lights.write_range(i, *range);
+                    lights.write_range(i, light.effective_range().unwrap_or_default());
                     lights.write_cone_cos(i, *inner_cone_cos, *outer_cone_cos);
                     lights.write_type(i, 2.0);
                 }
             }
+
+            bounds.push(LightBounds {
+                index: i as u32,
+                position,
+                range: light.effective_range(),
+                brightness: match light {
+                    Light::Directional { .. } => f32::INFINITY,
+                    Light::Point { color, intensity, .. } | Light::Spot { color, intensity, .. } => intensity * color.max(),
+                },
+            });
         }
 
-        lights.scratch_buffer[0] = active_len as f32;
+        // full rows, and at least one so the texture is valid
+        let n_rows = ((bounds.len() + LIGHTS_PER_ROW - 1) / LIGHTS_PER_ROW).max(1);
+        lights.scratch_buffer.resize(n_rows * LIGHTS_PER_ROW * SIZE_PER_LIGHT, 0.0);
+        upload_data_texture(gl, lights.data_texture_id, PixelInternalFormat::Rgba32f, PixelDataFormat::Rgba, &lights.scratch_buffer, (LIGHTS_PER_ROW * SIZE_PER_LIGHT / 4) as u32, n_rows as u32)?;
 
-        gl.upload_uniform_buffer_f32(
-            lights.buffer_id,
-            &lights.scratch_buffer[0..max_buffer_len],
-            BufferUsage::DynamicDraw,
-        )?;
-        gl.activate_uniform_buffer_loc(lights.buffer_id, UBO_LIGHTS);
-        Ok(())
+        let camera = self.camera.get_active_dyn().map(|camera| {
+            let view:Mat4 = camera.view().cast();
+            let projection:Mat4 = camera.projection().cast();
+            (view, projection)
+        });
+        let (_, _, width, height) = gl.get_viewport();
+
+        lights.clusters.update(gl, &bounds, camera, width, height)
     }
+}
+
+// nearest-filtered float data, read back with texelFetch
+pub(crate) fn upload_data_texture(
+    gl: &mut WebGl2Renderer,
+    texture_id: Id,
+    internal_format: PixelInternalFormat,
+    data_format: PixelDataFormat,
+    data: &[f32],
+    width: u32,
+    height: u32
+) -> Result<()> {
+    let data:Float32Array = TypedData::new(data).into();
+
+    gl.assign_texture(
+        texture_id,
+        TextureTarget::Texture2d,
+        &TextureOptions{
+            internal_format,
+            data_format,
+            data_type: DataType::Float,
+            cube_face: None,
+        },
+        Some(|gl:&WebGl2RenderingContext| {
+            gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::S, TextureWrapMode::ClampToEdge);
+            gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::T, TextureWrapMode::ClampToEdge);
+            gl.awsm_texture_set_min_filter(TextureTarget::Texture2d, TextureMinFilter::Nearest);
+            gl.awsm_texture_set_mag_filter(TextureTarget::Texture2d, TextureMagFilter::Nearest);
+        }),
+        &WebGlTextureSource::ArrayBufferView(&data, width, height, 1)
+    )?;
+
+    gl.release_texture_target(TextureTarget::Texture2d);
 
+    Ok(())
 }
@@ -0,0 +1,211 @@
+/*
+ * Clustered lighting
+ *
+ * The view frustum is split into a grid of "froxels": CLUSTERS_X * CLUSTERS_Y screen tiles
+ * and CLUSTERS_Z depth slices, which are exponential between the camera's near and far
+ * so they stay roughly the same shape all the way back
+ *
+ * Every frame, each light is assigned to the clusters its range overlaps (on the cpu)
+ * and the result is uploaded as two float textures:
+ *
+ *   clusters: CLUSTERS_X * CLUSTERS_Y wide, CLUSTERS_Z high, r: offset into the indices, g: count
+ *   indices: the light indices for all the clusters back to back, wrapped at LIGHT_INDICES_WIDTH
+ *
+ * The fragment shader finds its cluster from gl_FragCoord and its view depth
+ * and only loops over those lights (see apply_lights() in light.glsl)
+ *
+ * Directional lights are in every cluster, and lights with an unlimited range get one from their intensity
+ * (see Light::effective_range)
+ *
+ * Each cluster holds at most MAX_LIGHTS_PER_CLUSTER, which also bounds the indices texture size
+ * when there are more, the brightest ones are kept
+ */
+use crate::prelude::*;
+use super::{upload_data_texture, shadow::view_depth_from_ndc};
+use awsm_web::webgl::{WebGl2Renderer, PixelInternalFormat, PixelDataFormat};
+use nalgebra_glm::{Vec3, Vec4, Mat4};
+
+pub(crate) const CLUSTERS_X:usize = 16;
+pub(crate) const CLUSTERS_Y:usize = 9;
+pub(crate) const CLUSTERS_Z:usize = 24;
+pub(crate) const LIGHT_INDICES_WIDTH:usize = 1024;
+// with every cluster full that's 864 rows, comfortably under the 2048 MAX_TEXTURE_SIZE WebGl2 guarantees
+pub(crate) const MAX_LIGHTS_PER_CLUSTER:usize = 256;
+
+const N_CLUSTERS:usize = CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z;
+// log slices need a positive near plane, e.g. for orthographic cameras
+const MIN_NEAR:f32 = 0.01;
+// for infinite far planes
+const MAX_FAR_RATIO:f32 = 100000.0;
+
+pub(crate) struct LightClusters {
+    pub(crate) clusters_texture_id: Id,
+    pub(crate) indices_texture_id: Id,
+    // x: CLUSTERS_X / width, y: CLUSTERS_Y / height, z: near, w: CLUSTERS_Z / ln(far / near)
+    pub(crate) params: [f32;4],
+    // scratch, rebuilt every frame
+    cluster_lights: Vec<Vec<u32>>,
+    clusters_buffer: Vec<f32>,
+    indices_buffer: Vec<f32>,
+}
+
+// what the clusters need to know about each light
+pub(crate) struct LightBounds {
+    // into the light data texture
+    pub index: u32,
+    pub position: Vec3,
+    // None is everywhere
+    pub range: Option<f32>,
+    // which lights to keep when a cluster is full
+    pub brightness: f32,
+}
+
+impl LightClusters {
+    pub fn new(gl: &mut WebGl2Renderer) -> Result<Self> {
+        Ok(Self {
+            clusters_texture_id: gl.create_texture()?,
+            indices_texture_id: gl.create_texture()?,
+            params: [0.0; 4],
+            cluster_lights: vec![Vec::new(); N_CLUSTERS],
+            clusters_buffer: vec![0.0; N_CLUSTERS * 4],
+            indices_buffer: Vec::new(),
+        })
+    }
+
+    // camera is (view, projection), without one every cluster is just empty
+    pub(crate) fn update(&mut self, gl: &mut WebGl2Renderer, lights: &[LightBounds], camera: Option<(Mat4, Mat4)>, width: u32, height: u32) -> Result<()> {
+        for cluster in self.cluster_lights.iter_mut() {
+            cluster.clear();
+        }
+
+        if let Some((view, projection)) = camera {
+            let near = view_depth_from_ndc(&projection, -1.0).max(MIN_NEAR);
+            let far = view_depth_from_ndc(&projection, 1.0);
+            let far = if far.is_finite() && far > near { far } else { near * MAX_FAR_RATIO };
+
+            let z_scale = CLUSTERS_Z as f32 / (far / near).ln();
+            let slice = |depth: f32| -> usize {
+                if depth <= near {
+                    0
+                } else {
+                    (((depth / near).ln() * z_scale) as usize).min(CLUSTERS_Z - 1)
+                }
+            };
+            let tile = |ndc: f32, size: usize| -> usize {
+                ((((ndc.clamp(-1.0, 1.0) * 0.5) + 0.5) * size as f32) as usize).min(size - 1)
+            };
+
+            let view_projection = projection * view;
+
+            for light in lights {
+                let (x_range, y_range, z_range) = match light.range {
+                    Some(range) => {
+                        let depth = -(view * Vec4::new(light.position.x, light.position.y, light.position.z, 1.0)).z;
+                        if depth + range < near || depth - range > far {
+                            continue;
+                        }
+
+                        let (x_range, y_range) = match light_ndc_rect(&view_projection, &light.position, range) {
+                            LightArea::Hidden => continue,
+                            LightArea::Screen => ((0, CLUSTERS_X - 1), (0, CLUSTERS_Y - 1)),
+                            LightArea::Rect(min, max) => (
+                                (tile(min.0, CLUSTERS_X), tile(max.0, CLUSTERS_X)),
+                                (tile(min.1, CLUSTERS_Y), tile(max.1, CLUSTERS_Y)),
+                            ),
+                        };
+
+                        (x_range, y_range, (slice(depth - range), slice(depth + range)))
+                    },
+                    None => ((0, CLUSTERS_X - 1), (0, CLUSTERS_Y - 1), (0, CLUSTERS_Z - 1))
+                };
+
+                for z in z_range.0..=z_range.1 {
+                    for y in y_range.0..=y_range.1 {
+                        for x in x_range.0..=x_range.1 {
+                            self.cluster_lights[x + (y * CLUSTERS_X) + (z * CLUSTERS_X * CLUSTERS_Y)].push(light.index);
+                        }
+                    }
+                }
+            }
+
+            self.params = [
+                CLUSTERS_X as f32 / width.max(1) as f32,
+                CLUSTERS_Y as f32 / height.max(1) as f32,
+                near,
+                z_scale
+            ];
+        }
+
+        // lights are pushed in index order, so it's just a lookup
+        for cluster in self.cluster_lights.iter_mut() {
+            if cluster.len() > MAX_LIGHTS_PER_CLUSTER {
+                let brightness = |light_index: &u32| lights.get(*light_index as usize).map(|light| light.brightness).unwrap_or_default();
+                cluster.sort_by(|a, b| brightness(b).partial_cmp(&brightness(a)).unwrap_or(std::cmp::Ordering::Equal));
+                cluster.truncate(MAX_LIGHTS_PER_CLUSTER);
+            }
+        }
+
+        self.indices_buffer.clear();
+
+        for (index, cluster) in self.cluster_lights.iter().enumerate() {
+            self.clusters_buffer[index * 4] = self.indices_buffer.len() as f32;
+            self.clusters_buffer[(index * 4) + 1] = cluster.len() as f32;
+            self.indices_buffer.extend(cluster.iter().map(|light_index| *light_index as f32));
+        }
+
+        // full rows, and at least one so the texture is valid
+        let n_rows = ((self.indices_buffer.len() + LIGHT_INDICES_WIDTH - 1) / LIGHT_INDICES_WIDTH).max(1);
+        self.indices_buffer.resize(n_rows * LIGHT_INDICES_WIDTH, 0.0);
+
+        upload_data_texture(gl, self.clusters_texture_id, PixelInternalFormat::Rgba32f, PixelDataFormat::Rgba, &self.clusters_buffer, (CLUSTERS_X * CLUSTERS_Y) as u32, CLUSTERS_Z as u32)?;
+        upload_data_texture(gl, self.indices_texture_id, PixelInternalFormat::R32f, PixelDataFormat::Red, &self.indices_buffer, LIGHT_INDICES_WIDTH as u32, n_rows as u32)?;
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for LightClusters {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.delete_texture(self.clusters_texture_id)?;
+        gl.delete_texture(self.indices_texture_id)?;
+        Ok(())
+    }
+}
+
+pub(crate) enum LightArea {
+    Hidden,
+    Screen,
+    // min and max, in ndc
+    Rect((f32, f32), (f32, f32)),
+}
+
+// the screen rect of the bounding box around the light's range
+pub(crate) fn light_ndc_rect(view_projection: &Mat4, position: &Vec3, range: f32) -> LightArea {
+    let mut min = (f32::MAX, f32::MAX);
+    let mut max = (f32::MIN, f32::MIN);
+
+    for corner in 0..8 {
+        let offset = Vec3::new(
+            if corner & 1 == 0 { -range } else { range },
+            if corner & 2 == 0 { -range } else { range },
+            if corner & 4 == 0 { -range } else { range },
+        );
+        let point = position + offset;
+        let clip = view_projection * Vec4::new(point.x, point.y, point.z, 1.0);
+
+        // straddles the camera plane, can't project it
+        if clip.w <= 0.0 {
+            return LightArea::Screen;
+        }
+
+        let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+        min = (min.0.min(x), min.1.min(y));
+        max = (max.0.max(x), max.1.max(y));
+    }
+
+    if max.0 < -1.0 || max.1 < -1.0 || min.0 > 1.0 || min.1 > 1.0 {
+        return LightArea::Hidden;
+    }
+
+    LightArea::Rect(min, max)
+}
@@ -0,0 +1,643 @@
+/*
+ * Shadow mapping for directional (cascaded), spot, and point (cubemap) lights
+ *
+ * Whether shadows are enabled at all is baked into the mesh shaders
+ * so it's toggled rarely via renderer.set_shadows()
+ *
+ * After that, it's all components:
+ *
+ * 1. ShadowCaster on a light entity makes that light render a shadow map
+ * 2. ShadowCaster on a mesh entity makes it draw into those shadow maps
+ * 3. ShadowReceiver on a mesh entity makes it sample them
+ *
+ * There are a fixed number of shadow maps (MAX_SHADOW_MAPS), each directional light takes
+ * one per cascade (up to MAX_CASCADES) and each spot light takes one. Anything beyond that is skipped.
+ * A directional light gets either all of its cascades or none, so the default 3 cascades leave room for 3 spots
+ *
+ * Point lights render into a depth cubemap instead (up to MAX_POINT_SHADOW_MAPS), which is 6 draws of every caster
+ * so each one is only re-rendered when something changed: the light itself, or a caster within its range
+ * (transform, morph weights, or skin joints). And config.point_light_budget caps how many re-render per frame,
+ * the rest wait their turn without a shadow (if the map was for another light) or with a stale one
+ *
+ * The depth pass re-uses the regular mesh vertex shader, and just swaps out
+ * the camera ubo for the light's view and projection
+ * Masked meshes also get a fragment shader that discards below the alpha cutoff, so cutouts cast cutout shadows
+ */
+use std::hash::{Hash, Hasher};
+use rustc_hash::FxHasher;
+use crate::{
+    prelude::*,
+    light::Light,
+    bounds::{WorldBounds, BoundingSphere},
+    cubemap::cubemap::CubeMap,
+    renderer::{
+        draw_buffers::{FrameBuffer, FrameBufferIdKind},
+        systems::{upload_mesh_vertex_uniforms, upload_alpha_mask_uniforms},
+    },
+};
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    TextureTarget,
+    FrameBufferTarget,
+    ResizeStrategy,
+    GlToggle,
+    CmpFunction,
+    BufferMask,
+    UniformType,
+    PartialWebGlTextures,
+    TextureMinFilter,
+    TextureMagFilter,
+    TextureOptions,
+    TextureWrapTarget,
+    TextureWrapMode,
+    PixelInternalFormat,
+    PixelDataFormat,
+    DataType,
+    WebGlTextureSource,
+    FrameBufferAttachment,
+};
+use nalgebra::Isometry3;
+use nalgebra_glm::{Vec3, Vec4, Mat4};
+use web_sys::WebGl2RenderingContext;
+
+pub(crate) const MAX_SHADOW_MAPS:usize = 6;
+pub(crate) const MAX_CASCADES:usize = 4;
+pub(crate) const MAX_POINT_SHADOW_MAPS:usize = 4;
+const POINT_SHADOW_NEAR:f32 = 0.05;
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct ShadowCaster;
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct ShadowReceiver;
+
+#[derive(Clone, Debug)]
+pub struct ShadowConfig {
+    // width and height of each shadow map
+    pub map_size: u32,
+    // number of cascades for directional lights (up to MAX_CASCADES)
+    pub cascades: u32,
+    // directional shadows stop at this distance from the camera (or the camera's far plane)
+    pub max_distance: f32,
+    // blend between uniform (0.0) and logarithmic (1.0) cascade splits
+    pub cascade_split_lambda: f32,
+    pub bias: f32,
+    pub normal_bias: f32,
+    // width and height of each face of the point light cubemaps
+    pub point_map_size: u32,
+    // how many point light shadow maps can be re-rendered per frame
+    pub point_light_budget: u32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            map_size: 1024,
+            cascades: 3,
+            max_distance: 100.0,
+            cascade_split_lambda: 0.75,
+            bias: 0.001,
+            normal_bias: 0.02,
+            point_map_size: 512,
+            point_light_budget: 2,
+        }
+    }
+}
+
+pub struct Shadows {
+    pub(crate) config: Option<ShadowConfig>,
+    pub(crate) maps: Vec<FrameBuffer>,
+    // per-frame uniform data, MAX_SHADOW_MAPS entries each
+    pub(crate) view_projections: Vec<f32>,
+    // x: light index (-1 if unused), y: view depth start, z: view depth end, w: normal bias
+    pub(crate) params: Vec<f32>,
+    pub(crate) point_maps: Vec<PointShadowMap>,
+    // x: light index (-1 if unused), y: near, z: far, w: normal bias
+    pub(crate) point_params: Vec<f32>,
+    pub(crate) point_positions: Vec<f32>,
+}
+
+pub(crate) struct PointShadowMap {
+    pub fbo: Id,
+    pub texture_id: Id,
+    // what it was last rendered with, to know when it's dirty
+    pub light: Option<EntityId>,
+    pub signature: u64,
+}
+
+impl Shadows {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            maps: Vec::new(),
+            view_projections: vec![0.0; MAX_SHADOW_MAPS * 16],
+            params: vec![-1.0; MAX_SHADOW_MAPS * 4],
+            point_maps: Vec::new(),
+            point_params: vec![-1.0; MAX_POINT_SHADOW_MAPS * 4],
+            point_positions: vec![0.0; MAX_POINT_SHADOW_MAPS * 3],
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    // expects the mesh program to already be active
+    pub(crate) fn upload_uniforms(&self, gl: &mut WebGl2Renderer, receiver: bool) -> Result<()> {
+        let config = match self.config.as_ref() {
+            Some(config) => config,
+            None => return Ok(())
+        };
+
+        gl.upload_uniform_mat_4_name("u_shadow_view_projection", &self.view_projections)?;
+        gl.upload_uniform_fvec_name("u_shadow_params", UniformType::Vector4, &self.params)?;
+        gl.upload_uniform_fval_name("u_shadow_bias", config.bias)?;
+        gl.upload_uniform_fval_name("u_shadow_texel_size", 1.0 / config.map_size as f32)?;
+        gl.upload_uniform_fval_name("u_shadow_receiver", if receiver { 1.0 } else { 0.0 })?;
+
+        for (index, map) in self.maps.iter().enumerate() {
+            if let Some(depth) = map.depth {
+                gl.activate_texture_sampler_name(depth.id, &format!("u_shadow_map_{index}"))?;
+            }
+        }
+
+        gl.upload_uniform_fvec_name("u_point_shadow_params", UniformType::Vector4, &self.point_params)?;
+        gl.upload_uniform_fvec_name("u_point_shadow_positions", UniformType::Vector3, &self.point_positions)?;
+
+        // unused ones are skipped via the params
+        for (index, map) in self.point_maps.iter().enumerate() {
+            gl.activate_texture_sampler_name(map.texture_id, &format!("u_point_shadow_map_{index}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for Shadows {
+    fn destroy(&mut self, mut gl:&mut WebGl2Renderer) -> Result<()> {
+        for mut map in self.maps.drain(..) {
+            map.destroy(&mut gl)?;
+        }
+        for map in self.point_maps.drain(..) {
+            gl.delete_framebuffer(map.fbo)?;
+            gl.delete_texture(map.texture_id)?;
+        }
+        Ok(())
+    }
+}
+
+// a single shadow map render for this frame
+struct ShadowPass {
+    map_index: usize,
+    view: Mat4,
+    projection: Mat4,
+}
+
+impl AwsmRenderer {
+    pub fn set_shadows(&mut self, world: &World, config: Option<ShadowConfig>) -> Result<()> {
+        let changed = self.shadows.enabled() != config.is_some();
+
+        self.shadows.destroy(&mut self.gl)?;
+
+        if let Some(config) = config.as_ref() {
+            for _ in 0..MAX_SHADOW_MAPS {
+                let map = FrameBuffer::new(self)?
+                    .build_depth(self, config.map_size, config.map_size, FrameBufferIdKind::Texture, false)?
+                    .validate(self)?;
+                map.release(self);
+
+                // switch the depth texture to hardware comparisons (and bilinear pcf for free)
+                if let Some(depth) = map.depth {
+                    let texture = self.gl.get_texture(depth.id)?;
+                    let gl = &self.gl.gl;
+                    gl.awsm_bind_texture(TextureTarget::Texture2d, texture);
+                    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_COMPARE_MODE, WebGl2RenderingContext::COMPARE_REF_TO_TEXTURE as i32);
+                    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_COMPARE_FUNC, WebGl2RenderingContext::LEQUAL as i32);
+                    gl.awsm_texture_set_min_filter(TextureTarget::Texture2d, TextureMinFilter::Linear);
+                    gl.awsm_texture_set_mag_filter(TextureTarget::Texture2d, TextureMagFilter::Linear);
+                    self.gl.release_texture_target(TextureTarget::Texture2d);
+                }
+
+                self.shadows.maps.push(map);
+            }
+
+            for _ in 0..MAX_POINT_SHADOW_MAPS {
+                let map = PointShadowMap {
+                    fbo: self.gl.create_framebuffer()?,
+                    texture_id: empty_depth_cubemap_texture(&mut self.gl, config.point_map_size)?,
+                    light: None,
+                    signature: 0,
+                };
+                self.shadows.point_maps.push(map);
+            }
+        }
+
+        self.shadows.config = config;
+
+        if changed {
+            self.recompile_mesh_programs(world)?;
+        }
+
+        Ok(())
+    }
+
+    // clobbers the camera ubo, so must be called before update_camera_ubo()
+    pub(crate) fn render_shadow_maps(
+        &mut self,
+        meshes: &View<Mesh>,
+        materials: &View<Material>,
+        lights: &View<Light>,
+        shadow_casters: &View<ShadowCaster>,
+        mesh_morph_weights: &View<MeshMorphWeights>,
+        mesh_skin_joints: &View<MeshSkinJoint>,
+        world_transforms: &View<WorldTransform>,
+        world_bounds: &View<WorldBounds>,
+    ) -> Result<()> {
+
+        let config = match self.shadows.config.clone() {
+            Some(config) => config,
+            None => return Ok(())
+        };
+
+        for params in self.shadows.params.chunks_mut(4) {
+            params[0] = -1.0;
+        }
+        for params in self.shadows.point_params.chunks_mut(4) {
+            params[0] = -1.0;
+        }
+
+        let camera = match self.camera.get_active_dyn() {
+            Some(camera) => camera,
+            None => return Ok(())
+        };
+
+        let camera_projection:Mat4 = camera.projection().cast();
+        let camera_view_projection_inverse:Mat4 = camera.view_projection_inverse().cast();
+
+        let mut passes:Vec<ShadowPass> = Vec::new();
+        // (point map index, light position, far) for the maps that need re-rendering
+        let mut point_passes:Vec<(usize, Vec3, f32)> = Vec::new();
+        let mut n_point_lights = 0;
+        let n_point_maps = self.shadows.point_maps.len();
+        let casters = if n_point_maps > 0 { caster_states(meshes, shadow_casters, mesh_morph_weights, mesh_skin_joints, world_transforms, world_bounds) } else { Vec::new() };
+
+        // same iteration as update_lights, so the index matches the light in the shader
+        for (light_index, (entity, (transform, light))) in (world_transforms, lights).iter().with_id().enumerate() {
+            if !shadow_casters.contains(entity) {
+                continue;
+            }
+
+            let transform:&Mat4 = &transform;
+            let position:Isometry3<f32> = nalgebra::convert_unchecked(*transform);
+            let position = position.translation.vector;
+
+            match light {
+                Light::Directional { .. } => {
+                    let direction = light.world_direction(transform).unwrap_or_default();
+                    let splits = cascade_splits(&camera_projection, &config);
+                    if passes.len() + splits.len() - 1 > MAX_SHADOW_MAPS {
+                        continue;
+                    }
+
+                    for cascade in 0..splits.len() - 1 {
+                        let map_index = passes.len();
+                        let (near, far) = (splits[cascade], splits[cascade+1]);
+                        let (view, projection) = directional_view_projection(&direction, near, far, &camera_projection, &camera_view_projection_inverse, &config);
+                        self.shadows.write_pass(map_index, light_index, near, far, &view, &projection, &config);
+                        passes.push(ShadowPass { map_index, view, projection });
+                    }
+                },
+                Light::Spot { range, outer_cone_cos, .. } => {
+                    let map_index = passes.len();
+                    if map_index >= MAX_SHADOW_MAPS {
+                        continue;
+                    }
+                    let direction = light.world_direction(transform).unwrap_or_default();
+
+                    let far = if *range > 0.0 { *range } else { config.max_distance };
+                    let fov = (outer_cone_cos.clamp(-1.0, 1.0).acos() * 2.0).min(std::f32::consts::PI - 0.01);
+
+                    let view = nalgebra_glm::look_at(&position, &(position + direction), &up_vector(&direction));
+                    let projection = nalgebra_glm::perspective(1.0, fov, 0.05, far);
+
+                    self.shadows.write_pass(map_index, light_index, 0.0, f32::MAX, &view, &projection, &config);
+                    passes.push(ShadowPass { map_index, view, projection });
+                },
+                Light::Point { range, .. } => {
+                    let map_index = n_point_lights;
+                    if map_index >= n_point_maps {
+                        continue;
+                    }
+                    n_point_lights += 1;
+
+                    let far = if *range > 0.0 { *range } else { config.max_distance };
+
+                    // only what this light can actually see
+                    let signature = {
+                        let mut hasher = FxHasher::default();
+                        for value in position.iter().chain(std::iter::once(&far)) {
+                            value.to_bits().hash(&mut hasher);
+                        }
+                        for caster in casters.iter().filter(|caster| caster.in_range(&position, far)) {
+                            caster.hash.hash(&mut hasher);
+                        }
+                        hasher.finish()
+                    };
+
+                    let map = &mut self.shadows.point_maps[map_index];
+                    if (map.light != Some(entity) || map.signature != signature) && point_passes.len() < config.point_light_budget as usize {
+                        map.light = Some(entity);
+                        map.signature = signature;
+                        point_passes.push((map_index, position, far));
+                    }
+
+                    // otherwise it's still holding another light's shadow
+                    if map.light == Some(entity) {
+                        self.shadows.write_point_params(map_index, light_index, &position, far, &config);
+                    }
+                }
+            }
+        }
+
+        // anything no longer assigned needs to re-render when it is
+        for map in self.shadows.point_maps.iter_mut().skip(n_point_lights) {
+            map.light = None;
+        }
+
+        if passes.is_empty() && point_passes.is_empty() {
+            return Ok(());
+        }
+
+        let viewport_before = self.gl.get_viewport();
+
+        self.gl.set_depth_mask(true);
+        self.gl.toggle(GlToggle::Blend, false);
+        self.gl.toggle(GlToggle::DepthTest, true);
+        self.gl.toggle(GlToggle::CullFace, false);
+        self.gl.set_depth_func(CmpFunction::Less);
+
+        self.gl.resize(ResizeStrategy::ViewportSize(config.map_size, config.map_size));
+        for pass in passes {
+            self.gl.bind_framebuffer(self.shadows.maps[pass.map_index].id, FrameBufferTarget::DrawFrameBuffer)?;
+            self.draw_shadow_casters(&pass.view, &pass.projection, meshes, materials, shadow_casters, mesh_morph_weights, mesh_skin_joints, world_transforms)?;
+        }
+
+        // same face orientation as CubeMap::target_from_index
+        // https://learnopengl.com/Advanced-Lighting/Shadows/Point-Shadows
+        const FACES:[([f32;3], [f32;3]);6] = [
+            ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+        ];
+
+        self.gl.resize(ResizeStrategy::ViewportSize(config.point_map_size, config.point_map_size));
+        for (map_index, position, far) in point_passes {
+            let projection = nalgebra_glm::perspective(1.0, std::f32::consts::FRAC_PI_2, POINT_SHADOW_NEAR, far);
+
+            for (face, (target, up)) in FACES.iter().enumerate() {
+                let (fbo, texture_id) = {
+                    let map = &self.shadows.point_maps[map_index];
+                    (map.fbo, map.texture_id)
+                };
+                self.gl.assign_framebuffer_texture_2d(fbo, texture_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Depth, CubeMap::target_from_index(face)?)?;
+
+                let view = nalgebra_glm::look_at(&position, &(position + Vec3::from(*target)), &Vec3::from(*up));
+                self.draw_shadow_casters(&view, &projection, meshes, materials, shadow_casters, mesh_morph_weights, mesh_skin_joints, world_transforms)?;
+            }
+        }
+
+        self.gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+        self.gl.resize(ResizeStrategy::Viewport(viewport_before.0, viewport_before.1, viewport_before.2, viewport_before.3));
+
+        Ok(())
+    }
+
+    // expects the target framebuffer to already be bound
+    fn draw_shadow_casters(
+        &mut self,
+        view: &Mat4,
+        projection: &Mat4,
+        meshes: &View<Mesh>,
+        materials: &View<Material>,
+        shadow_casters: &View<ShadowCaster>,
+        mesh_morph_weights: &View<MeshMorphWeights>,
+        mesh_skin_joints: &View<MeshSkinJoint>,
+        world_transforms: &View<WorldTransform>,
+    ) -> Result<()> {
+        let mut mat4_buf:[f32;16] = [0.0;16];
+        let mut skin_buf:Vec<f32> = Vec::new();
+
+        self.upload_camera_ubo_override(view, projection)?;
+        self.gl.clear(&[BufferMask::DepthBufferBit]);
+
+        for (entity, (mesh, world_transform, _)) in (meshes, world_transforms, shadow_casters).iter().with_id() {
+            let program_id = self.shadow_depth_program(&mesh.shader_key)?;
+            let gl = &mut self.gl;
+            gl.activate_program(program_id)?;
+            gl.activate_vertex_array(mesh.vao_id)?;
+            upload_mesh_vertex_uniforms(gl, entity, mesh, world_transform, mesh_morph_weights, mesh_skin_joints, &self.skin_texture, &mut mat4_buf, &mut skin_buf)?;
+            if mesh.shader_key.alpha_mask_key().is_some() {
+                if let Ok(material) = materials.get(entity) {
+                    upload_alpha_mask_uniforms(gl, material)?;
+                }
+            }
+            mesh.draw(gl);
+        }
+
+        Ok(())
+    }
+}
+
+impl Shadows {
+    fn write_pass(&mut self, map_index: usize, light_index: usize, near: f32, far: f32, view: &Mat4, projection: &Mat4, config: &ShadowConfig) {
+        let view_projection = projection * view;
+        self.view_projections[map_index * 16..(map_index + 1) * 16].copy_from_slice(view_projection.as_slice());
+
+        let params = &mut self.params[map_index * 4..(map_index + 1) * 4];
+        params[0] = light_index as f32;
+        params[1] = near;
+        params[2] = far;
+        params[3] = config.normal_bias;
+    }
+}
+
+impl Shadows {
+    fn write_point_params(&mut self, map_index: usize, light_index: usize, position: &Vec3, far: f32, config: &ShadowConfig) {
+        let params = &mut self.point_params[map_index * 4..(map_index + 1) * 4];
+        params[0] = light_index as f32;
+        params[1] = POINT_SHADOW_NEAR;
+        params[2] = far;
+        params[3] = config.normal_bias;
+
+        self.point_positions[map_index * 3..(map_index + 1) * 3].copy_from_slice(position.as_slice());
+    }
+}
+
+// a caster as far as the point shadow dirty checks go
+struct CasterState {
+    // None if unknown, i.e. it could be anywhere
+    sphere: Option<BoundingSphere>,
+    // changes whenever anything that moves its vertices does
+    hash: u64,
+}
+
+impl CasterState {
+    fn in_range(&self, position: &Vec3, range: f32) -> bool {
+        match self.sphere.as_ref() {
+            Some(sphere) => (sphere.center - position).magnitude() - sphere.radius <= range,
+            None => true
+        }
+    }
+}
+
+// WorldTransform (and MeshSkinJoint) modification tracking is never cleared, so can't rely on that
+fn caster_states(
+    meshes: &View<Mesh>,
+    shadow_casters: &View<ShadowCaster>,
+    mesh_morph_weights: &View<MeshMorphWeights>,
+    mesh_skin_joints: &View<MeshSkinJoint>,
+    world_transforms: &View<WorldTransform>,
+    world_bounds: &View<WorldBounds>,
+) -> Vec<CasterState> {
+    (meshes, world_transforms, shadow_casters).iter().with_id()
+        .map(|(entity, (mesh, world_transform, _))| {
+            let mut hasher = FxHasher::default();
+
+            entity.hash(&mut hasher);
+            let world_transform:&Mat4 = &world_transform;
+            for value in world_transform.iter() {
+                value.to_bits().hash(&mut hasher);
+            }
+
+            if let Ok(morph_weights) = mesh_morph_weights.get(entity) {
+                for value in morph_weights.0.iter() {
+                    value.to_bits().hash(&mut hasher);
+                }
+            }
+
+            for skin_joint_entity in mesh.skin_joints.iter() {
+                if let Ok(skin_joint) = mesh_skin_joints.get(*skin_joint_entity) {
+                    for value in skin_joint.joint_mat.iter() {
+                        value.to_bits().hash(&mut hasher);
+                    }
+                }
+            }
+
+            // the bounds are for the rest pose, so a skinned mesh could reach further
+            let sphere = if mesh.skin_joints.is_empty() {
+                world_bounds.get(entity).ok().map(|bounds| bounds.sphere)
+            } else {
+                None
+            };
+
+            CasterState {
+                sphere,
+                hash: hasher.finish(),
+            }
+        })
+        .collect()
+}
+
+fn empty_depth_cubemap_texture(gl: &mut WebGl2Renderer, size: u32) -> Result<Id> {
+    let id = gl.create_texture()?;
+
+    for i in 0..6 {
+        gl.assign_texture(
+            id,
+            TextureTarget::CubeMap,
+            &TextureOptions{
+                internal_format: PixelInternalFormat::DepthComponent,
+                data_format: PixelDataFormat::DepthComponent,
+                data_type: DataType::UnsignedInt,
+                cube_face: Some(CubeMap::face_from_index(i)?),
+            },
+            Some(|gl:&WebGl2RenderingContext| {
+                gl.awsm_texture_set_wrap(TextureTarget::CubeMap, TextureWrapTarget::S, TextureWrapMode::ClampToEdge);
+                gl.awsm_texture_set_wrap(TextureTarget::CubeMap, TextureWrapTarget::T, TextureWrapMode::ClampToEdge);
+                gl.awsm_texture_set_min_filter(TextureTarget::CubeMap, TextureMinFilter::Linear);
+                gl.awsm_texture_set_mag_filter(TextureTarget::CubeMap, TextureMagFilter::Linear);
+                gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_CUBE_MAP, WebGl2RenderingContext::TEXTURE_COMPARE_MODE, WebGl2RenderingContext::COMPARE_REF_TO_TEXTURE as i32);
+                gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_CUBE_MAP, WebGl2RenderingContext::TEXTURE_COMPARE_FUNC, WebGl2RenderingContext::LEQUAL as i32);
+            }),
+            &WebGlTextureSource::EmptyBufferView(size, size, 1)
+        )?;
+    }
+
+    gl.release_texture_target(TextureTarget::CubeMap);
+
+    Ok(id)
+}
+
+// view-space depths of the cascade boundaries, using the "practical split scheme"
+// https://developer.nvidia.com/gpugems/gpugems3/part-ii-light-and-shadows/chapter-10-parallel-split-shadow-maps-programmable-gpus
+fn cascade_splits(camera_projection: &Mat4, config: &ShadowConfig) -> Vec<f32> {
+    let near = view_depth_from_ndc(camera_projection, -1.0);
+    let far = view_depth_from_ndc(camera_projection, 1.0).min(config.max_distance);
+    let n_cascades = config.cascades.clamp(1, MAX_CASCADES as u32);
+
+    (0..=n_cascades)
+        .map(|i| {
+            let p = i as f32 / n_cascades as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            config.cascade_split_lambda * log + (1.0 - config.cascade_split_lambda) * uniform
+        })
+        .collect()
+}
+
+// fits an orthographic projection around the bounding sphere of the cascade's slice of the camera frustum
+// the sphere keeps the size stable as the camera rotates, and snapping to texels stops it from shimmering
+fn directional_view_projection(direction: &Vec3, near: f32, far: f32, camera_projection: &Mat4, camera_view_projection_inverse: &Mat4, config: &ShadowConfig) -> (Mat4, Mat4) {
+    let near_ndc = ndc_from_view_depth(camera_projection, near);
+    let far_ndc = ndc_from_view_depth(camera_projection, far);
+
+    let mut corners:Vec<Vec3> = Vec::with_capacity(8);
+    for z in [near_ndc, far_ndc] {
+        for y in [-1.0, 1.0] {
+            for x in [-1.0, 1.0] {
+                let corner = camera_view_projection_inverse * Vec4::new(x, y, z, 1.0);
+                corners.push(corner.xyz() / corner.w);
+            }
+        }
+    }
+
+    let center = corners.iter().fold(Vec3::zeros(), |acc, corner| acc + corner) / corners.len() as f32;
+    let radius = corners.iter().fold(0.0f32, |acc, corner| acc.max((corner - center).magnitude()));
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // pull the eye back so that casters outside the slice still land in the map
+    let eye = center - (direction * radius * 2.0);
+    let view = nalgebra_glm::look_at(&eye, &center, &up_vector(direction));
+    let mut projection = nalgebra_glm::ortho(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+
+    let half_size = config.map_size as f32 / 2.0;
+    let origin = (projection * view) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    let origin = origin.xy() * half_size;
+    let offset = (origin.map(|v| v.round()) - origin) / half_size;
+    projection[(0, 3)] += offset.x;
+    projection[(1, 3)] += offset.y;
+
+    (view, projection)
+}
+
+fn up_vector(direction: &Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::new(1.0, 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+pub(crate) fn view_depth_from_ndc(projection: &Mat4, ndc_z: f32) -> f32 {
+    let inverse = projection.try_inverse().unwrap_or_else(Mat4::identity);
+    let p = inverse * Vec4::new(0.0, 0.0, ndc_z, 1.0);
+    -p.z / p.w
+}
+
+fn ndc_from_view_depth(projection: &Mat4, depth: f32) -> f32 {
+    let p = projection * Vec4::new(0.0, 0.0, -depth, 1.0);
+    p.z / p.w
+}
@@ -0,0 +1,314 @@
+/*
+ * CPU raycasting
+ *
+ * Only reads components, never touches gl, so it works headless (e.g. on a server, or in tests)
+ *
+ * Entities need a MeshGeometry (see Config::retain_mesh_geometry) and a WorldTransform
+ * the world-space bounds are tested first (if there are any) and then every triangle
+ *
+ * The geometry is the rest pose, so skinned and morphed meshes won't line up with what's on screen
+ * use AwsmRenderer::pick() when that matters
+ */
+use crate::{
+    prelude::*,
+    bounds::{Aabb, WorldBounds},
+};
+use nalgebra_glm::{Vec2, Vec3, Mat3, Mat4};
+use std::cmp::Ordering;
+
+// anything smaller is considered parallel to the triangle
+const EPSILON:f32 = 1e-7;
+
+#[derive(Clone, Debug)]
+pub struct RayHit {
+    pub entity: EntityId,
+    // world units from the ray origin
+    pub distance: f32,
+    // world space
+    pub point: Vec3,
+    // world space, normalized
+    // interpolated from the vertex normals if there are any, otherwise the face normal
+    pub normal: Vec3,
+    // TEXCOORD_0, if the geometry has it
+    pub uv: Option<Vec2>,
+}
+
+// one hit per entity (the nearest one), sorted nearest first
+// direction doesn't need to be normalized
+pub fn raycast(world: &World, origin: &Vec3, direction: &Vec3) -> Result<Vec<RayHit>> {
+    let (geometries, world_transforms, world_bounds, aabbs) = world.borrow::<(
+        View<MeshGeometry>,
+        View<WorldTransform>,
+        View<WorldBounds>,
+        View<Aabb>,
+    )>()?;
+
+    let mut hits = Vec::new();
+
+    let direction_len = direction.norm();
+    if direction_len == 0.0 {
+        return Ok(hits);
+    }
+    let direction = direction / direction_len;
+
+    for (entity, (geometry, world_transform)) in (&geometries, &world_transforms).iter().with_id() {
+        let world_transform:&Mat4 = &world_transform;
+
+        // broad phase - WorldBounds is already computed, but might not be there if the system isn't running
+        let aabb = match world_bounds.get(entity) {
+            Ok(bounds) => Some(bounds.aabb),
+            Err(_) => aabbs.get(entity).ok().map(|aabb| aabb.transform(world_transform)),
+        };
+
+        if let Some(aabb) = aabb {
+            if aabb.intersect_ray(origin, &direction).is_none() {
+                continue;
+            }
+        }
+
+        let inverse = match world_transform.try_inverse() {
+            Some(inverse) => inverse,
+            None => continue
+        };
+
+        // the ray parameter is the same in both spaces, since the direction is transformed (not re-normalized)
+        let local_origin = inverse.transform_point(&(*origin).into()).coords;
+        let local_direction = inverse.transform_vector(&direction);
+
+        if let Some(hit) = intersect_geometry(&geometry.0, &local_origin, &local_direction) {
+            let normal_matrix:Mat3 = inverse.fixed_slice::<3, 3>(0, 0).transpose();
+
+            hits.push(RayHit {
+                entity,
+                distance: hit.t,
+                point: origin + direction * hit.t,
+                normal: (normal_matrix * hit.normal).normalize(),
+                uv: hit.uv,
+            });
+        }
+    }
+
+    hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Ordering::Equal));
+
+    Ok(hits)
+}
+
+// local space
+struct GeometryHit {
+    t: f32,
+    normal: Vec3,
+    uv: Option<Vec2>,
+}
+
+fn intersect_geometry(geometry: &MeshGeometryData, origin: &Vec3, direction: &Vec3) -> Option<GeometryHit> {
+    // t, triangle index, barycentrics
+    let mut nearest:Option<(f32, usize, f32, f32)> = None;
+
+    for (index, triangle) in geometry.triangles.iter().enumerate() {
+        let (a, b, c) = match (
+            geometry.positions.get(triangle[0] as usize),
+            geometry.positions.get(triangle[1] as usize),
+            geometry.positions.get(triangle[2] as usize),
+        ) {
+            (Some(a), Some(b), Some(c)) => (a, b, c),
+            _ => continue
+        };
+
+        if let Some((t, u, v)) = intersect_triangle(origin, direction, a, b, c) {
+            if nearest.map(|(nearest_t, ..)| t < nearest_t).unwrap_or(true) {
+                nearest = Some((t, index, u, v));
+            }
+        }
+    }
+
+    let (t, index, u, v) = nearest?;
+    let [i0, i1, i2] = geometry.triangles[index];
+    let (i0, i1, i2) = (i0 as usize, i1 as usize, i2 as usize);
+    let w = 1.0 - u - v;
+
+    let normal = geometry.normals.as_ref()
+        .and_then(|normals| Some(normals.get(i0)? * w + normals.get(i1)? * u + normals.get(i2)? * v))
+        .filter(|normal| normal.norm_squared() > 0.0)
+        .unwrap_or_else(|| {
+            let (a, b, c) = (&geometry.positions[i0], &geometry.positions[i1], &geometry.positions[i2]);
+            (b - a).cross(&(c - a))
+        });
+
+    let uv = geometry.uvs.as_ref()
+        .and_then(|uvs| Some(uvs.get(i0)? * w + uvs.get(i1)? * u + uvs.get(i2)? * v));
+
+    Some(GeometryHit { t, normal, uv })
+}
+
+// Möller–Trumbore, double-sided
+// returns t along the ray and the barycentrics of b and c
+fn intersect_triangle(origin: &Vec3, direction: &Vec3, a: &Vec3, b: &Vec3, c: &Vec3) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(&edge2);
+    let det = edge1.dot(&p);
+
+    if det.abs() < EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(&p) * inv_det;
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let v = direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&q) * inv_det;
+    if t < 0.0 {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    // 2x2 in the xy plane, facing +z
+    fn quad() -> MeshGeometry {
+        MeshGeometry::new(MeshGeometryData {
+            positions: vec![
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+            ],
+            normals: None,
+            uvs: Some(vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(0.0, 1.0),
+            ]),
+            triangles: vec![[0, 1, 2], [0, 2, 3]],
+        })
+    }
+
+    fn triangle() -> (Vec3, Vec3, Vec3) {
+        (Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let (a, b, c) = triangle();
+        let (t, u, v) = intersect_triangle(&Vec3::new(0.25, 0.5, 2.0), &Vec3::new(0.0, 0.0, -1.0), &a, &b, &c).unwrap();
+        assert!(approx(t, 2.0));
+        assert!(approx(u, 0.25));
+        assert!(approx(v, 0.5));
+    }
+
+    #[test]
+    fn triangle_miss() {
+        let (a, b, c) = triangle();
+        // outside the hypotenuse
+        assert!(intersect_triangle(&Vec3::new(0.75, 0.75, 1.0), &Vec3::new(0.0, 0.0, -1.0), &a, &b, &c).is_none());
+        // pointing away
+        assert!(intersect_triangle(&Vec3::new(0.25, 0.25, 1.0), &Vec3::new(0.0, 0.0, 1.0), &a, &b, &c).is_none());
+    }
+
+    #[test]
+    fn triangle_backface() {
+        let (a, b, c) = triangle();
+        let (t, ..) = intersect_triangle(&Vec3::new(0.25, 0.25, -3.0), &Vec3::new(0.0, 0.0, 1.0), &a, &b, &c).unwrap();
+        assert!(approx(t, 3.0));
+    }
+
+    #[test]
+    fn triangle_parallel() {
+        let (a, b, c) = triangle();
+        assert!(intersect_triangle(&Vec3::new(-1.0, 0.25, 0.0), &Vec3::new(1.0, 0.0, 0.0), &a, &b, &c).is_none());
+        assert!(intersect_triangle(&Vec3::new(-1.0, 0.25, 1.0), &Vec3::new(1.0, 0.0, 0.0), &a, &b, &c).is_none());
+    }
+
+    #[test]
+    fn aabb_ray() {
+        let aabb = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+
+        assert!(approx(aabb.intersect_ray(&Vec3::new(-5.0, 0.0, 0.0), &Vec3::new(1.0, 0.0, 0.0)).unwrap(), 4.0));
+        assert!(approx(aabb.intersect_ray(&Vec3::new(-5.0, -5.0, 0.0), &Vec3::new(1.0, 1.0, 0.0)).unwrap(), 4.0));
+        // starting inside
+        assert!(approx(aabb.intersect_ray(&Vec3::zeros(), &Vec3::new(0.0, 0.0, 1.0)).unwrap(), 0.0));
+        // pointing away
+        assert!(aabb.intersect_ray(&Vec3::new(-5.0, 0.0, 0.0), &Vec3::new(-1.0, 0.0, 0.0)).is_none());
+        // parallel and outside a slab
+        assert!(aabb.intersect_ray(&Vec3::new(-5.0, 2.0, 0.0), &Vec3::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn raycast_world() {
+        let world = World::new();
+
+        // added out of order, to check the sorting
+        let far = world.add_entity((
+            quad(),
+            WorldTransform::new(nalgebra_glm::translation(&Vec3::new(0.0, 0.0, -10.0)) * nalgebra_glm::scaling(&Vec3::new(3.0, 3.0, 3.0))),
+        ));
+        let near = world.add_entity((
+            quad(),
+            WorldTransform::new(nalgebra_glm::translation(&Vec3::new(0.0, 0.0, -4.0))),
+            Aabb::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0)),
+        ));
+        // off to the side, culled by its bounds
+        world.add_entity((
+            quad(),
+            WorldTransform::new(nalgebra_glm::translation(&Vec3::new(10.0, 0.0, -6.0))),
+            Aabb::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0)),
+        ));
+
+        // direction doesn't need to be normalized
+        let hits = raycast(&world, &Vec3::new(0.5, 0.25, 0.0), &Vec3::new(0.0, 0.0, -2.0)).unwrap();
+        assert_eq!(hits.len(), 2);
+
+        assert_eq!(hits[0].entity, near);
+        assert!(approx(hits[0].distance, 4.0));
+        assert!(approx((hits[0].point - Vec3::new(0.5, 0.25, -4.0)).norm(), 0.0));
+        assert!(approx(hits[0].normal.z, 1.0));
+        let uv = hits[0].uv.unwrap();
+        assert!(approx(uv.x, 0.75) && approx(uv.y, 0.625));
+
+        assert_eq!(hits[1].entity, far);
+        assert!(approx(hits[1].distance, 10.0));
+        // scaled up 3x, so the same point is closer to the middle
+        let uv = hits[1].uv.unwrap();
+        assert!(approx(uv.x, 0.5 + 0.5 / 6.0) && approx(uv.y, 0.5 + 0.25 / 6.0));
+
+        assert!(raycast(&world, &Vec3::zeros(), &Vec3::zeros()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn raycast_rotated() {
+        let world = World::new();
+
+        // turned to face +x
+        let entity = world.add_entity((
+            quad(),
+            WorldTransform::new(nalgebra_glm::translation(&Vec3::new(0.0, 0.0, -3.0)) * nalgebra_glm::rotation(std::f32::consts::FRAC_PI_2, &Vec3::y())),
+        ));
+
+        // past its edge, which now runs along z
+        assert!(raycast(&world, &Vec3::new(5.0, 0.0, -5.0), &Vec3::new(-1.0, 0.0, 0.0)).unwrap().is_empty());
+
+        let hits = raycast(&world, &Vec3::new(5.0, 0.0, -3.0), &Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entity, entity);
+        assert!(approx(hits[0].distance, 5.0));
+        assert!(approx(hits[0].normal.x, 1.0));
+    }
+}
@@ -1,9 +1,20 @@
-mod draw_buffers;
+pub(crate) mod draw_buffers;
 pub mod cleanup;
 pub(crate) mod material;
 pub(crate) mod mesh;
 pub mod systems;
 pub mod shaders;
+pub(crate) mod skin_texture;
+pub(crate) mod instancing;
+pub(crate) mod queue;
+pub(crate) mod picker;
+pub mod post_process;
+pub mod tonemap;
+pub mod bloom;
+pub(crate) mod fxaa;
+pub mod ssao;
+pub mod deferred;
+pub(crate) mod transmission;
 
 use shipyard::*;
 use awsm_web::webgl::{
@@ -15,8 +26,9 @@ use shipyard_scenegraph::{prelude::*, init::init_scenegraph};
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 use std::ops::{Deref, DerefMut};
 use anyhow::Result;
-use crate::{prelude::*, camera::Camera, light::Lights, animation::clock::AnimationClock, cubemap::skybox::Skybox};
-use self::{draw_buffers::{DrawBuffers, DrawBufferMode}, shaders::ShaderCache};
+use crate::{prelude::*, camera::Camera, light::{Lights, shadow::Shadows}, animation::clock::AnimationClock, cubemap::{skybox::Skybox, environment::Environment}, bounds::CullingStats};
+pub use self::draw_buffers::DrawBufferMode;
+use self::{draw_buffers::DrawBuffers, shaders::ShaderCache, skin_texture::SkinTexture, instancing::Instancing, queue::RenderQueues, picker::ScenePicker, post_process::PostProcessChain, tonemap::Tonemap, bloom::BloomConfig, ssao::Ssao};
 use cleanup::DestroyWithGl;
 
 pub struct AwsmRenderer {
@@ -28,15 +40,37 @@ pub struct AwsmRenderer {
     pub camera: Camera,
     pub lights: Lights,
     pub skybox:Option<Skybox>,
+    // set via set_environment(), since it affects mesh programs
+    pub(crate) environment:Option<Environment>,
+    // set via set_shadows(), since it affects mesh programs
+    pub(crate) shadows:Shadows,
+    // set via set_ssao(), since it affects mesh programs
+    pub(crate) ssao:Ssao,
+    pub(crate) skin_texture:SkinTexture,
+    pub(crate) instancing:Instancing,
+    pub(crate) render_queues:RenderQueues,
+    pub culling_stats:CullingStats,
+    // created on the first pick(), see renderer/picker.rs
+    pub(crate) picker:Option<ScenePicker>,
+    // fullscreen passes between the main fbo and the screen, see renderer/post_process.rs
+    pub post_process:PostProcessChain,
     //pub programs: Programs,
     //pub vaos: Vaos,
     //pub buffers: Buffers,
-    //picker: Option<ScenePicker>
 }
 
 pub struct Config {
+    // linear, and tonemapped like everything else
     pub clear_color: [f32;4],
-    pub multisample: bool
+    // takes effect on the next resize()
+    pub draw_buffer_mode: DrawBufferMode,
+    // keep a CPU-side MeshGeometry when populating gltf, e.g. for raycasting
+    pub retain_mesh_geometry: bool,
+    // applied when resolving the HDR target to the screen, can be changed at any time
+    pub tonemap: Tonemap,
+    pub exposure: f32,
+    // mixed in before tonemapping, None to skip it entirely
+    pub bloom: Option<BloomConfig>,
 }
 
 
@@ -88,7 +122,16 @@ impl AwsmRenderer {
             draw_buffers: None,
             camera,
             lights,
-            skybox: None
+            skybox: None,
+            environment: None,
+            shadows: Shadows::new(),
+            ssao: Ssao::new(),
+            skin_texture: SkinTexture::new(),
+            instancing: Instancing::new(),
+            render_queues: RenderQueues::new(),
+            culling_stats: CullingStats::default(),
+            picker: None,
+            post_process: PostProcessChain::new(),
         })
     }
 
@@ -101,13 +144,7 @@ impl AwsmRenderer {
         }
 
         self.draw_buffers = Some(
-            DrawBuffers::new(
-                self, 
-                match self.config.multisample {
-                    true => DrawBufferMode::Multisample,
-                    false => DrawBufferMode::Regular,
-                }
-            )?
+            DrawBuffers::new(self, self.config.draw_buffer_mode)?
         );
 
         self.resize_camera(width, height);
@@ -0,0 +1,221 @@
+/*
+ * Bloom
+ *
+ * Physically based, i.e. no threshold - the HDR color is progressively downsampled into a mip chain
+ * then upsampled back up (accumulating each level along the way)
+ * and the result is mixed into the scene right before tonemapping
+ *
+ * see: https://learnopengl.com/Guest-Articles/2022/Phys.-Based-Bloom
+ *
+ * Enabled via Config::bloom
+ */
+use crate::prelude::*;
+use super::{
+    draw_buffers::{FrameBuffer, FrameBufferId, FrameBufferIdKind, make_hdr_texture},
+    post_process::{PostProcessPass, PostProcessInput, draw_fullscreen_triangle},
+};
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    ResizeStrategy,
+    TextureTarget,
+    FrameBufferTarget,
+    FrameBufferAttachment,
+    FrameBufferTextureTarget,
+    GlToggle,
+    BlendFactor,
+    UniformType,
+    PartialWebGlTextures,
+    TextureMinFilter,
+    TextureMagFilter,
+    TextureWrapTarget,
+    TextureWrapMode,
+};
+
+#[derive(Clone, Debug)]
+pub struct BloomConfig {
+    // how much of the bloom is mixed in, 0.0 to 1.0
+    pub intensity: f32,
+    // upsample filter radius, in uv units
+    pub radius: f32,
+    // number of downsamples, each half the size of the last
+    pub mip_count: u32,
+}
+
+impl Default for BloomConfig {
+    fn default() -> Self {
+        Self {
+            intensity: 0.04,
+            radius: 0.005,
+            mip_count: 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BloomPrograms {
+    pub downsample: Id,
+    pub upsample: Id,
+    pub composite: Id,
+}
+
+pub(crate) struct BloomPass {
+    // set every frame from the config, see AwsmRenderer::update_post_process
+    pub config: Option<BloomConfig>,
+    pub programs: Option<BloomPrograms>,
+    mips: Vec<BloomMip>,
+    // width, height, and mip count the chain was built for
+    size: (u32, u32, u32),
+}
+
+struct BloomMip {
+    fbo: FrameBuffer,
+    texture_id: Id,
+    width: u32,
+    height: u32,
+}
+
+impl BloomPass {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            programs: None,
+            mips: Vec::new(),
+            size: (0, 0, 0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    fn update_mips(&mut self, gl: &mut WebGl2Renderer, width: u32, height: u32, mip_count: u32) -> Result<()> {
+        if self.size == (width, height, mip_count) {
+            return Ok(());
+        }
+
+        for mip in self.mips.iter_mut() {
+            mip.fbo.destroy(gl)?;
+        }
+        self.mips.clear();
+
+        let (mut mip_width, mut mip_height) = (width, height);
+
+        // always at least one, stop early if it gets too small to matter
+        for _ in 0..mip_count.max(1) {
+            mip_width = (mip_width / 2).max(1);
+            mip_height = (mip_height / 2).max(1);
+
+            let fbo_id = gl.create_framebuffer()?;
+            let texture_id = make_hdr_texture(gl, mip_width, mip_height)?;
+
+            // the down/upsample filters rely on bilinear taps
+            gl.gl.awsm_texture_set_min_filter(TextureTarget::Texture2d, TextureMinFilter::Linear);
+            gl.gl.awsm_texture_set_mag_filter(TextureTarget::Texture2d, TextureMagFilter::Linear);
+            gl.gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::S, TextureWrapMode::ClampToEdge);
+            gl.gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::T, TextureWrapMode::ClampToEdge);
+
+            gl.assign_framebuffer_texture_2d(fbo_id, texture_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0, FrameBufferTextureTarget::Texture2d)?;
+            gl.check_framebuffer_status(FrameBufferTarget::DrawFrameBuffer)?;
+
+            self.mips.push(BloomMip {
+                fbo: FrameBuffer {
+                    id: fbo_id,
+                    depth: None,
+                    color: Some(FrameBufferId { kind: FrameBufferIdKind::Texture, multisample: false, id: texture_id }),
+                },
+                texture_id,
+                width: mip_width,
+                height: mip_height,
+            });
+
+            if mip_width == 1 && mip_height == 1 {
+                break;
+            }
+        }
+
+        gl.release_texture_target(TextureTarget::Texture2d);
+        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+        self.size = (width, height, mip_count);
+
+        Ok(())
+    }
+}
+
+impl PostProcessPass for BloomPass {
+    fn render(&mut self, gl: &mut WebGl2Renderer, input: &PostProcessInput) -> Result<()> {
+        let config = self.config.clone().ok_or_else(|| anyhow!("bloom isn't enabled"))?;
+        let programs = self.programs.ok_or_else(|| anyhow!("bloom programs aren't set"))?;
+
+        self.update_mips(gl, input.width, input.height, config.mip_count)?;
+
+        let viewport_before = gl.get_viewport();
+
+        // downsample
+        gl.activate_program(programs.downsample)?;
+
+        let (mut source, mut source_width, mut source_height) = (input.color, input.width, input.height);
+
+        for (index, mip) in self.mips.iter().enumerate() {
+            gl.bind_framebuffer(mip.fbo.id, FrameBufferTarget::DrawFrameBuffer)?;
+            gl.resize(ResizeStrategy::ViewportSize(mip.width, mip.height));
+
+            gl.activate_texture_sampler_name(source, "u_color_sampler")?;
+            gl.upload_uniform_fvec_name("u_src_resolution", UniformType::Vector2, &[source_width as f32, source_height as f32])?;
+            gl.upload_uniform_ival_name("u_first_mip", if index == 0 { 1 } else { 0 })?;
+
+            draw_fullscreen_triangle(gl);
+
+            source = mip.texture_id;
+            source_width = mip.width;
+            source_height = mip.height;
+        }
+
+        // upsample, each level is added on top of the one above it
+        gl.activate_program(programs.upsample)?;
+        gl.upload_uniform_fval_name("u_filter_radius", config.radius)?;
+        gl.toggle(GlToggle::Blend, true);
+        gl.set_blend_func(BlendFactor::One, BlendFactor::One);
+
+        for index in (1..self.mips.len()).rev() {
+            let (source, target) = (&self.mips[index], &self.mips[index - 1]);
+
+            gl.bind_framebuffer(target.fbo.id, FrameBufferTarget::DrawFrameBuffer)?;
+            gl.resize(ResizeStrategy::ViewportSize(target.width, target.height));
+            gl.activate_texture_sampler_name(source.texture_id, "u_color_sampler")?;
+
+            draw_fullscreen_triangle(gl);
+        }
+
+        gl.toggle(GlToggle::Blend, false);
+
+        // composite into whatever the chain wants next
+        match input.output {
+            Some(fbo_id) => gl.bind_framebuffer(fbo_id, FrameBufferTarget::DrawFrameBuffer)?,
+            None => gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer),
+        }
+
+        let (x, y, width, height) = viewport_before;
+        gl.resize(ResizeStrategy::Viewport(x, y, width, height));
+
+        gl.activate_program(programs.composite)?;
+        input.upload_uniforms(gl)?;
+        gl.activate_texture_sampler_name(self.mips[0].texture_id, "u_bloom_sampler")?;
+        gl.upload_uniform_fval_name("u_bloom_intensity", config.intensity)?;
+
+        draw_fullscreen_triangle(gl);
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for BloomPass {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        for mip in self.mips.iter_mut() {
+            mip.fbo.destroy(gl)?;
+        }
+        self.mips.clear();
+        self.size = (0, 0, 0);
+        Ok(())
+    }
+}
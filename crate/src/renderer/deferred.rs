@@ -0,0 +1,297 @@
+/*
+ * Deferred shading, in DrawBufferMode::Deferred
+ *
+ * Opaque and masked meshes are drawn with the g-buffer variant of the mesh shader (ShaderKey::deferred)
+ * which writes into 4 attachments at once, plus the main depth texture:
+ *
+ *   0: the main color target, with just the ambient (IBL, SSAO) and emissive light
+ *   1: albedo (rgb), specular weight (a)
+ *   2: world-space normal (rgb), shadow receiver (a)
+ *   3: occlusion, roughness, metallic, ior
+ *
+ * Then each punctual light is a fullscreen pass, scissored to the screen rect of its range
+ * and additively blended on top. So the cost is per lit pixel, instead of every light for every mesh
+ *
+ * The skybox, transmissive and blended meshes are still forward (clustered), on top of the result
+ *
+ * Clearcoat, sheen, iridescence and the KHR_materials_specular color aren't in the g-buffer
+ * (f0 is rebuilt from ior and metallic alone), so opaque and masked meshes with any of those
+ * stay on their forward program too, drawn right after the lights
+ */
+use crate::{
+    prelude::*,
+    light::{
+        Light,
+        Lights,
+        shadow::Shadows,
+        cluster::{LightArea, light_ndc_rect},
+    },
+};
+use super::{
+    draw_buffers::{DrawBufferMode, make_hdr_texture},
+    post_process::draw_fullscreen_triangle,
+    queue::{DrawItem, QueueItem},
+    shaders::ShaderKey,
+};
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    TextureTarget,
+    FrameBufferTarget,
+    FrameBufferAttachment,
+    FrameBufferTextureTarget,
+    DrawBuffer,
+    GlToggle,
+    BlendFactor,
+};
+use nalgebra::Isometry3;
+use nalgebra_glm::{Vec3, Mat4};
+
+pub struct GBuffer {
+    fbo_id: Id,
+    // just the main color, since the lighting passes sample the depth
+    lighting_fbo_id: Id,
+    albedo_id: Id,
+    normal_id: Id,
+    orm_id: Id,
+    // owned by the main fbo
+    depth_id: Id,
+}
+
+// gathered each frame, see AwsmRenderer::update_deferred()
+pub(crate) struct DeferredLighting {
+    program_id: Id,
+    lights: Vec<DeferredLight>,
+    // taken out of the opaque and mask queues, still in the same order
+    pub(crate) forward: Vec<QueueItem>,
+}
+
+struct DeferredLight {
+    // into the light data texture
+    index: u32,
+    // x, y, width, height in pixels, None is the whole screen
+    scissor: Option<(i32, i32, u32, u32)>,
+}
+
+impl GBuffer {
+    // shares the color and depth textures of the main fbo
+    pub fn new(gl: &mut WebGl2Renderer, width: u32, height: u32, color_id: Id, depth_id: Id) -> Result<Self> {
+        let fbo_id = gl.create_framebuffer()?;
+
+        let albedo_id = make_hdr_texture(gl, width, height)?;
+        let normal_id = make_hdr_texture(gl, width, height)?;
+        let orm_id = make_hdr_texture(gl, width, height)?;
+
+        gl.assign_framebuffer_texture_2d(fbo_id, color_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0, FrameBufferTextureTarget::Texture2d)?;
+        gl.assign_framebuffer_texture_2d(fbo_id, albedo_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color1, FrameBufferTextureTarget::Texture2d)?;
+        gl.assign_framebuffer_texture_2d(fbo_id, normal_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color2, FrameBufferTextureTarget::Texture2d)?;
+        gl.assign_framebuffer_texture_2d(fbo_id, orm_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color3, FrameBufferTextureTarget::Texture2d)?;
+        gl.assign_framebuffer_texture_2d(fbo_id, depth_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Depth, FrameBufferTextureTarget::Texture2d)?;
+        gl.check_framebuffer_status(FrameBufferTarget::DrawFrameBuffer)?;
+        gl.draw_buffers(&vec![DrawBuffer::Color0, DrawBuffer::Color1, DrawBuffer::Color2, DrawBuffer::Color3])?;
+
+        let lighting_fbo_id = gl.create_framebuffer()?;
+        gl.assign_framebuffer_texture_2d(lighting_fbo_id, color_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0, FrameBufferTextureTarget::Texture2d)?;
+        gl.check_framebuffer_status(FrameBufferTarget::DrawFrameBuffer)?;
+
+        gl.release_texture_target(TextureTarget::Texture2d);
+        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+        Ok(Self {
+            fbo_id,
+            lighting_fbo_id,
+            albedo_id,
+            normal_id,
+            orm_id,
+            depth_id,
+        })
+    }
+
+    // the main color and depth are already cleared, and the rest is only read where there's depth
+    pub(crate) fn bind(&self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.bind_framebuffer(self.fbo_id, FrameBufferTarget::DrawFrameBuffer)?;
+        Ok(())
+    }
+
+    // leaves the lighting fbo bound
+    pub(crate) fn render_lighting(&self, gl: &mut WebGl2Renderer, lighting: &DeferredLighting, lights: &Lights, shadows: &Shadows) -> Result<()> {
+        gl.bind_framebuffer(self.lighting_fbo_id, FrameBufferTarget::DrawFrameBuffer)?;
+
+        if lighting.lights.is_empty() {
+            return Ok(());
+        }
+
+        gl.toggle(GlToggle::DepthTest, false);
+        gl.toggle(GlToggle::CullFace, false);
+        gl.toggle(GlToggle::Blend, true);
+        gl.set_blend_func(BlendFactor::One, BlendFactor::One);
+
+        gl.activate_program(lighting.program_id)?;
+        gl.activate_texture_sampler_name(self.albedo_id, "u_gbuffer_albedo")?;
+        gl.activate_texture_sampler_name(self.normal_id, "u_gbuffer_normal")?;
+        gl.activate_texture_sampler_name(self.orm_id, "u_gbuffer_orm")?;
+        gl.activate_texture_sampler_name(self.depth_id, "u_gbuffer_depth")?;
+
+        // each pass is a single light, so the clusters aren't needed
+        lights.upload_uniforms(gl, false)?;
+        // per-pixel receiver flag is in the g-buffer
+        shadows.upload_uniforms(gl, true)?;
+
+        for light in lighting.lights.iter() {
+            match light.scissor {
+                Some((x, y, width, height)) => {
+                    gl.toggle(GlToggle::ScissorTest, true);
+                    gl.scissor(x, y, width, height);
+                },
+                None => {
+                    gl.toggle(GlToggle::ScissorTest, false);
+                }
+            }
+
+            gl.upload_uniform_ival_name("u_light_index", light.index as i32)?;
+            draw_fullscreen_triangle(gl);
+        }
+
+        gl.toggle(GlToggle::ScissorTest, false);
+        gl.toggle(GlToggle::Blend, false);
+        gl.toggle(GlToggle::DepthTest, true);
+
+        Ok(())
+    }
+}
+
+impl AwsmRenderer {
+    // in DrawBufferMode::Deferred, switches the opaque and mask queues over to the g-buffer programs
+    // (except for what the g-buffer can't hold, see is_deferrable) and gathers the lights to apply afterwards
+    // must be after update_render_queues() and update_lights()
+    pub(crate) fn update_deferred(
+        &mut self,
+        meshes: &View<Mesh>,
+        lights: &View<Light>,
+        world_transforms: &View<WorldTransform>,
+    ) -> Result<Option<DeferredLighting>> {
+        let (width, height) = match self.draw_buffers.as_ref() {
+            Some(draw_buffers) if draw_buffers.mode == DrawBufferMode::Deferred => (draw_buffers.width, draw_buffers.height),
+            _ => return Ok(None)
+        };
+
+        let items:Vec<DrawItem> = self.render_queues.opaque.iter()
+            .chain(self.render_queues.mask.iter())
+            .map(|queue_item| queue_item.item)
+            .collect();
+
+        let mut program_ids = Vec::with_capacity(items.len());
+
+        for item in items {
+            let shader_key = match item {
+                DrawItem::Entity(entity) => meshes.get(entity)?.shader_key.clone(),
+                DrawItem::Batch(index) => {
+                    let mesh = meshes.get(self.instancing.batches[index].entities[0])?;
+                    let mut shader_key = mesh.shader_key.clone();
+                    shader_key.instance_model_loc = mesh.instance_attribute_loc;
+                    shader_key
+                }
+            };
+
+            program_ids.push(if is_deferrable(&shader_key) {
+                Some(self.deferred_mesh_program(shader_key)?)
+            } else {
+                None
+            });
+        }
+
+        let mut program_ids = program_ids.into_iter();
+        let mut forward = Vec::new();
+
+        for queue in [&mut self.render_queues.opaque, &mut self.render_queues.mask] {
+            for mut queue_item in std::mem::take(queue) {
+                match program_ids.next().flatten() {
+                    Some(program_id) => {
+                        queue_item.program_id = program_id;
+                        queue.push(queue_item);
+                    },
+                    None => forward.push(queue_item)
+                }
+            }
+        }
+
+        let view_projection:Mat4 = match self.camera.get_active_dyn() {
+            Some(camera) => {
+                let projection:Mat4 = camera.projection().cast();
+                let view:Mat4 = camera.view().cast();
+                projection * view
+            },
+            None => Mat4::identity()
+        };
+
+        let mut deferred_lights = Vec::new();
+
+        // same iteration as update_lights, so the index matches the light in the shader
+        for (index, (transform, light)) in (world_transforms, lights).iter().enumerate() {
+            let scissor = match light.effective_range() {
+                Some(range) => {
+                    let transform:&Mat4 = &transform;
+                    let position:Isometry3<f32> = nalgebra::convert_unchecked(*transform);
+
+                    match light_scissor(&view_projection, &position.translation.vector, range, width, height) {
+                        LightScissor::Hidden => continue,
+                        LightScissor::Screen => None,
+                        LightScissor::Rect(x, y, width, height) => Some((x, y, width, height)),
+                    }
+                },
+                // directional
+                None => None
+            };
+
+            deferred_lights.push(DeferredLight {
+                index: index as u32,
+                scissor,
+            });
+        }
+
+        Ok(Some(DeferredLighting {
+            program_id: self.deferred_lighting_program()?,
+            lights: deferred_lights,
+            forward,
+        }))
+    }
+}
+
+// whether the g-buffer has everything the material needs
+fn is_deferrable(shader_key: &ShaderKey) -> bool {
+    !(shader_key.clearcoat || shader_key.sheen || shader_key.iridescence || shader_key.specular)
+}
+
+enum LightScissor {
+    Hidden,
+    Screen,
+    Rect(i32, i32, u32, u32),
+}
+
+// the pixel rect of the bounding box around the light's range
+fn light_scissor(view_projection: &Mat4, position: &Vec3, range: f32, width: u32, height: u32) -> LightScissor {
+    let (min, max) = match light_ndc_rect(view_projection, position, range) {
+        LightArea::Hidden => return LightScissor::Hidden,
+        LightArea::Screen => return LightScissor::Screen,
+        LightArea::Rect(min, max) => (min, max),
+    };
+
+    let to_pixels = |ndc: f32, size: u32| -> i32 {
+        (((ndc.clamp(-1.0, 1.0) * 0.5) + 0.5) * size as f32) as i32
+    };
+
+    let (x0, y0) = (to_pixels(min.0, width), to_pixels(min.1, height));
+    let (x1, y1) = (to_pixels(max.0, width) + 1, to_pixels(max.1, height) + 1);
+
+    LightScissor::Rect(x0, y0, (x1 - x0) as u32, (y1 - y0) as u32)
+}
+
+impl DestroyWithGl for GBuffer {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.delete_framebuffer(self.fbo_id)?;
+        gl.delete_framebuffer(self.lighting_fbo_id)?;
+        gl.delete_texture(self.albedo_id)?;
+        gl.delete_texture(self.normal_id)?;
+        gl.delete_texture(self.orm_id)?;
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 use crate::prelude::*;
 use super::cleanup::DestroyWithGl;
+use super::post_process::{PostProcessChain, PostProcessInput};
+use super::deferred::{GBuffer, DeferredLighting};
+use super::transmission::TransmissionBuffer;
+use crate::light::{Lights, shadow::Shadows};
 use awsm_web::webgl::{
     WebGl2Renderer,
     Id,
@@ -26,6 +30,11 @@ use awsm_web::webgl::{
     BufferUsage,
     DataType,
     VertexArray,
+    PixelDataFormat,
+    PixelInternalFormat,
+    PartialWebGlTextures,
+    TextureWrapTarget,
+    TextureWrapMode,
 };
 use shipyard::*;
 
@@ -40,6 +49,10 @@ pub struct DrawBuffers {
     pub clear_color: [f32;4],
     pub fbo_main_draw: Option<FrameBuffer>,
     pub fbo_main_multisample: Option<FrameBuffer>,
+    // only in DrawBufferMode::Deferred
+    pub gbuffer: Option<GBuffer>,
+    // only once something transmissive is drawn, see renderer/transmission.rs
+    pub transmission: Option<TransmissionBuffer>,
     pub mode: DrawBufferMode,
     pub quad: Quad,
 }
@@ -47,7 +60,11 @@ pub struct DrawBuffers {
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum DrawBufferMode {
     Regular,
-    Multisample
+    Multisample,
+    // single-sample, antialiased in post-processing (see renderer/fxaa.rs)
+    Fxaa,
+    // single-sample, opaques are lit from a g-buffer (see renderer/deferred.rs)
+    Deferred,
 }
 
 impl DestroyWithGl for DrawBuffers {
@@ -58,6 +75,12 @@ impl DestroyWithGl for DrawBuffers {
         if let Some(mut fbo) = self.fbo_main_multisample.take() {
             fbo.destroy(&mut gl)?;
         }
+        if let Some(mut gbuffer) = self.gbuffer.take() {
+            gbuffer.destroy(&mut gl)?;
+        }
+        if let Some(mut transmission) = self.transmission.take() {
+            transmission.destroy(&mut gl)?;
+        }
         Ok(())
     }
 }
@@ -77,22 +100,27 @@ impl DrawBuffers {
         let multisample = mode == DrawBufferMode::Multisample;
 
 
+        // single-sample targets are textures, so post-processing can read them directly
+        let kind = if multisample { FrameBufferIdKind::Render } else { FrameBufferIdKind::Texture };
+
         let fbo_main_draw = FrameBuffer::new(renderer)?
-            .build_depth(renderer, width, height, FrameBufferIdKind::Render, multisample)?
-            .build_color(renderer, width, height, FrameBufferIdKind::Render, multisample)?
+            .build_depth(renderer, width, height, kind, multisample)?
+            .build_color(renderer, width, height, kind, multisample)?
             .validate(renderer)?;
 
         renderer.gl.draw_buffers(&vec![DrawBuffer::Color0])?;
         fbo_main_draw.release(renderer);
 
         let fbo_main_multisample = match mode {
-            DrawBufferMode::Regular => {
+            DrawBufferMode::Regular | DrawBufferMode::Fxaa | DrawBufferMode::Deferred => {
                 None
             },
             DrawBufferMode::Multisample => {
-                // multisample blit target is just color for downsampling, no need for depth
+                // multisample blit target for downsampling
+                // depth is only blitted when post-processing needs it
                 let fbo_main_multisample = FrameBuffer::new(renderer)?
-                    .build_color(renderer, width, height, FrameBufferIdKind::Render, false)?
+                    .build_depth(renderer, width, height, FrameBufferIdKind::Texture, false)?
+                    .build_color(renderer, width, height, FrameBufferIdKind::Texture, false)?
                     .validate(renderer)?;
 
                 fbo_main_multisample.release(renderer);
@@ -100,12 +128,23 @@ impl DrawBuffers {
             }
         };
 
+        let gbuffer = match mode {
+            DrawBufferMode::Deferred => {
+                let color = fbo_main_draw.color.ok_or_else(|| anyhow!("main framebuffer has no color"))?;
+                let depth = fbo_main_draw.depth.ok_or_else(|| anyhow!("main framebuffer has no depth"))?;
+                Some(GBuffer::new(&mut renderer.gl, width, height, color.id, depth.id)?)
+            },
+            _ => None
+        };
+
         Ok(Self {
             width,
             height,
             clear_color,
             fbo_main_draw: Some(fbo_main_draw),
             fbo_main_multisample,
+            gbuffer,
+            transmission: None,
             mode,
             quad
         })
@@ -119,19 +158,46 @@ impl DrawBuffers {
             gl.clear_draw_buffer_vf32_values(Buffer::Color, 0, &self.clear_color);
         }
 
+        // the opaques go into the g-buffer instead, which shares the color and depth
+        if let Some(gbuffer) = &self.gbuffer {
+            gbuffer.bind(gl)?;
+        }
+
         Ok(())
     }
 
-    pub fn post_draw(&self, gl:&mut WebGl2Renderer) -> Result<()> {
+    // in DrawBufferMode::Deferred, between the opaques and everything else
+    // leaves the main fbo bound, for the forward passes
+    pub(crate) fn render_deferred_lighting(&self, gl:&mut WebGl2Renderer, lighting: &DeferredLighting, lights: &Lights, shadows: &Shadows) -> Result<()> {
+        if let Some(gbuffer) = &self.gbuffer {
+            gbuffer.render_lighting(gl, lighting, lights, shadows)?;
+        }
+
         if let Some(fbo) = &self.fbo_main_draw {
-            gl.bind_framebuffer(fbo.id, FrameBufferTarget::ReadFrameBuffer)?;
+            gl.bind_framebuffer(fbo.id, FrameBufferTarget::DrawFrameBuffer)?;
         }
 
-        // multisampling
-        // i.e. to downsample from the msaa into single-sample fbo
-        // and that can't be done directly into the front buffer
-        if let Some(fbo) = &self.fbo_main_multisample {
+        Ok(())
+    }
+
+    // after the opaques and skybox, before the transmission queue
+    // leaves the main fbo bound, for the forward passes
+    pub(crate) fn render_transmission_background(&self, gl:&mut WebGl2Renderer) -> Result<()> {
+        if let (Some(fbo), Some(transmission)) = (&self.fbo_main_draw, &self.transmission) {
+            transmission.copy_from(gl, fbo.id)?;
             gl.bind_framebuffer(fbo.id, FrameBufferTarget::DrawFrameBuffer)?;
+        }
+
+        Ok(())
+    }
+
+    // the main fbo is HDR, so it always goes through the post-processing chain (at least for tonemapping)
+    pub fn post_draw(&self, gl:&mut WebGl2Renderer, post_process: &mut PostProcessChain) -> Result<()> {
+        // multisampling
+        // i.e. to downsample from the msaa into single-sample textures
+        if let (Some(fbo), Some(fbo_resolve)) = (&self.fbo_main_draw, &self.fbo_main_multisample) {
+            gl.bind_framebuffer(fbo.id, FrameBufferTarget::ReadFrameBuffer)?;
+            gl.bind_framebuffer(fbo_resolve.id, FrameBufferTarget::DrawFrameBuffer)?;
             gl.blit_framebuffer(
                 0,0, self.width, self.height,
                 0,0, self.width, self.height,
@@ -139,20 +205,37 @@ impl DrawBuffers {
                 BlitFilter::Nearest
             );
 
-            gl.bind_framebuffer(fbo.id, FrameBufferTarget::ReadFrameBuffer)?;
+            // only the user passes might read depth
+            if post_process.is_active() {
+                gl.blit_framebuffer(
+                    0,0, self.width, self.height,
+                    0,0, self.width, self.height,
+                    BufferMask::DepthBufferBit, 
+                    BlitFilter::Nearest
+                );
+            }
+
+            gl.release_framebuffer(FrameBufferTarget::ReadFrameBuffer);
         }
-        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
-        //gl.set_clear_color(self.clear_color[0], self.clear_color[1],self.clear_color[2],self.clear_color[3]);
-        //gl.clear(&[BufferMask::ColorBufferBit, BufferMask::DepthBufferBit, BufferMask::StencilBufferBit]);
 
-        gl.blit_framebuffer(
-            0,0, self.width, self.height,
-            0,0, self.width, self.height,
-            BufferMask::ColorBufferBit, 
-            BlitFilter::Nearest
-        );
+        post_process.render(gl, &self.post_process_input()?)
+    }
 
-        Ok(())
+    // the single-sample result of the main pass
+    pub fn post_process_input(&self) -> Result<PostProcessInput> {
+        let fbo = self.fbo_main_multisample.as_ref()
+            .or(self.fbo_main_draw.as_ref())
+            .ok_or_else(|| anyhow!("no main framebuffer"))?;
+
+        let color = fbo.color.ok_or_else(|| anyhow!("main framebuffer has no color"))?;
+
+        Ok(PostProcessInput {
+            color: color.id,
+            depth: fbo.depth.map(|depth| depth.id),
+            width: self.width,
+            height: self.height,
+            output: None,
+        })
     }
 }
 
@@ -202,13 +285,12 @@ impl FrameBuffer {
                 
                 depth_id
             },
-            // untested...
             FrameBufferIdKind::Texture => {
                 if multisample {
                     return Err(anyhow!("todo: multisample texture not support"));
                 }
 
-                let depth_id = make_texture(gl, width, height)?;
+                let depth_id = make_depth_texture(gl, width, height)?;
                 gl.assign_framebuffer_texture_2d(self.id, depth_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Depth, FrameBufferTextureTarget::Texture2d)?;
 
                 depth_id
@@ -228,9 +310,9 @@ impl FrameBuffer {
             FrameBufferIdKind::Render => {
                 let color_id = gl.create_renderbuffer()?;
                 if multisample {
-                    gl.assign_renderbuffer_storage_multisample_max(color_id, RenderBufferFormat::Rgba8, width, height)?;
+                    gl.assign_renderbuffer_storage_multisample_max(color_id, RenderBufferFormat::Rgba16f, width, height)?;
                 } else {
-                    gl.assign_renderbuffer_storage(color_id, RenderBufferFormat::Rgba8, width, height)?;
+                    gl.assign_renderbuffer_storage(color_id, RenderBufferFormat::Rgba16f, width, height)?;
                 }
                 gl.assign_framebuffer_renderbuffer(self.id, color_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0)?;
 
@@ -240,7 +322,7 @@ impl FrameBuffer {
                 if multisample {
                     return Err(anyhow!("todo: multisample texture not support"));
                 }
-                let color_id = make_texture(gl, width, height)?; 
+                let color_id = make_hdr_texture(gl, width, height)?;
                 gl.assign_framebuffer_texture_2d(self.id, color_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0, FrameBufferTextureTarget::Texture2d)?;
 
                 color_id
@@ -297,7 +379,7 @@ impl DestroyWithGl for FrameBufferId {
     }
 }
 
-fn make_texture(gl:&mut WebGl2Renderer, width: u32, height: u32) -> Result<Id> {
+pub(crate) fn make_texture(gl:&mut WebGl2Renderer, width: u32, height: u32) -> Result<Id> {
     let id = gl.create_texture()?;
 
     gl.assign_simple_texture(
@@ -316,6 +398,51 @@ fn make_texture(gl:&mut WebGl2Renderer, width: u32, height: u32) -> Result<Id> {
     Ok(id)
 }
 
+// awsm doesn't have sized depth formats for textures (only renderbuffers)
+// so let it register the texture as usual, then re-specify the storage directly
+pub(crate) fn make_depth_texture(gl:&mut WebGl2Renderer, width: u32, height: u32) -> Result<Id> {
+    let id = make_texture(gl, width, height)?;
+
+    let texture = gl.get_texture(id)?;
+    gl.gl.awsm_bind_texture(TextureTarget::Texture2d, texture);
+    gl.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+        TextureTarget::Texture2d as u32,
+        0,
+        RenderBufferFormat::DepthComponent32f as i32,
+        width as i32,
+        height as i32,
+        0,
+        PixelDataFormat::DepthComponent as u32,
+        DataType::Float as u32,
+        None
+    ).map_err(|err| anyhow!("{:?}", err))?;
+    gl.gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::S, TextureWrapMode::ClampToEdge);
+    gl.gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::T, TextureWrapMode::ClampToEdge);
+
+    Ok(id)
+}
+
+// half float color, same format as the main color renderbuffer so they can be blitted between
+pub(crate) fn make_hdr_texture(gl:&mut WebGl2Renderer, width: u32, height: u32) -> Result<Id> {
+    let id = make_texture(gl, width, height)?;
+
+    let texture = gl.get_texture(id)?;
+    gl.gl.awsm_bind_texture(TextureTarget::Texture2d, texture);
+    gl.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+        TextureTarget::Texture2d as u32,
+        0,
+        PixelInternalFormat::Rgba16f as i32,
+        width as i32,
+        height as i32,
+        0,
+        PixelDataFormat::Rgba as u32,
+        DataType::HalfFloat as u32,
+        None
+    ).map_err(|err| anyhow!("{:?}", err))?;
+
+    Ok(id)
+}
+
 // not used right now... but might be for post-effects like bloom...
 
 pub struct Quad {
@@ -0,0 +1,45 @@
+/*
+ * FXAA
+ *
+ * The cheap alternative to MSAA, selected via DrawBufferMode::Fxaa
+ * the scene renders into single-sample textures and this runs as the very last post-processing pass
+ *
+ * SMAA would look better, but needs precomputed area/search textures, so it's not here (yet)
+ */
+use crate::prelude::*;
+use super::post_process::{PostProcessPass, PostProcessInput, draw_fullscreen_triangle};
+use awsm_web::webgl::WebGl2Renderer;
+
+pub(crate) struct FxaaPass {
+    // set every frame, see AwsmRenderer::update_post_process
+    pub enabled: bool,
+    pub program_id: Option<Id>,
+}
+
+impl FxaaPass {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            program_id: None,
+        }
+    }
+}
+
+impl PostProcessPass for FxaaPass {
+    fn render(&mut self, gl: &mut WebGl2Renderer, input: &PostProcessInput) -> Result<()> {
+        let program_id = self.program_id.ok_or_else(|| anyhow!("fxaa program isn't set"))?;
+
+        gl.activate_program(program_id)?;
+        input.upload_uniforms(gl)?;
+
+        draw_fullscreen_triangle(gl);
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for FxaaPass {
+    fn destroy(&mut self, _gl: &mut WebGl2Renderer) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * Hardware instancing
+ *
+ * Meshes that share the same vao, program, and material get drawn with a single instanced call
+ * where the model matrix comes from a per-instance attribute instead of u_model
+ *
+ * Nothing needs to opt-in, batches are rebuilt every frame from whatever is in the world
+ * (that's also how EXT_mesh_gpu_instancing works, see gltf/instancing.rs)
+ *
+ * Skinned and morphed meshes are never batched, since they need per-entity uniforms
+ * and neither are blended meshes, since they need to be sorted individually
+ */
+use crate::prelude::*;
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    AttributeOptions,
+    BufferData,
+    BufferTarget,
+    BufferUsage,
+    DataType,
+};
+use nalgebra_glm::Mat4;
+use rustc_hash::{FxHashMap, FxHashSet};
+use crate::{
+    light::shadow::ShadowReceiver,
+    bounds::{Frustum, WorldBounds, is_culled},
+    renderer::shaders::ShaderKeyAlphaMode,
+};
+
+// guaranteed minimum in WebGl2
+pub(crate) const MAX_VERTEX_ATTRIBUTES:u32 = 16;
+
+pub struct Instancing {
+    pub(crate) buffer: InstanceBuffer,
+    pub(crate) batches: Vec<InstanceBatch>,
+    // skipped in the regular, one-by-one, draw
+    batched: FxHashSet<EntityId>,
+}
+
+// one dynamic buffer, re-filled for each batch
+pub(crate) struct InstanceBuffer {
+    buffer_id: Option<Id>,
+    data: Vec<f32>,
+}
+
+pub(crate) struct InstanceBatch {
+    // the instanced variant, not the mesh's own program
+    pub program_id: Id,
+    pub vao_id: Id,
+    pub shadow_receiver: bool,
+    pub entities: Vec<EntityId>,
+    // the program the batch was matched on
+    mesh_program_id: Id,
+}
+
+impl Instancing {
+    pub fn new() -> Self {
+        Self {
+            buffer: InstanceBuffer {
+                buffer_id: None,
+                data: Vec::new(),
+            },
+            batches: Vec::new(),
+            batched: FxHashSet::default(),
+        }
+    }
+
+    pub(crate) fn is_batched(&self, entity: EntityId) -> bool {
+        self.batched.contains(&entity)
+    }
+}
+
+impl InstanceBuffer {
+    // expects the batch's program and vao to already be active
+    pub(crate) fn upload(&mut self, gl:&mut WebGl2Renderer, mesh: &Mesh, entities: &[EntityId], world_transforms: &View<WorldTransform>) -> Result<()> {
+        let loc = mesh.instance_attribute_loc.ok_or_else(|| anyhow!("mesh can't be instanced"))?;
+
+        let buffer_id = match self.buffer_id {
+            Some(buffer_id) => buffer_id,
+            None => {
+                let buffer_id = gl.create_buffer()?;
+                self.buffer_id = Some(buffer_id);
+                buffer_id
+            }
+        };
+
+        self.data.clear();
+        for entity in entities {
+            let world_transform:&Mat4 = &*world_transforms.get(*entity)?;
+            self.data.extend_from_slice(world_transform.as_slice());
+        }
+
+        gl.upload_buffer(
+            buffer_id,
+            BufferData::new(
+                &self.data,
+                BufferTarget::ArrayBuffer,
+                BufferUsage::DynamicDraw,
+            )
+        )?;
+
+        // the buffer is still bound, so this points the active vao at it
+        // one vec4 column per location
+        for column in 0..4 {
+            gl.activate_attribute_loc(loc + column, &AttributeOptions {
+                size: 4,
+                data_type: DataType::Float,
+                normalized: false,
+                stride: 64,
+                offset: (column * 16) as u64,
+                is_int_array: false,
+            });
+            gl.vertex_attrib_divisor(loc + column, 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl AwsmRenderer {
+    // must be called before drawing, and after the mesh programs are settled
+    pub(crate) fn update_instance_batches(
+        &mut self,
+        meshes: &View<Mesh>,
+        materials: &View<Material>,
+        world_transforms: &View<WorldTransform>,
+        mesh_morph_weights: &View<MeshMorphWeights>,
+        shadow_receivers: &View<ShadowReceiver>,
+        frustum: Option<&Frustum>,
+        world_bounds: &View<WorldBounds>,
+    ) -> Result<()> {
+        let mut batches = std::mem::take(&mut self.instancing.batches);
+        batches.clear();
+
+        // Id isn't hashable, so batches are found by (primitive, shadow receiver) instead
+        // and the few in that bucket are checked for the rest (just one, unless a copy's material was changed)
+        // meshes without a primitive all land in the same bucket
+        let mut lookup:FxHashMap<(Option<EntityId>, bool), Vec<usize>> = FxHashMap::default();
+
+        for (entity, (mesh, material, _)) in (meshes, materials, world_transforms).iter().with_id() {
+            if mesh.instance_attribute_loc.is_none() || !mesh.skin_joints.is_empty() || mesh_morph_weights.contains(entity) {
+                continue;
+            }
+
+            // blended meshes are sorted one by one, see renderer/queue.rs
+            if mesh.shader_key.alpha_mode == ShaderKeyAlphaMode::Blend {
+                continue;
+            }
+
+            if is_culled(frustum, entity, mesh, world_bounds) {
+                continue;
+            }
+
+            let shadow_receiver = shadow_receivers.contains(entity);
+
+            let is_match = |batch:&InstanceBatch| {
+                batch.vao_id == mesh.vao_id
+                    && batch.mesh_program_id == mesh.program_id
+                    && batch.shadow_receiver == shadow_receiver
+                    && materials.get(batch.entities[0]).map(|other| other == material).unwrap_or(false)
+            };
+
+            let bucket = lookup.entry((mesh.primitive, shadow_receiver)).or_default();
+
+            match bucket.iter().copied().find(|index| is_match(&batches[*index])) {
+                Some(index) => {
+                    batches[index].entities.push(entity);
+                },
+                None => {
+                    bucket.push(batches.len());
+                    batches.push(InstanceBatch {
+                        // filled in below, only for batches that are actually used
+                        program_id: mesh.program_id,
+                        vao_id: mesh.vao_id,
+                        shadow_receiver,
+                        entities: vec![entity],
+                        mesh_program_id: mesh.program_id,
+                    });
+                }
+            }
+        }
+
+        // a batch of one is just a regular draw
+        batches.retain(|batch| batch.entities.len() > 1);
+
+        self.instancing.batched.clear();
+
+        for batch in batches.iter_mut() {
+            let mesh = meshes.get(batch.entities[0])?;
+            let mut shader_key = mesh.shader_key.clone();
+            shader_key.instance_model_loc = mesh.instance_attribute_loc;
+            batch.program_id = self.mesh_program(shader_key)?;
+
+            self.instancing.batched.extend(batch.entities.iter().copied());
+        }
+
+        self.instancing.batches = batches;
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for Instancing {
+    fn destroy(&mut self, gl:&mut WebGl2Renderer) -> Result<()> {
+        if let Some(buffer_id) = self.buffer.buffer_id.take() {
+            gl.delete_buffer(buffer_id)?;
+        }
+        Ok(())
+    }
+}
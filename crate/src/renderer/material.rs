@@ -7,7 +7,7 @@ pub use pbr::*;
 mod texture;
 pub use texture::*;
 
-#[derive(Component, Clone, Debug)]
+#[derive(Component, Clone, Debug, PartialEq)]
 pub enum Material {
     Pbr(PbrMaterial)
 }
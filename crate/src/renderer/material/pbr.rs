@@ -2,7 +2,7 @@ use nalgebra::{Vector4, Vector3};
 use crate::{prelude::*, renderer::shaders::{ShaderKey, ShaderKeyAlphaMode}};
 use super::texture::TextureInfo;
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct PbrMaterial {
     pub base_color_factor: Vector4<f32>,
     pub metallic_factor: f32,
@@ -13,11 +13,122 @@ pub struct PbrMaterial {
     pub emissive_texture: Option<TextureInfo>, 
     pub normal_texture: Option<TextureInfo>, 
     pub normal_texture_scale: Option<f32>, 
+    pub occlusion_texture: Option<TextureInfo>, 
+    pub occlusion_strength: Option<f32>, 
+    pub clearcoat: Option<PbrClearcoat>,
+    pub sheen: Option<PbrSheen>,
+    pub specular: Option<PbrSpecular>,
+    pub transmission: Option<PbrTransmission>,
+    // only has an effect along with transmission
+    pub volume: Option<PbrVolume>,
+    pub iridescence: Option<PbrIridescence>,
+    // KHR_materials_ior, the shader defaults to 1.5
+    pub ior: Option<f32>,
     pub alpha_mode: Option<AlphaMode>,
     pub double_sided: bool,
 }
 
-#[derive(Clone, Debug, Copy)]
+// KHR_materials_clearcoat
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PbrClearcoat {
+    pub factor: f32,
+    pub texture: Option<TextureInfo>,
+    pub roughness_factor: f32,
+    pub roughness_texture: Option<TextureInfo>,
+    pub normal_texture: Option<TextureInfo>,
+    pub normal_texture_scale: Option<f32>,
+}
+
+// KHR_materials_sheen
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PbrSheen {
+    pub color_factor: Vector3<f32>,
+    pub color_texture: Option<TextureInfo>,
+    pub roughness_factor: f32,
+    pub roughness_texture: Option<TextureInfo>,
+}
+
+// KHR_materials_specular
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrSpecular {
+    pub factor: f32,
+    // alpha channel
+    pub texture: Option<TextureInfo>,
+    pub color_factor: Vector3<f32>,
+    // rgb channels
+    pub color_texture: Option<TextureInfo>,
+}
+
+impl Default for PbrSpecular {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            texture: None,
+            color_factor: Vector3::new(1.0, 1.0, 1.0),
+            color_texture: None,
+        }
+    }
+}
+
+// KHR_materials_transmission
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PbrTransmission {
+    pub factor: f32,
+    // red channel
+    pub texture: Option<TextureInfo>,
+}
+
+// KHR_materials_volume
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrVolume {
+    // in the mesh's local space, 0.0 is thin-walled
+    pub thickness_factor: f32,
+    // green channel
+    pub thickness_texture: Option<TextureInfo>,
+    // in world space, infinity means no attenuation
+    pub attenuation_distance: f32,
+    pub attenuation_color: Vector3<f32>,
+}
+
+impl Default for PbrVolume {
+    fn default() -> Self {
+        Self {
+            thickness_factor: 0.0,
+            thickness_texture: None,
+            attenuation_distance: f32::INFINITY,
+            attenuation_color: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// KHR_materials_iridescence
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrIridescence {
+    pub factor: f32,
+    // red channel
+    pub texture: Option<TextureInfo>,
+    pub ior: f32,
+    // in nanometers
+    pub thickness_minimum: f32,
+    pub thickness_maximum: f32,
+    // green channel, mixes between the minimum and maximum
+    pub thickness_texture: Option<TextureInfo>,
+}
+
+impl Default for PbrIridescence {
+    fn default() -> Self {
+        Self {
+            factor: 0.0,
+            texture: None,
+            ior: 1.3,
+            thickness_minimum: 100.0,
+            thickness_maximum: 400.0,
+            thickness_texture: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
 pub enum AlphaMode {
     Opaque,
     Blend,
@@ -35,18 +146,105 @@ impl PbrMaterial {
         }
         if let Some(tex) = self.base_color_texture.as_ref() {
             shader_key.base_color_texture_uv_index = Some(tex.uv_index);
+            shader_key.base_color_uv_transform = tex.transform.is_some();
         }
 
         if let Some(tex) = self.metallic_roughness_texture.as_ref() {
             shader_key.metallic_roughness_texture_uv_index = Some(tex.uv_index);
+            shader_key.metallic_roughness_uv_transform = tex.transform.is_some();
         }
 
         if let Some(tex) = self.emissive_texture.as_ref() {
             shader_key.emissive_texture_uv_index = Some(tex.uv_index);
+            shader_key.emissive_uv_transform = tex.transform.is_some();
         }
 
         if let Some(tex) = self.normal_texture.as_ref() {
             shader_key.normal_texture_uv_index = Some(tex.uv_index);
+            shader_key.normal_uv_transform = tex.transform.is_some();
+        }
+
+        if let Some(tex) = self.occlusion_texture.as_ref() {
+            shader_key.occlusion_texture_uv_index = Some(tex.uv_index);
+            shader_key.occlusion_uv_transform = tex.transform.is_some();
+        }
+
+        if let Some(clearcoat) = self.clearcoat.as_ref() {
+            shader_key.clearcoat = true;
+
+            if let Some(tex) = clearcoat.texture.as_ref() {
+                shader_key.clearcoat_texture_uv_index = Some(tex.uv_index);
+                shader_key.clearcoat_uv_transform = tex.transform.is_some();
+            }
+            if let Some(tex) = clearcoat.roughness_texture.as_ref() {
+                shader_key.clearcoat_roughness_texture_uv_index = Some(tex.uv_index);
+                shader_key.clearcoat_roughness_uv_transform = tex.transform.is_some();
+            }
+            if let Some(tex) = clearcoat.normal_texture.as_ref() {
+                shader_key.clearcoat_normal_texture_uv_index = Some(tex.uv_index);
+                shader_key.clearcoat_normal_uv_transform = tex.transform.is_some();
+            }
+        }
+
+        if let Some(sheen) = self.sheen.as_ref() {
+            shader_key.sheen = true;
+
+            if let Some(tex) = sheen.color_texture.as_ref() {
+                shader_key.sheen_color_texture_uv_index = Some(tex.uv_index);
+                shader_key.sheen_color_uv_transform = tex.transform.is_some();
+            }
+            if let Some(tex) = sheen.roughness_texture.as_ref() {
+                shader_key.sheen_roughness_texture_uv_index = Some(tex.uv_index);
+                shader_key.sheen_roughness_uv_transform = tex.transform.is_some();
+            }
+        }
+
+        if let Some(specular) = self.specular.as_ref() {
+            shader_key.specular = true;
+
+            if let Some(tex) = specular.texture.as_ref() {
+                shader_key.specular_texture_uv_index = Some(tex.uv_index);
+                shader_key.specular_uv_transform = tex.transform.is_some();
+            }
+            if let Some(tex) = specular.color_texture.as_ref() {
+                shader_key.specular_color_texture_uv_index = Some(tex.uv_index);
+                shader_key.specular_color_uv_transform = tex.transform.is_some();
+            }
+        }
+
+        if let Some(transmission) = self.transmission.as_ref() {
+            shader_key.transmission = true;
+
+            if let Some(tex) = transmission.texture.as_ref() {
+                shader_key.transmission_texture_uv_index = Some(tex.uv_index);
+                shader_key.transmission_uv_transform = tex.transform.is_some();
+            }
+
+            if let Some(volume) = self.volume.as_ref() {
+                shader_key.volume = true;
+
+                if let Some(tex) = volume.thickness_texture.as_ref() {
+                    shader_key.volume_thickness_texture_uv_index = Some(tex.uv_index);
+                    shader_key.volume_thickness_uv_transform = tex.transform.is_some();
+                }
+            }
+        }
+
+        if let Some(iridescence) = self.iridescence.as_ref() {
+            shader_key.iridescence = true;
+
+            if let Some(tex) = iridescence.texture.as_ref() {
+                shader_key.iridescence_texture_uv_index = Some(tex.uv_index);
+                shader_key.iridescence_uv_transform = tex.transform.is_some();
+            }
+            if let Some(tex) = iridescence.thickness_texture.as_ref() {
+                shader_key.iridescence_thickness_texture_uv_index = Some(tex.uv_index);
+                shader_key.iridescence_thickness_uv_transform = tex.transform.is_some();
+            }
+        }
+
+        if self.ior.is_some() {
+            shader_key.ior = true;
         }
     }
 }
@@ -1,8 +1,42 @@
 use crate::prelude::*;
+use nalgebra::{Matrix3, Vector2};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TextureInfo {
     pub id: Id,
-    pub uv_index: u32
+    pub uv_index: u32,
+    pub transform: Option<TextureTransform>,
 }
 
+// KHR_texture_transform
+// the texCoord override is already applied to the uv_index on import
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureTransform {
+    pub offset: Vector2<f32>,
+    // radians, counter-clockwise
+    pub rotation: f32,
+    pub scale: Vector2<f32>,
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self {
+            offset: Vector2::zeros(),
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+        }
+    }
+}
+
+impl TextureTransform {
+    // translation * rotation * scale, multiplied with vec3(uv, 1.0) in the shader
+    pub fn matrix(&self) -> Matrix3<f32> {
+        let (s, c) = self.rotation.sin_cos();
+
+        Matrix3::new(
+            c * self.scale.x, s * self.scale.y, self.offset.x,
+            -s * self.scale.x, c * self.scale.y, self.offset.y,
+            0.0, 0.0, 1.0,
+        )
+    }
+}
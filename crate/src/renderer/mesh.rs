@@ -1,7 +1,8 @@
 use awsm_web::webgl::{WebGl2Renderer, BeginMode, DataType};
 use crate::prelude::*;
 use super::{cleanup::DestroyWithGl, shaders::ShaderKey};
-use nalgebra_glm::Mat4;
+use nalgebra_glm::{Mat4, Vec2, Vec3};
+use std::sync::Arc;
 
 
 #[derive(Component, Clone, Debug)]
@@ -11,7 +12,42 @@ pub struct Mesh {
     pub shader_key: ShaderKey,
     pub program_id: Id,
     pub draw_strategy: DrawStrategy,
-    pub skin_joints: Vec<EntityId>
+    pub skin_joints: Vec<EntityId>,
+    // one per joint, these belong to the skin and not the joint (a joint can be shared by skins)
+    pub skin_inverse_bind_mats: Vec<Mat4>,
+    // where the per-instance model matrix goes, if this mesh can be instanced at all
+    // see renderer/instancing.rs
+    pub instance_attribute_loc: Option<u32>,
+    // the entity this mesh was first created on, shared by all its copies (i.e. the same vao)
+    // so instances can be grouped without comparing everything, see renderer/instancing.rs
+    pub primitive: Option<EntityId>,
+}
+
+impl Mesh {
+    // assumes the program, vao, and uniforms are already set
+    pub(crate) fn draw(&self, gl:&mut WebGl2Renderer) {
+        match self.draw_strategy {
+            DrawStrategy::Arrays { mode, first, count } => {
+                gl.draw_arrays(mode, first, count);
+            },
+            DrawStrategy::Elements { mode, count, data_type, offset} => {
+                gl.draw_elements(mode, count, data_type, offset);
+            }
+        }
+    }
+
+    // same as draw() but the instance attributes must also be set
+    pub(crate) fn draw_instanced(&self, gl:&mut WebGl2Renderer, instance_count: u32) -> Result<()> {
+        match self.draw_strategy {
+            DrawStrategy::Arrays { mode, first, count } => {
+                gl.draw_arrays_instanced(mode, first, count, instance_count)?;
+            },
+            DrawStrategy::Elements { mode, count, data_type, offset} => {
+                gl.draw_elements_instanced(mode, count, data_type, offset, instance_count)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl DestroyWithGl for Mesh {
@@ -33,11 +69,35 @@ impl DestroyWithGl for Mesh {
 #[derive(Component, Clone, Debug)]
 pub struct MeshMorphWeights(pub Vec<f32>);
 
+// CPU-side copy of the geometry, for raycasting
+// only kept from gltf if Config::retain_mesh_geometry is set, but can be added manually too
+// shared by every copy of the same primitive, so cloning is cheap
+#[derive(Component, Clone, Debug, Default)]
+pub struct MeshGeometry(pub Arc<MeshGeometryData>);
+
+impl MeshGeometry {
+    pub fn new(data: MeshGeometryData) -> Self {
+        Self(Arc::new(data))
+    }
+}
+
+// local space and rest pose, i.e. no morphs or skinning
+#[derive(Clone, Debug, Default)]
+pub struct MeshGeometryData {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    // TEXCOORD_0
+    pub uvs: Option<Vec<Vec2>>,
+    // strips and fans are unrolled, points and lines are left out
+    pub triangles: Vec<[u32;3]>,
+}
+
 #[derive(Component, Clone, Debug)]
 #[track(Modification)]
 pub struct MeshSkinJoint {
-    pub inverse_bind_mat: Mat4,
-    pub world_transform: Mat4,
+    // joint world transform, the inverse bind matrix is applied per skin
+    // see Mesh::skin_inverse_bind_mats and upload_mesh_vertex_uniforms
+    pub joint_mat: Mat4,
 }
 
 #[derive(Component, Clone, Debug)]
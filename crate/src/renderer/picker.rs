@@ -0,0 +1,195 @@
+/*
+ * GPU picking
+ *
+ * On demand, every mesh is drawn into an R32UI target using the regular mesh vertex shader
+ * (so skinning and morphs line up exactly with what's on screen)
+ * and then the one pixel under the cursor is read back
+ *
+ * The value written is index+1 into the list of entities drawn for that pick, 0 is nothing
+ * so EntityId doesn't need to fit in 32 bits
+ *
+ * Masked meshes discard below their alpha cutoff, same as when drawn, so their holes aren't pickable
+ */
+use crate::{
+    prelude::*,
+    bounds::{WorldBounds, is_culled},
+};
+use super::{
+    draw_buffers::{FrameBuffer, FrameBufferId, FrameBufferIdKind},
+    systems::{upload_mesh_vertex_uniforms, upload_alpha_mask_uniforms},
+};
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    RenderBufferFormat,
+    FrameBufferTarget,
+    FrameBufferAttachment,
+    GlToggle,
+    CmpFunction,
+    ReadPixelFormat,
+    ReadPixelDataType,
+};
+use js_sys::{Object, Uint32Array};
+
+pub(crate) struct ScenePicker {
+    fbo: FrameBuffer,
+    width: u32,
+    height: u32,
+    entities: Vec<EntityId>,
+}
+
+impl ScenePicker {
+    fn new(gl: &mut WebGl2Renderer, width: u32, height: u32) -> Result<Self> {
+        let fbo_id = gl.create_framebuffer()?;
+
+        let color_id = gl.create_renderbuffer()?;
+        gl.assign_renderbuffer_storage(color_id, RenderBufferFormat::R32ui, width, height)?;
+        gl.assign_framebuffer_renderbuffer(fbo_id, color_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0)?;
+
+        let depth_id = gl.create_renderbuffer()?;
+        gl.assign_renderbuffer_storage(depth_id, RenderBufferFormat::DepthComponent32f, width, height)?;
+        gl.assign_framebuffer_renderbuffer(fbo_id, depth_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Depth)?;
+
+        gl.check_framebuffer_status(FrameBufferTarget::DrawFrameBuffer)?;
+
+        gl.release_renderbuffer();
+        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+        Ok(Self {
+            fbo: FrameBuffer {
+                id: fbo_id,
+                depth: Some(FrameBufferId { kind: FrameBufferIdKind::Render, multisample: false, id: depth_id }),
+                color: Some(FrameBufferId { kind: FrameBufferIdKind::Render, multisample: false, id: color_id }),
+            },
+            width,
+            height,
+            entities: Vec::new(),
+        })
+    }
+}
+
+impl AwsmRenderer {
+    // x and y are in drawing buffer pixels, from the top-left (i.e. like mouse coordinates)
+    pub fn pick(&mut self, world: &World, x: u32, y: u32) -> Result<Option<EntityId>> {
+        let (_, _, width, height) = self.gl.get_viewport();
+
+        if x >= width || y >= height {
+            return Ok(None);
+        }
+
+        // the shadow passes clobber the camera ubo, so make sure it's the real camera
+        if !self.update_camera_ubo()? {
+            return Ok(None);
+        }
+
+        let mut picker = match self.picker.take() {
+            Some(picker) if picker.width == width && picker.height == height => picker,
+            picker => {
+                if let Some(mut picker) = picker {
+                    picker.destroy(&mut self.gl)?;
+                }
+                ScenePicker::new(&mut self.gl, width, height)?
+            }
+        };
+
+        let result = self.render_picker(world, &mut picker, x, height - 1 - y);
+        self.picker = Some(picker);
+
+        let id = result?;
+
+        Ok(match id {
+            0 => None,
+            id => self.picker.as_ref().and_then(|picker| picker.entities.get(id as usize - 1).copied())
+        })
+    }
+
+    // y is in gl coordinates here, i.e. from the bottom
+    fn render_picker(&mut self, world: &World, picker: &mut ScenePicker, x: u32, y: u32) -> Result<u32> {
+        let (meshes, materials, mesh_morph_weights, mesh_skin_joints, world_transforms, world_bounds) = world.borrow::<(
+            View<Mesh>,
+            View<Material>,
+            View<MeshMorphWeights>,
+            View<MeshSkinJoint>,
+            View<WorldTransform>,
+            View<WorldBounds>,
+        )>()?;
+
+        let frustum = self.camera_frustum();
+        let mut mat4_buf:[f32;16] = [0.0;16];
+        let mut skin_buf:Vec<f32> = Vec::new();
+
+        {
+            let gl = &mut self.gl;
+            gl.bind_framebuffer(picker.fbo.id, FrameBufferTarget::DrawFrameBuffer)?;
+            gl.reset_color_draw_buffer_vu32(0);
+            gl.reset_depth_stencil_draw_buffer();
+
+            gl.set_depth_mask(true);
+            gl.toggle(GlToggle::Blend, false);
+            gl.toggle(GlToggle::DepthTest, true);
+            gl.set_depth_func(CmpFunction::Less);
+
+            // only the pixel under the cursor matters
+            gl.toggle(GlToggle::ScissorTest, true);
+            gl.scissor(x as i32, y as i32, 1, 1);
+        }
+
+        picker.entities.clear();
+
+        for (entity, (mesh, world_transform)) in (&meshes, &world_transforms).iter().with_id() {
+            if is_culled(frustum.as_ref(), entity, mesh, &world_bounds) {
+                continue;
+            }
+
+            let program_id = self.picking_program(&mesh.shader_key)?;
+            let gl = &mut self.gl;
+
+            gl.activate_program(program_id)?;
+            gl.activate_vertex_array(mesh.vao_id)?;
+            upload_mesh_vertex_uniforms(gl, entity, mesh, world_transform, &mesh_morph_weights, &mesh_skin_joints, &self.skin_texture, &mut mat4_buf, &mut skin_buf)?;
+
+            picker.entities.push(entity);
+            gl.upload_uniform_uval_name("u_pick_id", picker.entities.len() as u32)?;
+
+            // back faces should be pickable exactly when they're visible
+            if let Ok(Material::Pbr(pbr)) = materials.get(entity) {
+                gl.toggle(GlToggle::CullFace, !pbr.double_sided);
+            }
+
+            if mesh.shader_key.alpha_mask_key().is_some() {
+                if let Ok(material) = materials.get(entity) {
+                    upload_alpha_mask_uniforms(gl, material)?;
+                }
+            }
+
+            mesh.draw(gl);
+        }
+
+        let gl = &mut self.gl;
+        gl.toggle(GlToggle::ScissorTest, false);
+
+        // RGBA_INTEGER/UNSIGNED_INT is the one combination always allowed for unsigned integer targets
+        gl.bind_framebuffer(picker.fbo.id, FrameBufferTarget::ReadFrameBuffer)?;
+        let data = Uint32Array::new_with_length(4);
+        let data_object:&Object = &data;
+        gl.gl.read_pixels_with_opt_array_buffer_view(
+            x as i32,
+            y as i32,
+            1,
+            1,
+            ReadPixelFormat::RgbaInteger as u32,
+            ReadPixelDataType::UnsignedInt as u32,
+            Some(data_object)
+        ).map_err(|err| anyhow!("{:?}", err))?;
+
+        gl.release_framebuffer(FrameBufferTarget::ReadFrameBuffer);
+        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+        Ok(data.get_index(0))
+    }
+}
+
+impl DestroyWithGl for ScenePicker {
+    fn destroy(&mut self, gl:&mut WebGl2Renderer) -> Result<()> {
+        self.fbo.destroy(gl)
+    }
+}
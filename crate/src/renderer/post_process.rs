@@ -0,0 +1,353 @@
+/*
+ * Post-processing
+ *
+ * A chain of fullscreen passes that runs between the main fbo and the screen
+ * each enabled pass reads the previous one's color (and the scene depth) and the last one draws to the screen
+ * in between, the passes ping-pong between two RGBA16F textures which follow the drawing buffer size
+ *
+ * The user passes run first, on the linear HDR color, then bloom (if enabled, see renderer/bloom.rs)
+ * and then tonemapping, which always runs (see renderer/tonemap.rs)
+ * and finally FXAA, in DrawBufferMode::Fxaa (see renderer/fxaa.rs)
+ *
+ * Most passes are just a fragment shader against the fullscreen triangle (see FragmentPass)
+ * which gets these inputs, if it declares them:
+ *
+ *   in vec2 tex_coord;
+ *   uniform sampler2D u_color_sampler;
+ *   uniform sampler2D u_depth_sampler;
+ *   uniform vec2 u_resolution;
+ *
+ * Anything fancier (e.g. with its own intermediate targets) can implement PostProcessPass directly
+ */
+use crate::prelude::*;
+use super::{
+    draw_buffers::{FrameBuffer, FrameBufferId, FrameBufferIdKind, DrawBufferMode, make_hdr_texture},
+    tonemap::TonemapPass,
+    bloom::BloomPass,
+    fxaa::FxaaPass,
+};
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    ShaderType,
+    TextureTarget,
+    FrameBufferTarget,
+    FrameBufferAttachment,
+    FrameBufferTextureTarget,
+    GlToggle,
+    BeginMode,
+    UniformType,
+    PartialWebGlTextures,
+    TextureMinFilter,
+    TextureMagFilter,
+};
+
+pub trait PostProcessPass: DestroyWithGl {
+    // the output target is already bound, with the viewport covering all of it
+    fn render(&mut self, gl: &mut WebGl2Renderer, input: &PostProcessInput) -> Result<()>;
+}
+
+// texture ids, all the same size as the drawing buffer
+#[derive(Clone, Debug)]
+pub struct PostProcessInput {
+    pub color: Id,
+    pub depth: Option<Id>,
+    pub width: u32,
+    pub height: u32,
+    // the framebuffer the pass is drawing into (None is the screen)
+    // only needed by passes that bind their own targets along the way
+    pub output: Option<Id>,
+}
+
+impl PostProcessInput {
+    // expects the pass's program to already be active
+    // uniforms the shader doesn't declare are skipped
+    pub fn upload_uniforms(&self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.activate_texture_sampler_name(self.color, "u_color_sampler")?;
+
+        if let Some(depth) = self.depth {
+            let _ = gl.activate_texture_sampler_name(depth, "u_depth_sampler");
+        }
+
+        let _ = gl.upload_uniform_fvec_name("u_resolution", UniformType::Vector2, &[self.width as f32, self.height as f32]);
+
+        Ok(())
+    }
+}
+
+pub struct PostProcessEntry {
+    pub name: String,
+    pub enabled: bool,
+    pub pass: Box<dyn PostProcessPass>,
+}
+
+pub struct PostProcessChain {
+    pub passes: Vec<PostProcessEntry>,
+    pub(crate) bloom: BloomPass,
+    pub(crate) tonemap: TonemapPass,
+    pub(crate) fxaa: FxaaPass,
+    targets: Option<PingPongTargets>,
+}
+
+struct PingPongTargets {
+    fbos: [FrameBuffer; 2],
+    width: u32,
+    height: u32,
+}
+
+impl PostProcessChain {
+    pub fn new() -> Self {
+        Self {
+            passes: Vec::new(),
+            bloom: BloomPass::new(),
+            tonemap: TonemapPass::new(),
+            fxaa: FxaaPass::new(),
+            targets: None,
+        }
+    }
+
+    // passes run in the order they're pushed
+    pub fn push(&mut self, name: impl Into<String>, pass: impl PostProcessPass + 'static) {
+        self.insert(self.passes.len(), name, pass);
+    }
+
+    pub fn insert(&mut self, index: usize, name: impl Into<String>, pass: impl PostProcessPass + 'static) {
+        self.passes.insert(index, PostProcessEntry {
+            name: name.into(),
+            enabled: true,
+            pass: Box::new(pass),
+        });
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut PostProcessEntry> {
+        self.passes.iter_mut().find(|entry| entry.name == name)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let entry = self.get_mut(name).ok_or_else(|| anyhow!("no post-process pass named {}", name))?;
+        entry.enabled = enabled;
+        Ok(())
+    }
+
+    // returns false if there was no such pass
+    pub fn remove(&mut self, gl: &mut WebGl2Renderer, name: &str) -> Result<bool> {
+        match self.passes.iter().position(|entry| entry.name == name) {
+            Some(index) => {
+                let mut entry = self.passes.remove(index);
+                entry.pass.destroy(gl)?;
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    // whether any user passes are enabled (tonemapping always is)
+    pub fn is_active(&self) -> bool {
+        self.passes.iter().any(|entry| entry.enabled)
+    }
+
+    // expects the scene to be resolved into the input textures
+    // and leaves the final result in the default framebuffer
+    pub(crate) fn render(&mut self, gl: &mut WebGl2Renderer, scene: &PostProcessInput) -> Result<()> {
+        // with just tonemapping it goes straight to the screen, no need for the intermediate targets
+        if self.is_active() || self.bloom.enabled() || self.fxaa.enabled {
+            self.update_targets(gl, scene.width, scene.height)?;
+        }
+
+        let mut passes:Vec<&mut (dyn PostProcessPass + 'static)> = self.passes
+            .iter_mut()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.pass.as_mut())
+            .collect();
+
+        if self.bloom.enabled() {
+            passes.push(&mut self.bloom);
+        }
+        passes.push(&mut self.tonemap);
+        if self.fxaa.enabled {
+            passes.push(&mut self.fxaa);
+        }
+
+        let n_passes = passes.len();
+
+        gl.toggle(GlToggle::DepthTest, false);
+        gl.toggle(GlToggle::Blend, false);
+        gl.toggle(GlToggle::CullFace, false);
+
+        let mut input = scene.clone();
+
+        for (index, pass) in passes.into_iter().enumerate() {
+            let output = match &self.targets {
+                Some(targets) if index < n_passes - 1 => Some(&targets.fbos[index % 2]),
+                _ => None
+            };
+
+            match output {
+                Some(fbo) => gl.bind_framebuffer(fbo.id, FrameBufferTarget::DrawFrameBuffer)?,
+                None => gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer),
+            }
+            input.output = output.map(|fbo| fbo.id);
+
+            pass.render(gl, &input)?;
+
+            if let Some(color) = output.and_then(|fbo| fbo.color) {
+                input.color = color.id;
+            }
+        }
+
+        gl.toggle(GlToggle::DepthTest, true);
+
+        Ok(())
+    }
+
+    fn update_targets(&mut self, gl: &mut WebGl2Renderer, width: u32, height: u32) -> Result<()> {
+        match &self.targets {
+            Some(targets) if targets.width == width && targets.height == height => {
+                return Ok(());
+            },
+            _ => {}
+        }
+
+        if let Some(mut targets) = self.targets.take() {
+            targets.destroy(gl)?;
+        }
+
+        self.targets = Some(PingPongTargets {
+            fbos: [
+                make_color_target(gl, width, height)?,
+                make_color_target(gl, width, height)?,
+            ],
+            width,
+            height
+        });
+
+        Ok(())
+    }
+}
+
+fn make_color_target(gl: &mut WebGl2Renderer, width: u32, height: u32) -> Result<FrameBuffer> {
+    let id = gl.create_framebuffer()?;
+    let color_id = make_hdr_texture(gl, width, height)?;
+
+    // FXAA samples in between texels, everything else lines up exactly so it doesn't matter
+    gl.gl.awsm_texture_set_min_filter(TextureTarget::Texture2d, TextureMinFilter::Linear);
+    gl.gl.awsm_texture_set_mag_filter(TextureTarget::Texture2d, TextureMagFilter::Linear);
+
+    gl.assign_framebuffer_texture_2d(id, color_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0, FrameBufferTextureTarget::Texture2d)?;
+    gl.check_framebuffer_status(FrameBufferTarget::DrawFrameBuffer)?;
+
+    gl.release_texture_target(TextureTarget::Texture2d);
+    gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+    Ok(FrameBuffer {
+        id,
+        depth: None,
+        color: Some(FrameBufferId { kind: FrameBufferIdKind::Texture, multisample: false, id: color_id }),
+    })
+}
+
+// the vertices come from gl_VertexID, see fullscreen-triangle.vert
+pub fn draw_fullscreen_triangle(gl: &mut WebGl2Renderer) {
+    gl.draw_arrays(BeginMode::Triangles, 0, 3);
+}
+
+// a single fragment shader, which is all most effects need
+pub struct FragmentPass {
+    pub program_id: Id,
+    // for anything beyond the standard inputs, called with the program active
+    pub uniforms: Option<Box<dyn FnMut(&mut WebGl2Renderer) -> Result<()>>>,
+}
+
+impl FragmentPass {
+    pub fn new(renderer: &mut AwsmRenderer, fragment_source: &str) -> Result<Self> {
+        Ok(Self {
+            program_id: renderer.compile_post_process_program(fragment_source)?,
+            uniforms: None,
+        })
+    }
+
+    pub fn with_uniforms(mut self, uniforms: impl FnMut(&mut WebGl2Renderer) -> Result<()> + 'static) -> Self {
+        self.uniforms = Some(Box::new(uniforms));
+        self
+    }
+}
+
+impl PostProcessPass for FragmentPass {
+    fn render(&mut self, gl: &mut WebGl2Renderer, input: &PostProcessInput) -> Result<()> {
+        gl.activate_program(self.program_id)?;
+        input.upload_uniforms(gl)?;
+
+        if let Some(uniforms) = self.uniforms.as_mut() {
+            uniforms(gl)?;
+        }
+
+        draw_fullscreen_triangle(gl);
+
+        Ok(())
+    }
+}
+
+// the program is owned by the shader cache (see compile_post_process_program), so there's nothing to delete
+// and awsm_web has no way to delete programs anyway
+impl DestroyWithGl for FragmentPass {
+    fn destroy(&mut self, _gl: &mut WebGl2Renderer) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl AwsmRenderer {
+    // links a fragment shader against the shared fullscreen triangle vertex shader
+    // cached by source, so re-creating the same pass (e.g. whenever the draw buffers are rebuilt) doesn't pile up programs
+    pub fn compile_post_process_program(&mut self, fragment_source: &str) -> Result<Id> {
+        if let Some(program_id) = self.shaders.programs.post_process.get(fragment_source) {
+            return Ok(*program_id);
+        }
+
+        let fragment_id = self.gl.compile_shader(fragment_source, ShaderType::Fragment)?;
+        let program_id = self.gl.compile_program(&vec![self.shaders.vertices.fullscreen_triangle, fragment_id])?;
+        self.shaders.programs.post_process.insert(fragment_source.to_string(), program_id);
+
+        Ok(program_id)
+    }
+
+    // picks up any changes to Config::tonemap, Config::exposure, and Config::bloom
+    // and the DrawBufferMode from the last resize
+    pub(crate) fn update_post_process(&mut self) -> Result<()> {
+        let program_id = self.tonemap_program(self.config.tonemap)?;
+
+        self.post_process.tonemap.program_id = Some(program_id);
+        self.post_process.tonemap.exposure = self.config.exposure;
+
+        self.post_process.bloom.config = self.config.bloom.clone();
+        self.post_process.bloom.programs = Some(self.shaders.programs.bloom);
+
+        self.post_process.fxaa.enabled = self.draw_buffers.as_ref().map(|draw_buffers| draw_buffers.mode == DrawBufferMode::Fxaa).unwrap_or(false);
+        self.post_process.fxaa.program_id = Some(self.shaders.programs.fxaa);
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for PingPongTargets {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        for fbo in self.fbos.iter_mut() {
+            fbo.destroy(gl)?;
+        }
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for PostProcessChain {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        if let Some(mut targets) = self.targets.take() {
+            targets.destroy(gl)?;
+        }
+        for entry in self.passes.iter_mut() {
+            entry.pass.destroy(gl)?;
+        }
+        self.bloom.destroy(gl)?;
+        self.tonemap.destroy(gl)?;
+        self.fxaa.destroy(gl)?;
+        self.passes.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+/*
+ * Render queues
+ *
+ * Everything that survives culling is put into one of four queues, by alpha mode:
+ *
+ * opaque and mask: sorted by program, then vao, then front-to-back
+ * so state changes are kept down and early depth testing can do its thing
+ *
+ * transmission: non-blended meshes with KHR_materials_transmission, sorted like the opaques
+ * but drawn after them, since they sample the opaque scene (see renderer/transmission.rs)
+ *
+ * blend: sorted back-to-front, drawn last with depth writes off
+ *
+ * Instanced batches are queued as a single item (blended meshes are never batched, since they need their own depth)
+ */
+use crate::{
+    prelude::*,
+    bounds::{Frustum, WorldBounds, CullingStats, is_culled},
+    renderer::shaders::{ShaderKey, ShaderKeyAlphaMode},
+};
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum DrawItem {
+    Entity(EntityId),
+    // index into instancing.batches
+    Batch(usize),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct QueueItem {
+    pub item: DrawItem,
+    // the mesh (or batch) program, or the g-buffer variant (see renderer/deferred.rs)
+    pub program_id: Id,
+    program_rank: u32,
+    vao_rank: u32,
+    // view space, positive is in front of the camera
+    depth: f32,
+}
+
+pub struct RenderQueues {
+    pub(crate) opaque: Vec<QueueItem>,
+    pub(crate) mask: Vec<QueueItem>,
+    pub(crate) transmission: Vec<QueueItem>,
+    pub(crate) blend: Vec<QueueItem>,
+    // Id isn't hashable or orderable, so programs and vaos are ranked by the order they're first seen
+    ranks: Vec<(Id, Vec<Id>)>,
+}
+
+impl RenderQueues {
+    pub fn new() -> Self {
+        Self {
+            opaque: Vec::new(),
+            mask: Vec::new(),
+            transmission: Vec::new(),
+            blend: Vec::new(),
+            ranks: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.opaque.clear();
+        self.mask.clear();
+        self.transmission.clear();
+        self.blend.clear();
+        self.ranks.clear();
+    }
+
+    fn push(&mut self, item: DrawItem, shader_key: &ShaderKey, program_id: Id, vao_id: Id, depth: f32) {
+        let program_rank = match self.ranks.iter().position(|(id, _)| *id == program_id) {
+            Some(rank) => rank,
+            None => {
+                self.ranks.push((program_id, Vec::new()));
+                self.ranks.len() - 1
+            }
+        };
+
+        let vaos = &mut self.ranks[program_rank].1;
+        let vao_rank = match vaos.iter().position(|id| *id == vao_id) {
+            Some(rank) => rank,
+            None => {
+                vaos.push(vao_id);
+                vaos.len() - 1
+            }
+        };
+
+        let queue_item = QueueItem {
+            item,
+            program_id,
+            program_rank: program_rank as u32,
+            vao_rank: vao_rank as u32,
+            depth,
+        };
+
+        match shader_key.alpha_mode {
+            ShaderKeyAlphaMode::Blend => self.blend.push(queue_item),
+            _ if shader_key.transmission => self.transmission.push(queue_item),
+            ShaderKeyAlphaMode::Opaque => self.opaque.push(queue_item),
+            ShaderKeyAlphaMode::Mask => self.mask.push(queue_item),
+        }
+    }
+
+    fn sort(&mut self) {
+        let state_then_front_to_back = |a: &QueueItem, b: &QueueItem| {
+            a.program_rank.cmp(&b.program_rank)
+                .then(a.vao_rank.cmp(&b.vao_rank))
+                .then(a.depth.partial_cmp(&b.depth).unwrap_or(Ordering::Equal))
+        };
+
+        self.opaque.sort_unstable_by(state_then_front_to_back);
+        self.mask.sort_unstable_by(state_then_front_to_back);
+        self.transmission.sort_unstable_by(state_then_front_to_back);
+
+        // stable, so equal depths keep a consistent order between frames
+        self.blend.sort_by(|a, b| b.depth.partial_cmp(&a.depth).unwrap_or(Ordering::Equal));
+    }
+}
+
+impl AwsmRenderer {
+    // must be called after update_instance_batches
+    pub(crate) fn update_render_queues(
+        &mut self,
+        meshes: &View<Mesh>,
+        materials: &View<Material>,
+        world_transforms: &View<WorldTransform>,
+        world_bounds: &View<WorldBounds>,
+        frustum: Option<&Frustum>,
+    ) -> Result<()> {
+        let view:Mat4 = match self.camera.get_active_dyn() {
+            Some(camera) => camera.view().cast(),
+            None => Mat4::identity()
+        };
+
+        let queues = &mut self.render_queues;
+        let instancing = &self.instancing;
+        let culling_stats = &mut self.culling_stats;
+
+        queues.clear();
+        *culling_stats = CullingStats::default();
+
+        // bounds center if we have it, otherwise the origin of the mesh
+        let view_depth = |entity: EntityId, world_transform: &Mat4| -> f32 {
+            let position = match world_bounds.get(entity) {
+                Ok(bounds) => bounds.sphere.center,
+                Err(_) => Vec3::new(world_transform[(0, 3)], world_transform[(1, 3)], world_transform[(2, 3)]),
+            };
+            -(view * Vec4::new(position.x, position.y, position.z, 1.0)).z
+        };
+
+        for (entity, (mesh, _, world_transform)) in (meshes, materials, world_transforms).iter().with_id() {
+            if is_culled(frustum, entity, mesh, world_bounds) {
+                culling_stats.culled += 1;
+                continue;
+            }
+
+            culling_stats.drawn += 1;
+
+            // queued below, as part of the batch
+            if instancing.is_batched(entity) {
+                continue;
+            }
+
+            let world_transform:&Mat4 = &world_transform;
+            let depth = view_depth(entity, world_transform);
+            queues.push(DrawItem::Entity(entity), &mesh.shader_key, mesh.program_id, mesh.vao_id, depth);
+        }
+
+        for (index, batch) in instancing.batches.iter().enumerate() {
+            let mesh = meshes.get(batch.entities[0])?;
+
+            // nearest instance
+            let mut depth = f32::MAX;
+            for entity in batch.entities.iter() {
+                let world_transform:&Mat4 = &*world_transforms.get(*entity)?;
+                depth = depth.min(view_depth(*entity, world_transform));
+            }
+
+            queues.push(DrawItem::Batch(index), &mesh.shader_key, batch.program_id, batch.vao_id, depth);
+        }
+
+        queues.sort();
+
+        Ok(())
+    }
+}
@@ -12,6 +12,7 @@ use crate::prelude::*;
 use awsm_web::webgl::{Id, WebGl2Renderer, ShaderType};
 use beach_map::{BeachMap, DefaultVersion};
 use rustc_hash::{FxHashMap, FxHashSet};
+use super::{tonemap::Tonemap, bloom::BloomPrograms, ssao::SsaoPrograms};
 
 mod fragment;
 pub use fragment::*;
@@ -28,13 +29,27 @@ pub struct ShaderCache {
     pub(crate) fragments: FragmentCache,
 }
 
-type MaxLights = u32;
-
 pub(crate) struct ProgramCache {
     pub sprite: Id,
     pub panorama_cubemap: Id,
     pub skybox: Id,
-    pub mesh: FxHashMap<(ShaderKey, MaxLights), Id>,
+    pub ibl_filtering: Id,
+    pub bloom: BloomPrograms,
+    pub fxaa: Id,
+    pub ssao: SsaoPrograms,
+    pub mesh: FxHashMap<ShaderKey, Id>,
+    // mesh vertex shader with a depth-only fragment shader
+    pub shadow_depth: FxHashMap<ShaderKey, Id>,
+    // mesh vertex shader writing out an id, see renderer/picker.rs
+    pub picking: FxHashMap<ShaderKey, Id>,
+    // mesh vertex shader writing out view-space normals, see renderer/ssao.rs
+    pub ssao_prepass: FxHashMap<ShaderKey, Id>,
+    // fullscreen resolve, one per curve, see renderer/tonemap.rs
+    pub tonemap: FxHashMap<Tonemap, Id>,
+    // keyed by whether shadows are enabled, see renderer/deferred.rs
+    pub deferred_lighting: FxHashMap<bool, Id>,
+    // keyed by the fragment source, see renderer/post_process.rs
+    pub post_process: FxHashMap<String, Id>,
 }
 
 // merely a key to hash ad-hoc shader generation
@@ -50,14 +65,67 @@ pub struct ShaderKey {
     pub morph_targets: Vec<MorphTarget>,
     pub skin_targets: Vec<SkinTarget>,
     pub n_morph_target_weights: u8,
-    pub n_skin_joints: u8,
+    pub n_skin_joints: u32,
+    // read joints from the renderer's skin texture instead of a uniform array
+    pub skin_texture: bool,
+    // first of 4 consecutive attribute locations for the per-instance model matrix
+    // only set on the instanced variant, see renderer/instancing.rs
+    pub instance_model_loc: Option<u32>,
     pub tex_coords: Option<Vec<u32>>,
     pub vertex_colors: Option<Vec<VertexColor>>,
     pub normal_texture_uv_index: Option<u32>,
     pub metallic_roughness_texture_uv_index: Option<u32>,
     pub base_color_texture_uv_index: Option<u32>,
     pub emissive_texture_uv_index: Option<u32>,
+    pub occlusion_texture_uv_index: Option<u32>,
+    pub clearcoat_texture_uv_index: Option<u32>,
+    pub clearcoat_roughness_texture_uv_index: Option<u32>,
+    pub clearcoat_normal_texture_uv_index: Option<u32>,
+    pub sheen_color_texture_uv_index: Option<u32>,
+    pub sheen_roughness_texture_uv_index: Option<u32>,
+    pub specular_texture_uv_index: Option<u32>,
+    pub specular_color_texture_uv_index: Option<u32>,
+    pub transmission_texture_uv_index: Option<u32>,
+    pub volume_thickness_texture_uv_index: Option<u32>,
+    pub iridescence_texture_uv_index: Option<u32>,
+    pub iridescence_thickness_texture_uv_index: Option<u32>,
+    // KHR_texture_transform, per texture slot
+    pub normal_uv_transform: bool,
+    pub metallic_roughness_uv_transform: bool,
+    pub base_color_uv_transform: bool,
+    pub emissive_uv_transform: bool,
+    pub occlusion_uv_transform: bool,
+    pub clearcoat_uv_transform: bool,
+    pub clearcoat_roughness_uv_transform: bool,
+    pub clearcoat_normal_uv_transform: bool,
+    pub sheen_color_uv_transform: bool,
+    pub sheen_roughness_uv_transform: bool,
+    pub specular_uv_transform: bool,
+    pub specular_color_uv_transform: bool,
+    pub transmission_uv_transform: bool,
+    pub volume_thickness_uv_transform: bool,
+    pub iridescence_uv_transform: bool,
+    pub iridescence_thickness_uv_transform: bool,
+    // KHR_materials_clearcoat, KHR_materials_sheen, KHR_materials_specular
+    pub clearcoat: bool,
+    pub sheen: bool,
+    pub specular: bool,
+    // KHR_materials_transmission, KHR_materials_volume (only along with transmission)
+    // also puts the mesh in the transmission queue, see renderer/transmission.rs
+    pub transmission: bool,
+    pub volume: bool,
+    // KHR_materials_iridescence, KHR_materials_ior
+    pub iridescence: bool,
+    pub ior: bool,
     pub alpha_mode: ShaderKeyAlphaMode,
+    // set from the renderer's environment, not the mesh itself
+    pub ibl: bool,
+    // set from the renderer's shadow config, not the mesh itself
+    pub shadows: bool,
+    // set from the renderer's ssao config, not the mesh itself
+    pub ssao: bool,
+    // the g-buffer variant, see deferred_mesh_program()
+    pub deferred: bool,
 }
 
 #[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
@@ -73,38 +141,151 @@ impl Default for ShaderKeyAlphaMode {
     }
 }
 
+// a texture slot in the pbr shader
+pub(crate) struct TextureUv {
+    // matches the shader, e.g. v_{name}_uv, {NAME}_UV_MAP, {NAME}_UV_TRANSFORM
+    pub name: &'static str,
+    pub uv_index: Option<u32>,
+    pub transform: bool,
+}
+
+impl ShaderKey {
+    pub(crate) fn texture_uvs(&self) -> Vec<TextureUv> {
+        let slot = |name, uv_index, transform| TextureUv { name, uv_index, transform };
+
+        vec![
+            slot("normal", self.normal_texture_uv_index, self.normal_uv_transform),
+            slot("metallic_roughness", self.metallic_roughness_texture_uv_index, self.metallic_roughness_uv_transform),
+            slot("base_color", self.base_color_texture_uv_index, self.base_color_uv_transform),
+            slot("emissive", self.emissive_texture_uv_index, self.emissive_uv_transform),
+            slot("occlusion", self.occlusion_texture_uv_index, self.occlusion_uv_transform),
+            slot("clearcoat", self.clearcoat_texture_uv_index, self.clearcoat_uv_transform),
+            slot("clearcoat_roughness", self.clearcoat_roughness_texture_uv_index, self.clearcoat_roughness_uv_transform),
+            slot("clearcoat_normal", self.clearcoat_normal_texture_uv_index, self.clearcoat_normal_uv_transform),
+            slot("sheen_color", self.sheen_color_texture_uv_index, self.sheen_color_uv_transform),
+            slot("sheen_roughness", self.sheen_roughness_texture_uv_index, self.sheen_roughness_uv_transform),
+            slot("specular", self.specular_texture_uv_index, self.specular_uv_transform),
+            slot("specular_color", self.specular_color_texture_uv_index, self.specular_color_uv_transform),
+            slot("transmission", self.transmission_texture_uv_index, self.transmission_uv_transform),
+            slot("volume_thickness", self.volume_thickness_texture_uv_index, self.volume_thickness_uv_transform),
+            slot("iridescence", self.iridescence_texture_uv_index, self.iridescence_uv_transform),
+            slot("iridescence_thickness", self.iridescence_thickness_texture_uv_index, self.iridescence_thickness_uv_transform),
+        ]
+    }
+}
+
 impl AwsmRenderer {
-    pub fn mesh_program(&mut self, key: ShaderKey, max_lights: u32) -> Result<Id> {
+    pub fn mesh_program(&mut self, mut key: ShaderKey) -> Result<Id> {
+        key.ibl = self.environment.is_some();
+        key.shadows = self.shadows.enabled();
+        key.ssao = self.ssao.enabled();
+
         let shaders = &mut self.shaders;
         let gl = &mut self.gl;
 
-        match shaders.programs.mesh.entry((key.clone(), max_lights)) {
+        match shaders.programs.mesh.entry(key.clone()) {
             Entry::Occupied(entry) => Ok(entry.get().clone()),
             Entry::Vacant(entry) => {
 
                 let vertex_id = shaders.vertices.mesh_shader(gl, &key)?;
-                let fragment_id = shaders.fragments.mesh_shader(gl, &key, self.lights.max_lights)?;
+                let fragment_id = shaders.fragments.mesh_shader(gl, &key)?;
                 let program_id = gl.compile_program(&vec![vertex_id, fragment_id])?;
 
                 // need to do for each ubo
                 gl.init_uniform_buffer_name(program_id, "ubo_camera")?;
-                if max_lights > 0 {
-                    gl.init_uniform_buffer_name(program_id, "ubo_lights")?;
-                }
 
                 Ok(entry.insert(program_id).clone())
             }
         }
     }
 
-    pub fn recompile_mesh_programs_max_lights(&mut self, world: &World, max_lights: u32) -> Result<()> {
+    // same as mesh_program() but writes into the g-buffer instead of applying lights
+    // looked up per-frame, so switching DrawBufferMode doesn't need a recompile
+    pub fn deferred_mesh_program(&mut self, mut key: ShaderKey) -> Result<Id> {
+        key.deferred = true;
+        self.mesh_program(key)
+    }
+
+    pub fn deferred_lighting_program(&mut self) -> Result<Id> {
+        let shadows = self.shadows.enabled();
+        let shaders = &mut self.shaders;
+        let gl = &mut self.gl;
+
+        match shaders.programs.deferred_lighting.entry(shadows) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let fragment_id = shaders.fragments.deferred_lighting_shader(gl, shadows)?;
+                let program_id = gl.compile_program(&vec![shaders.vertices.fullscreen_triangle, fragment_id])?;
+
+                gl.init_uniform_buffer_name(program_id, "ubo_camera")?;
+
+                Ok(entry.insert(program_id).clone())
+            }
+        }
+    }
+
+    pub fn shadow_depth_program(&mut self, key: &ShaderKey) -> Result<Id> {
+        let shaders = &mut self.shaders;
+        let gl = &mut self.gl;
+
+        match shaders.programs.shadow_depth.entry(key.clone()) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let vertex_id = shaders.vertices.mesh_shader(gl, key)?;
+                let fragment_id = shaders.fragments.shadow_depth_shader(gl, key)?;
+                let program_id = gl.compile_program(&vec![vertex_id, fragment_id])?;
+
+                gl.init_uniform_buffer_name(program_id, "ubo_camera")?;
+
+                Ok(entry.insert(program_id).clone())
+            }
+        }
+    }
+
+    pub fn picking_program(&mut self, key: &ShaderKey) -> Result<Id> {
+        let shaders = &mut self.shaders;
+        let gl = &mut self.gl;
+
+        match shaders.programs.picking.entry(key.clone()) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let vertex_id = shaders.vertices.mesh_shader(gl, key)?;
+                let fragment_id = shaders.fragments.picking_shader(gl, key)?;
+                let program_id = gl.compile_program(&vec![vertex_id, fragment_id])?;
+
+                gl.init_uniform_buffer_name(program_id, "ubo_camera")?;
+
+                Ok(entry.insert(program_id).clone())
+            }
+        }
+    }
+
+    pub fn ssao_prepass_program(&mut self, key: &ShaderKey) -> Result<Id> {
+        let shaders = &mut self.shaders;
+        let gl = &mut self.gl;
+
+        match shaders.programs.ssao_prepass.entry(key.clone()) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let vertex_id = shaders.vertices.mesh_shader(gl, key)?;
+                let fragment_id = shaders.fragments.ssao_prepass_shader(gl, key)?;
+                let program_id = gl.compile_program(&vec![vertex_id, fragment_id])?;
+
+                gl.init_uniform_buffer_name(program_id, "ubo_camera")?;
+
+                Ok(entry.insert(program_id).clone())
+            }
+        }
+    }
+
+    pub fn recompile_mesh_programs(&mut self, world: &World) -> Result<()> {
         // only recompile existing meshes. 
         // New ones will inherently need to have their program id available
         world.run(|mut meshes: ViewMut<Mesh>| -> Result<()> {
             let mut n_updated = 0;
 
             for mesh in (&mut meshes).iter() {
-                mesh.program_id = self.mesh_program(mesh.shader_key.clone(), max_lights)?;
+                mesh.program_id = self.mesh_program(mesh.shader_key.clone())?;
                 n_updated += 1;
             }
 
@@ -136,7 +317,24 @@ impl ProgramCache {
             sprite: gl.compile_program(&vec![vertex_ids.quad_unit, fragment_ids.unlit_diffuse])?,
             panorama_cubemap: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.panorama_to_cubemap])?,
             skybox: gl.compile_program(&vec![vertex_ids.skybox, fragment_ids.skybox])?,
+            ibl_filtering: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.ibl_filtering])?,
+            bloom: BloomPrograms {
+                downsample: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.bloom_downsample])?,
+                upsample: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.bloom_upsample])?,
+                composite: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.bloom_composite])?,
+            },
+            fxaa: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.fxaa])?,
+            ssao: SsaoPrograms {
+                occlusion: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.ssao])?,
+                blur: gl.compile_program(&vec![vertex_ids.fullscreen_triangle, fragment_ids.ssao_blur])?,
+            },
             mesh: FxHashMap::default(),
+            shadow_depth: FxHashMap::default(),
+            picking: FxHashMap::default(),
+            ssao_prepass: FxHashMap::default(),
+            tonemap: FxHashMap::default(),
+            deferred_lighting: FxHashMap::default(),
+            post_process: FxHashMap::default(),
         };
 
         for program_id in vec![ 
@@ -5,12 +5,30 @@ use beach_map::{BeachMap, DefaultVersion};
 use rustc_hash::FxHashMap;
 
 use super::{COMMON_CAMERA, COMMON_MATH, COMMON_COLOR_SPACE, ShaderKey, ShaderKeyAlphaMode};
+use crate::light::{
+    LIGHTS_PER_ROW,
+    shadow::{MAX_SHADOW_MAPS, MAX_POINT_SHADOW_MAPS},
+    cluster::{CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z, LIGHT_INDICES_WIDTH},
+};
+use crate::renderer::tonemap::Tonemap;
 
 const ENTRY_MESH_PBR:&'static str = include_str!("./glsl/fragment/mesh-pbr.frag");
 const ENTRY_QUAD_TEXTURE:&'static str = include_str!("./glsl/fragment/quad-texture.frag");
 const ENTRY_UNLIT_DIFFUSE:&'static str = include_str!("./glsl/fragment/unlit-diffuse.frag");
 const ENTRY_PANORAMA_TO_CUBEMAP:&'static str = include_str!("./glsl/fragment/panorama_to_cubemap.frag");
 const ENTRY_SKYBOX:&'static str = include_str!("./glsl/fragment/skybox.frag");
+const ENTRY_IBL_FILTERING:&'static str = include_str!("./glsl/fragment/ibl_filtering.frag");
+const ENTRY_SHADOW_DEPTH:&'static str = include_str!("./glsl/fragment/shadow_depth.frag");
+const ENTRY_PICKING:&'static str = include_str!("./glsl/fragment/picking.frag");
+const ENTRY_TONEMAP:&'static str = include_str!("./glsl/fragment/tonemap.frag");
+const ENTRY_BLOOM_DOWNSAMPLE:&'static str = include_str!("./glsl/fragment/bloom_downsample.frag");
+const ENTRY_BLOOM_UPSAMPLE:&'static str = include_str!("./glsl/fragment/bloom_upsample.frag");
+const ENTRY_BLOOM_COMPOSITE:&'static str = include_str!("./glsl/fragment/bloom_composite.frag");
+const ENTRY_FXAA:&'static str = include_str!("./glsl/fragment/fxaa.frag");
+const ENTRY_SSAO_PREPASS:&'static str = include_str!("./glsl/fragment/ssao_prepass.frag");
+const ENTRY_SSAO:&'static str = include_str!("./glsl/fragment/ssao.frag");
+const ENTRY_SSAO_BLUR:&'static str = include_str!("./glsl/fragment/ssao_blur.frag");
+const ENTRY_DEFERRED_LIGHTING:&'static str = include_str!("./glsl/fragment/deferred_lighting.frag");
 
 const MESH_PBR_DATA_STRUCTS:&'static str = include_str!("./glsl/fragment/material/pbr/data/structs.glsl");
 const MESH_PBR_DATA_UNIFORMS:&'static str = include_str!("./glsl/fragment/material/pbr/data/uniforms.glsl");
@@ -25,13 +43,35 @@ const MESH_PBR_FN_COLOR:&'static str = include_str!("./glsl/fragment/material/pb
 const MESH_PBR_FN_IRIDESCENCE:&'static str = include_str!("./glsl/fragment/material/pbr/fn/iridescence.glsl");
 const MESH_PBR_FN_AMBIENT_OCCLUSION:&'static str = include_str!("./glsl/fragment/material/pbr/fn/ambient_occlusion.glsl");
 const MESH_PBR_FN_TONE_MAP:&'static str = include_str!("./glsl/fragment/material/pbr/fn/tone_map.glsl");
+const MESH_PBR_FN_IBL:&'static str = include_str!("./glsl/fragment/material/pbr/fn/ibl.glsl");
+const MESH_PBR_FN_SSAO:&'static str = include_str!("./glsl/fragment/material/pbr/fn/ssao.glsl");
+const MESH_PBR_FN_TRANSMISSION:&'static str = include_str!("./glsl/fragment/material/pbr/fn/transmission.glsl");
+const ALPHA_MASK:&'static str = include_str!("./glsl/fragment/material/alpha_mask.glsl");
 
 pub(crate) struct FragmentCache {
     pub unlit_diffuse: Id,
     pub quad_texture: Id,
     pub panorama_to_cubemap: Id,
     pub skybox: Id,
+    pub ibl_filtering: Id,
+    pub shadow_depth: Id,
+    // masked meshes discard in the depth pass, see AlphaMaskKey
+    pub shadow_depth_masked: FxHashMap<AlphaMaskKey, Id>,
+    pub picking: Id,
+    pub picking_masked: FxHashMap<AlphaMaskKey, Id>,
+    pub bloom_downsample: Id,
+    pub bloom_upsample: Id,
+    pub bloom_composite: Id,
+    pub fxaa: Id,
+    pub ssao: Id,
+    pub ssao_blur: Id,
+    // with and without vertex normals, see ssao_prepass_shader()
+    pub ssao_prepass: [Id;2],
+    pub ssao_prepass_masked: FxHashMap<(bool, AlphaMaskKey), Id>,
     pub mesh: FxHashMap<ShaderKey, Id>,
+    pub tonemap: FxHashMap<Tonemap, Id>,
+    // keyed by whether shadows are enabled
+    pub deferred_lighting: FxHashMap<bool, Id>,
 }
 
 impl FragmentCache { 
@@ -44,47 +84,256 @@ impl FragmentCache {
                 .replace("% INCLUDES_COMMON_CAMERA %", COMMON_CAMERA)
                 .replace("% INCLUDES_COMMON_COLOR_SPACE %", COMMON_COLOR_SPACE)
             , ShaderType::Fragment)?,
-            mesh: FxHashMap::default()
+            ibl_filtering: gl.compile_shader(ENTRY_IBL_FILTERING, ShaderType::Fragment)?,
+            shadow_depth: compile_alpha_masked(gl, ENTRY_SHADOW_DEPTH, None)?,
+            shadow_depth_masked: FxHashMap::default(),
+            picking: compile_alpha_masked(gl, ENTRY_PICKING, None)?,
+            picking_masked: FxHashMap::default(),
+            bloom_downsample: gl.compile_shader(ENTRY_BLOOM_DOWNSAMPLE, ShaderType::Fragment)?,
+            bloom_upsample: gl.compile_shader(ENTRY_BLOOM_UPSAMPLE, ShaderType::Fragment)?,
+            bloom_composite: gl.compile_shader(ENTRY_BLOOM_COMPOSITE, ShaderType::Fragment)?,
+            fxaa: gl.compile_shader(ENTRY_FXAA, ShaderType::Fragment)?,
+            ssao: gl.compile_shader(ENTRY_SSAO, ShaderType::Fragment)?,
+            ssao_blur: gl.compile_shader(ENTRY_SSAO_BLUR, ShaderType::Fragment)?,
+            ssao_prepass: [
+                compile_ssao_prepass(gl, false, None)?,
+                compile_ssao_prepass(gl, true, None)?,
+            ],
+            ssao_prepass_masked: FxHashMap::default(),
+            mesh: FxHashMap::default(),
+            tonemap: FxHashMap::default(),
+            deferred_lighting: FxHashMap::default(),
         })
     }
 
+    pub fn tonemap_shader(&mut self, gl:&mut WebGl2Renderer, tonemap: Tonemap) -> Result<Id> {
+        match self.tonemap.entry(tonemap) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let source = ENTRY_TONEMAP
+                    .replace("% INCLUDES_COMMON_COLOR_SPACE %", COMMON_COLOR_SPACE)
+                    .replace("% INCLUDES_TONE_MAP %", &format!("{}{}", tonemap.define(), MESH_PBR_FN_TONE_MAP));
+
+                let id = gl.compile_shader(&source, ShaderType::Fragment)?;
+                Ok(entry.insert(id).clone())
+            }
+        }
+    }
+
+    pub fn deferred_lighting_shader(&mut self, gl:&mut WebGl2Renderer, shadows: bool) -> Result<Id> {
+        match self.deferred_lighting.entry(shadows) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let mut defines = light_defines();
+                if shadows {
+                    defines.push_str(&shadow_defines());
+                }
+
+                let source = ENTRY_DEFERRED_LIGHTING
+                    .replace("% INCLUDES_COMMON_MATH %", COMMON_MATH)
+                    .replace("% INCLUDES_COMMON_CAMERA %", COMMON_CAMERA)
+                    .replace("% INCLUDES_DEFINES %", &defines)
+                    .replace("% INCLUDES_DEPS %", &format!(r#"
+                        {MESH_PBR_DATA_STRUCTS}
+                        {MESH_PBR_DATA_UNIFORMS}
+                        {MESH_PBR_FN_MISC}
+                        {MESH_PBR_FN_BRDF}
+                        {MESH_PBR_FN_LIGHT}
+                    "#))
+                    .replace("% INCLUDES_SHADOW_MAPS %", &shadow_maps_main());
+
+                let id = gl.compile_shader(&source, ShaderType::Fragment)?;
+                Ok(entry.insert(id).clone())
+            }
+        }
+    }
+
+    pub fn shadow_depth_shader(&mut self, gl:&mut WebGl2Renderer, key: &ShaderKey) -> Result<Id> {
+        let mask = match key.alpha_mask_key() {
+            Some(mask) => mask,
+            None => return Ok(self.shadow_depth)
+        };
+
+        match self.shadow_depth_masked.entry(mask) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let id = compile_alpha_masked(gl, ENTRY_SHADOW_DEPTH, Some(mask))?;
+                Ok(entry.insert(id).clone())
+            }
+        }
+    }
+
+    pub fn picking_shader(&mut self, gl:&mut WebGl2Renderer, key: &ShaderKey) -> Result<Id> {
+        let mask = match key.alpha_mask_key() {
+            Some(mask) => mask,
+            None => return Ok(self.picking)
+        };
+
+        match self.picking_masked.entry(mask) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let id = compile_alpha_masked(gl, ENTRY_PICKING, Some(mask))?;
+                Ok(entry.insert(id).clone())
+            }
+        }
+    }
+
+    // the prepass reads v_normal only if the vertex shader writes it
+    pub fn ssao_prepass_shader(&mut self, gl:&mut WebGl2Renderer, key: &ShaderKey) -> Result<Id> {
+        let normals = key.normal_attribute_loc.is_some();
+
+        let mask = match key.alpha_mask_key() {
+            Some(mask) => mask,
+            None => return Ok(self.ssao_prepass[if normals { 1 } else { 0 }])
+        };
+
+        match self.ssao_prepass_masked.entry((normals, mask)) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let id = compile_ssao_prepass(gl, normals, Some(mask))?;
+                Ok(entry.insert(id).clone())
+            }
+        }
+    }
+
     // we only need to compile the shader once ever per a given key
     // after that, it's cached in memory and merely re-used for programs
-    pub fn mesh_shader(&mut self, mut gl:&mut WebGl2Renderer, key: &ShaderKey, max_lights: u32) -> Result<Id> {
+    pub fn mesh_shader(&mut self, mut gl:&mut WebGl2Renderer, key: &ShaderKey) -> Result<Id> {
         match self.mesh.entry(key.clone()) {
             Entry::Occupied(entry) => Ok(entry.get().clone()),
             Entry::Vacant(entry) => {
-                let id = gl.compile_shader(&key.into_fragment_code(max_lights)?, ShaderType::Fragment)?;
+                let id = gl.compile_shader(&key.into_fragment_code()?, ShaderType::Fragment)?;
                 Ok(entry.insert(id).clone())
             }
         }
     }
 }
 
+fn compile_ssao_prepass(gl:&mut WebGl2Renderer, normals: bool, mask: Option<AlphaMaskKey>) -> Result<Id> {
+    let source = ENTRY_SSAO_PREPASS
+        .replace("% INCLUDES_COMMON_CAMERA %", COMMON_CAMERA)
+        .replace("% INCLUDES_DEFINES %", if normals { "#define VARYING_NORMAL\n" } else { "" });
+
+    compile_alpha_masked(gl, &source, mask)
+}
+
+// the parts of a ShaderKey that decide which fragments of a masked mesh are cut out
+// so the passes that don't run the full material can discard the same ones
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AlphaMaskKey {
+    base_color_uv: bool,
+    base_color_uv_transform: bool,
+    vertex_colors: bool,
+}
+
+impl ShaderKey {
+    // None unless the mesh is masked, in which case the unmasked shader is fine
+    pub(crate) fn alpha_mask_key(&self) -> Option<AlphaMaskKey> {
+        match self.alpha_mode {
+            ShaderKeyAlphaMode::Mask => Some(AlphaMaskKey {
+                base_color_uv: self.base_color_texture_uv_index.is_some(),
+                base_color_uv_transform: self.base_color_uv_transform,
+                vertex_colors: self.vertex_colors.is_some(),
+            }),
+            _ => None
+        }
+    }
+}
+
+impl AlphaMaskKey {
+    fn defines(&self) -> String {
+        let mut res = "#define ALPHA_MASK\n".to_string();
+
+        if self.base_color_uv {
+            res.push_str("#define BASE_COLOR_UV_MAP\n");
+        }
+        if self.base_color_uv_transform {
+            res.push_str("#define BASE_COLOR_UV_TRANSFORM\n");
+        }
+        if self.vertex_colors {
+            res.push_str("#define VERTEX_COLORS\n");
+        }
+
+        res
+    }
+}
+
+fn compile_alpha_masked(gl:&mut WebGl2Renderer, entry: &str, mask: Option<AlphaMaskKey>) -> Result<Id> {
+    let defines = mask.map(|mask| mask.defines()).unwrap_or_default();
+    let source = entry.replace("% INCLUDES_ALPHA_MASK %", &format!("{defines}{ALPHA_MASK}"));
+
+    gl.compile_shader(&source, ShaderType::Fragment)
+}
+
 impl ShaderKey {
-    fn into_fragment_code(&self, max_lights: u32) -> Result<String> {
+    fn into_fragment_code(&self) -> Result<String> {
         let mut res:String = ENTRY_MESH_PBR
             .replace("% INCLUDES_COMMON_MATH %", COMMON_MATH)
             .replace("% INCLUDES_COMMON_CAMERA %", COMMON_CAMERA)
             .replace("% INCLUDES_COMMON_COLOR_SPACE %", COMMON_COLOR_SPACE)
-            .replace("% INCLUDES_MATERIAL_DEPS %", &self.into_fragment_material_deps(max_lights)?);
-
-        if max_lights > 0 {
-            let mut s = "".to_string();
-
-            for i in 0..max_lights {
-                s.push_str(&format!("apply_light_output(material, normal_info, convert_ubo_light(u_lights.light[{i}]), light_output, step({}.0, u_lights.active_len));\n", i+1));
-            }
+            .replace("% INCLUDES_MATERIAL_DEPS %", &self.into_fragment_material_deps()?);
 
-            res = res.replace("% INCLUDES_LIGHT_MAIN %", &s);
+        if self.lights() && self.shadows {
+            res = res.replace("% INCLUDES_SHADOW_MAPS %", &shadow_maps_main());
         }
 
         Ok(res)
     }
 }
 
+// the body of get_shadow(), since the samplers can't be dynamically indexed
+fn shadow_maps_main() -> String {
+    let mut s = "".to_string();
+
+    for i in 0..MAX_SHADOW_MAPS {
+        s.push_str(&format!("shadow *= get_shadow_map_factor(u_shadow_map_{i}, {i}, light_index, view_depth, normal_info);\n"));
+    }
+    for i in 0..MAX_POINT_SHADOW_MAPS {
+        s.push_str(&format!("shadow *= get_point_shadow_map_factor(u_point_shadow_map_{i}, {i}, light_index, normal_info);\n"));
+    }
+
+    s
+}
+
+// see light/cluster.rs
+fn light_defines() -> String {
+    let mut res = "".to_string();
+
+    res.push_str("#define LIGHTS\n");
+    res.push_str(&format!("#define LIGHT_CLUSTERS_X {}\n", CLUSTERS_X));
+    res.push_str(&format!("#define LIGHT_CLUSTERS_Y {}\n", CLUSTERS_Y));
+    res.push_str(&format!("#define LIGHT_CLUSTERS_Z {}\n", CLUSTERS_Z));
+    res.push_str(&format!("#define LIGHT_INDICES_WIDTH {}\n", LIGHT_INDICES_WIDTH));
+    res.push_str(&format!("#define LIGHTS_PER_ROW {}\n", LIGHTS_PER_ROW));
+
+    res
+}
+
+fn shadow_defines() -> String {
+    let mut res = "".to_string();
+
+    res.push_str("#define SHADOWS\n");
+    res.push_str(&format!("#define MAX_SHADOW_MAPS {}\n", MAX_SHADOW_MAPS));
+    res.push_str(&format!("#define MAX_POINT_SHADOW_MAPS {}\n", MAX_POINT_SHADOW_MAPS));
+    res.push_str("precision highp sampler2DShadow;\n");
+    res.push_str("precision highp samplerCubeShadow;\n");
+    for i in 0..MAX_SHADOW_MAPS {
+        res.push_str(&format!("uniform sampler2DShadow u_shadow_map_{i};\n"));
+    }
+    for i in 0..MAX_POINT_SHADOW_MAPS {
+        res.push_str(&format!("uniform samplerCubeShadow u_point_shadow_map_{i};\n"));
+    }
+
+    res
+}
+
 impl ShaderKey {
-    fn into_fragment_material_deps(&self, max_lights: u32) -> Result<String> {
+    // the g-buffer variant leaves the lights for the lighting pass, see renderer/deferred.rs
+    fn lights(&self) -> bool {
+        !self.deferred
+    }
+
+    fn into_fragment_material_deps(&self) -> Result<String> {
         let mut res = String::new();
 
         // debug flags
@@ -97,38 +346,81 @@ impl ShaderKey {
             ShaderKeyAlphaMode::Blend => { res.push_str("#define ALPHAMODE 2\n"); }
         }
         
+        // tonemapping happens at the end, see renderer/tonemap.rs
         res.push_str("#define LINEAR_OUTPUT\n");
 
         res.push_str("#define METALLIC_ROUGHNESS\n");
-        if self.metallic_roughness_texture_uv_index.is_some() {
-            res.push_str("#define METALLIC_ROUGHNESS_UV_MAP\n");
-        }
 
-        if self.base_color_texture_uv_index.is_some() {
-            res.push_str("#define BASE_COLOR_UV_MAP\n");
+        for uv in self.texture_uvs() {
+            let define = uv.name.to_uppercase();
+            if uv.uv_index.is_some() {
+                res.push_str(&format!("#define {define}_UV_MAP\n"));
+            }
+            if uv.transform {
+                res.push_str(&format!("#define {define}_UV_TRANSFORM\n"));
+            }
         }
 
         if self.normal_attribute_loc.is_some() {
             res.push_str("#define VARYING_NORMAL\n");
         }
 
-        if self.normal_texture_uv_index.is_some() {
-            res.push_str("#define NORMAL_UV_MAP\n");
+        if self.clearcoat {
+            res.push_str("#define CLEARCOAT\n");
+        }
+
+        if self.sheen {
+            res.push_str("#define SHEEN\n");
+        }
+
+        if self.specular {
+            res.push_str("#define SPECULAR\n");
+        }
+
+        if self.transmission {
+            res.push_str("#define TRANSMISSION\n");
+        }
+
+        if self.volume {
+            res.push_str("#define VOLUME\n");
+        }
+
+        if self.iridescence {
+            res.push_str("#define IRIDESCENCE\n");
         }
 
-        if self.emissive_texture_uv_index.is_some() {
-            res.push_str("#define EMISSIVE_UV_MAP\n");
+        if self.ior {
+            res.push_str("#define IOR\n");
         }
 
         if self.vertex_colors.is_some() {
             res.push_str("#define VERTEX_COLORS\n");
         }
 
-        if max_lights > 0 {
-            res.push_str(&format!("#define MAX_LIGHTS {}\n", max_lights));
+        if self.lights() {
+            res.push_str(&light_defines());
+        }
+
+        if self.ibl {
+            res.push_str("#define IBL\n");
+        }
+
+        if self.lights() && self.shadows {
+            res.push_str(&shadow_defines());
+        }
+
+        if self.deferred {
+            res.push_str("#define DEFERRED\n");
+        }
+
+        if self.ssao {
+            res.push_str("#define SSAO\n");
         }
 
 
+        // the lights need the volume functions too
+        let transmission = if self.transmission { MESH_PBR_FN_TRANSMISSION } else { "" };
+
         // basic imports
         res.push_str(&format!(r#"
             {MESH_PBR_DATA_STRUCTS}
@@ -142,9 +434,19 @@ impl ShaderKey {
             {MESH_PBR_FN_IRIDESCENCE}
             {MESH_PBR_FN_TONE_MAP}
             {MESH_PBR_FN_COLOR}
+            {transmission}
             {MESH_PBR_FN_LIGHT}
+            {MESH_PBR_FN_AMBIENT_OCCLUSION}
         "#));
 
+        if self.ibl {
+            res.push_str(MESH_PBR_FN_IBL);
+        }
+
+        if self.ssao {
+            res.push_str(MESH_PBR_FN_SSAO);
+        }
+
 
         Ok(res)
     }
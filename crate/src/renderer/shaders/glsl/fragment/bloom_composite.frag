@@ -0,0 +1,18 @@
+#version 300 es
+precision highp float;
+
+// energy conserving, i.e. a lerp rather than adding light
+
+uniform sampler2D u_color_sampler;
+uniform sampler2D u_bloom_sampler;
+uniform float u_bloom_intensity;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+void main() {
+    vec4 color = texture(u_color_sampler, tex_coord);
+    vec3 bloom = texture(u_bloom_sampler, tex_coord).rgb;
+
+    fragment_color = vec4(mix(color.rgb, bloom, u_bloom_intensity), color.a);
+}
@@ -0,0 +1,64 @@
+#version 300 es
+precision highp float;
+
+// 13-tap downsample from Call of Duty: Advanced Warfare (Jimenez 2014)
+// see: https://learnopengl.com/Guest-Articles/2022/Phys.-Based-Bloom
+
+uniform sampler2D u_color_sampler;
+uniform vec2 u_src_resolution;
+// the first downsample uses a Karis average, so single very bright pixels don't flicker
+uniform int u_first_mip;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+float karis_average(vec3 color) {
+    float luma = dot(color, vec3(0.2126, 0.7152, 0.0722)) * 0.25;
+    return 1.0 / (1.0 + luma);
+}
+
+void main() {
+    float x = 1.0 / u_src_resolution.x;
+    float y = 1.0 / u_src_resolution.y;
+
+    vec3 a = texture(u_color_sampler, vec2(tex_coord.x - 2.0 * x, tex_coord.y + 2.0 * y)).rgb;
+    vec3 b = texture(u_color_sampler, vec2(tex_coord.x,           tex_coord.y + 2.0 * y)).rgb;
+    vec3 c = texture(u_color_sampler, vec2(tex_coord.x + 2.0 * x, tex_coord.y + 2.0 * y)).rgb;
+
+    vec3 d = texture(u_color_sampler, vec2(tex_coord.x - 2.0 * x, tex_coord.y)).rgb;
+    vec3 e = texture(u_color_sampler, vec2(tex_coord.x,           tex_coord.y)).rgb;
+    vec3 f = texture(u_color_sampler, vec2(tex_coord.x + 2.0 * x, tex_coord.y)).rgb;
+
+    vec3 g = texture(u_color_sampler, vec2(tex_coord.x - 2.0 * x, tex_coord.y - 2.0 * y)).rgb;
+    vec3 h = texture(u_color_sampler, vec2(tex_coord.x,           tex_coord.y - 2.0 * y)).rgb;
+    vec3 i = texture(u_color_sampler, vec2(tex_coord.x + 2.0 * x, tex_coord.y - 2.0 * y)).rgb;
+
+    vec3 j = texture(u_color_sampler, vec2(tex_coord.x - x, tex_coord.y + y)).rgb;
+    vec3 k = texture(u_color_sampler, vec2(tex_coord.x + x, tex_coord.y + y)).rgb;
+    vec3 l = texture(u_color_sampler, vec2(tex_coord.x - x, tex_coord.y - y)).rgb;
+    vec3 m = texture(u_color_sampler, vec2(tex_coord.x + x, tex_coord.y - y)).rgb;
+
+    vec3 color;
+
+    if (u_first_mip == 1) {
+        vec3 g0 = (a + b + d + e) * (0.125 / 4.0);
+        vec3 g1 = (b + c + e + f) * (0.125 / 4.0);
+        vec3 g2 = (d + e + g + h) * (0.125 / 4.0);
+        vec3 g3 = (e + f + h + i) * (0.125 / 4.0);
+        vec3 g4 = (j + k + l + m) * (0.5 / 4.0);
+
+        color = (g0 * karis_average(g0))
+            + (g1 * karis_average(g1))
+            + (g2 * karis_average(g2))
+            + (g3 * karis_average(g3))
+            + (g4 * karis_average(g4));
+    } else {
+        color = e * 0.125;
+        color += (a + c + g + i) * 0.03125;
+        color += (b + d + f + h) * 0.0625;
+        color += (j + k + l + m) * 0.125;
+    }
+
+    // keeps black pixels from turning into NaN further down the chain
+    fragment_color = vec4(max(color, 0.0001), 1.0);
+}
@@ -0,0 +1,36 @@
+#version 300 es
+precision highp float;
+
+// 3x3 tent filter, blended additively onto the next mip up
+// see: https://learnopengl.com/Guest-Articles/2022/Phys.-Based-Bloom
+
+uniform sampler2D u_color_sampler;
+// in uv units
+uniform float u_filter_radius;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+void main() {
+    float x = u_filter_radius;
+    float y = u_filter_radius;
+
+    vec3 a = texture(u_color_sampler, vec2(tex_coord.x - x, tex_coord.y + y)).rgb;
+    vec3 b = texture(u_color_sampler, vec2(tex_coord.x,     tex_coord.y + y)).rgb;
+    vec3 c = texture(u_color_sampler, vec2(tex_coord.x + x, tex_coord.y + y)).rgb;
+
+    vec3 d = texture(u_color_sampler, vec2(tex_coord.x - x, tex_coord.y)).rgb;
+    vec3 e = texture(u_color_sampler, vec2(tex_coord.x,     tex_coord.y)).rgb;
+    vec3 f = texture(u_color_sampler, vec2(tex_coord.x + x, tex_coord.y)).rgb;
+
+    vec3 g = texture(u_color_sampler, vec2(tex_coord.x - x, tex_coord.y - y)).rgb;
+    vec3 h = texture(u_color_sampler, vec2(tex_coord.x,     tex_coord.y - y)).rgb;
+    vec3 i = texture(u_color_sampler, vec2(tex_coord.x + x, tex_coord.y - y)).rgb;
+
+    vec3 color = e * 4.0;
+    color += (b + d + f + h) * 2.0;
+    color += (a + c + g + i);
+    color *= 1.0 / 16.0;
+
+    fragment_color = vec4(color, 1.0);
+}
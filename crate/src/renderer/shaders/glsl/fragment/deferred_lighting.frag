@@ -0,0 +1,77 @@
+#version 300 es
+
+precision highp float;
+precision highp int;
+
+// one punctual light from the g-buffer, additively blended into the main color
+// drawn once per light, scissored to its range, see renderer/deferred.rs
+
+% INCLUDES_COMMON_MATH %
+% INCLUDES_COMMON_CAMERA %
+% INCLUDES_DEFINES %
+
+// reconstructed from depth in main(), same name as the mesh shader's varying
+// so light.glsl works unchanged
+vec3 v_position;
+
+% INCLUDES_DEPS %
+
+uniform sampler2D u_gbuffer_albedo;
+uniform sampler2D u_gbuffer_normal;
+uniform sampler2D u_gbuffer_orm;
+uniform sampler2D u_gbuffer_depth;
+uniform int u_light_index;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+void main() {
+    float depth = texture(u_gbuffer_depth, tex_coord).r;
+
+    // nothing was drawn here
+    if(depth >= 1.0) {
+        discard;
+    }
+
+    vec4 position = camera.view_projection_inverse * vec4(vec3(tex_coord, depth) * 2.0 - 1.0, 1.0);
+    v_position = position.xyz / position.w;
+
+    vec4 albedo = texture(u_gbuffer_albedo, tex_coord);
+    vec4 normal = texture(u_gbuffer_normal, tex_coord);
+    vec4 orm = texture(u_gbuffer_orm, tex_coord);
+
+    // same as get_material() for metallic-roughness
+    Material material;
+    material.base_color = vec4(albedo.rgb, 1.0);
+    material.specular_weight = albedo.a;
+    material.perceptual_roughness = orm.g;
+    material.alpha_roughness = orm.g * orm.g;
+    material.metallic = orm.b;
+    material.ior = orm.a;
+    // no KHR_materials_specular color here, those meshes are drawn forward (see renderer/deferred.rs)
+    material.f0 = mix(vec3(pow((orm.a - 1.0) / (orm.a + 1.0), 2.0)), albedo.rgb, orm.b);
+    material.f90 = vec3(1.0);
+    material.c_diff = mix(albedo.rgb, vec3(0), orm.b);
+
+    NormalInfo normal_info;
+    normal_info.normal = normalize(normal.xyz);
+    normal_info.geom_normal = normal_info.normal;
+    normal_info.view = normalize(camera.position.xyz - v_position);
+
+    LightOutput light_output = get_light_output();
+    light_output.f_diffuse = vec3(0.0);
+    light_output.f_specular = vec3(0.0);
+
+    float shadow = 1.0;
+    #ifdef SHADOWS
+        // normal.w is whether the mesh is a shadow receiver
+        shadow = mix(1.0, get_shadow(u_light_index, normal_info), normal.w);
+    #endif
+
+    // not in the g-buffer, and unused without IRIDESCENCE
+    Iridescence iridescence;
+
+    apply_light_output(material, normal_info, iridescence, get_light(u_light_index), light_output, shadow);
+
+    fragment_color = vec4(light_output.f_diffuse + light_output.f_specular, 1.0);
+}
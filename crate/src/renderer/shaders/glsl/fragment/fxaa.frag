@@ -0,0 +1,65 @@
+#version 300 es
+precision highp float;
+
+// FXAA, the simplified "console" variant
+// see: https://developer.download.nvidia.com/assets/gamedev/files/sdk/11/FXAA_WhitePaper.pdf
+//
+// runs after tonemapping, since the edge detection wants perceptual luma
+// and relies on bilinear filtering of the input
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+#define FXAA_SPAN_MAX 8.0
+
+uniform sampler2D u_color_sampler;
+uniform vec2 u_resolution;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+void main() {
+    vec2 texel = 1.0 / u_resolution;
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+
+    vec4 color_m = texture(u_color_sampler, tex_coord);
+    vec3 rgb_nw = texture(u_color_sampler, tex_coord + vec2(-1.0, -1.0) * texel).rgb;
+    vec3 rgb_ne = texture(u_color_sampler, tex_coord + vec2(1.0, -1.0) * texel).rgb;
+    vec3 rgb_sw = texture(u_color_sampler, tex_coord + vec2(-1.0, 1.0) * texel).rgb;
+    vec3 rgb_se = texture(u_color_sampler, tex_coord + vec2(1.0, 1.0) * texel).rgb;
+
+    float luma_nw = dot(rgb_nw, luma);
+    float luma_ne = dot(rgb_ne, luma);
+    float luma_sw = dot(rgb_sw, luma);
+    float luma_se = dot(rgb_se, luma);
+    float luma_m = dot(color_m.rgb, luma);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 dir = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        ((luma_nw + luma_sw) - (luma_ne + luma_se))
+    );
+
+    float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * (0.25 * FXAA_REDUCE_MUL), FXAA_REDUCE_MIN);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+
+    dir = min(vec2(FXAA_SPAN_MAX), max(vec2(-FXAA_SPAN_MAX), dir * rcp_dir_min)) * texel;
+
+    vec3 rgb_a = 0.5 * (
+        texture(u_color_sampler, tex_coord + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(u_color_sampler, tex_coord + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+        texture(u_color_sampler, tex_coord + dir * -0.5).rgb +
+        texture(u_color_sampler, tex_coord + dir * 0.5).rgb
+    );
+
+    float luma_b = dot(rgb_b, luma);
+
+    if (luma_b < luma_min || luma_b > luma_max) {
+        fragment_color = vec4(rgb_a, color_m.a);
+    } else {
+        fragment_color = vec4(rgb_b, color_m.a);
+    }
+}
@@ -0,0 +1,354 @@
+#version 300 es
+
+precision highp float;
+
+// adapted from https://github.com/KhronosGroup/glTF-Sample-Viewer/blob/master/source/shaders/ibl_filtering.frag
+// used both for prefiltering the environment cubemaps (one face/mip at a time)
+// and for generating the brdf lookup tables
+
+#define MATH_PI 3.1415926535897932384626433832795
+
+#define DISTRIBUTION_LAMBERTIAN 0
+#define DISTRIBUTION_GGX 1
+#define DISTRIBUTION_CHARLIE 2
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+uniform samplerCube u_cubemap;
+uniform int u_current_face;
+uniform int u_distribution;
+uniform int u_sample_count;
+uniform int u_is_generating_lut;
+uniform float u_roughness;
+uniform float u_width;
+uniform float u_lod_bias;
+
+struct MicrofacetSample {
+    float pdf;
+    float cos_theta;
+    float sin_theta;
+    float phi;
+};
+
+float saturate(float v) {
+    return clamp(v, 0.0, 1.0);
+}
+
+// same face layout as panorama_to_cubemap
+vec3 uv_to_xyz(int face, vec2 uv)
+{
+    if(face == 0)
+        return vec3(     1.0,   uv.y,    -uv.x);
+    else if(face == 1)
+        return vec3(    -1.0,   uv.y,     uv.x);
+    else if(face == 2)
+        return vec3(   +uv.x,   -1.0,    +uv.y);
+    else if(face == 3)
+        return vec3(   +uv.x,    1.0,    -uv.y);
+    else if(face == 4)
+        return vec3(   +uv.x,   uv.y,     1.0);
+    else
+        return vec3(   -uv.x,  +uv.y,    -1.0);
+}
+
+// http://holger.dammertz.org/stuff/notes_HammersleyOnHemisphere.html
+float radical_inverse_vdc(uint bits)
+{
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return float(bits) * 2.3283064365386963e-10; // / 0x100000000
+}
+
+vec2 hammersley_2d(int i, int n) {
+    return vec2(float(i)/float(n), radical_inverse_vdc(uint(i)));
+}
+
+mat3 generate_tbn(vec3 normal)
+{
+    vec3 bitangent = vec3(0.0, 1.0, 0.0);
+
+    float n_dot_up = dot(normal, vec3(0.0, 1.0, 0.0));
+    float epsilon = 0.0000001;
+    if (1.0 - abs(n_dot_up) <= epsilon)
+    {
+        // Sampling +Y or -Y, so we need a more robust bitangent.
+        bitangent = (n_dot_up > 0.0) ? vec3(0.0, 0.0, 1.0) : vec3(0.0, 0.0, -1.0);
+    }
+
+    vec3 tangent = normalize(cross(bitangent, normal));
+    bitangent = cross(normal, tangent);
+
+    return mat3(tangent, bitangent, normal);
+}
+
+float d_ggx(float n_dot_h, float roughness) {
+    float a = n_dot_h * roughness;
+    float k = roughness / (1.0 - n_dot_h * n_dot_h + a * a);
+    return k * k * (1.0 / MATH_PI);
+}
+
+float d_charlie(float sheen_roughness, float n_dot_h)
+{
+    sheen_roughness = max(sheen_roughness, 0.000001); //clamp (0,1]
+    float inv_r = 1.0 / sheen_roughness;
+    float cos2h = n_dot_h * n_dot_h;
+    float sin2h = 1.0 - cos2h;
+    return (2.0 + inv_r) * pow(sin2h, inv_r * 0.5) / (2.0 * MATH_PI);
+}
+
+MicrofacetSample sample_ggx(vec2 xi, float roughness)
+{
+    MicrofacetSample ggx;
+
+    // evaluate sampling equations
+    float alpha = roughness * roughness;
+    ggx.cos_theta = saturate(sqrt((1.0 - xi.y) / (1.0 + (alpha*alpha - 1.0) * xi.y)));
+    ggx.sin_theta = sqrt(1.0 - ggx.cos_theta * ggx.cos_theta);
+    ggx.phi = 2.0 * MATH_PI * xi.x;
+
+    // evaluate GGX pdf (for half vector)
+    ggx.pdf = d_ggx(ggx.cos_theta, alpha);
+
+    // Apply the Jacobian to obtain a pdf that is parameterized by l
+    // see https://bruop.github.io/ibl/
+    // Typically you'd have the following:
+    // float pdf = D_GGX(NoH, roughness) * NoH / (4.0 * VoH);
+    // but since V = N => VoH == NoH
+    ggx.pdf /= 4.0;
+
+    return ggx;
+}
+
+MicrofacetSample sample_charlie(vec2 xi, float roughness)
+{
+    MicrofacetSample charlie;
+
+    float alpha = roughness * roughness;
+    charlie.sin_theta = pow(xi.y, alpha / (2.0*alpha + 1.0));
+    charlie.cos_theta = sqrt(1.0 - charlie.sin_theta * charlie.sin_theta);
+    charlie.phi = 2.0 * MATH_PI * xi.x;
+
+    // evaluate Charlie pdf (for half vector)
+    charlie.pdf = d_charlie(alpha, charlie.cos_theta);
+
+    // Apply the Jacobian to obtain a pdf that is parameterized by l
+    charlie.pdf /= 4.0;
+
+    return charlie;
+}
+
+MicrofacetSample sample_lambertian(vec2 xi, float roughness)
+{
+    MicrofacetSample lambertian;
+
+    // Cosine weighted hemisphere sampling
+    // http://www.pbr-book.org/3ed-2018/Monte_Carlo_Integration/2D_Sampling_with_Multidimensional_Transformations.html#Cosine-WeightedHemisphereSampling
+    lambertian.cos_theta = sqrt(1.0 - xi.y);
+    lambertian.sin_theta = sqrt(xi.y); // equivalent to `sqrt(1.0 - cos_theta*cos_theta)`;
+    lambertian.phi = 2.0 * MATH_PI * xi.x;
+
+    lambertian.pdf = lambertian.cos_theta / MATH_PI; // evaluation for solid angle, therefore drop the sin_theta
+
+    return lambertian;
+}
+
+// returns the sample direction in xyz and the pdf in w
+vec4 get_importance_sample(int sample_index, vec3 n, float roughness)
+{
+    // generate a quasi monte carlo point in the unit square [0.1)^2
+    vec2 xi = hammersley_2d(sample_index, u_sample_count);
+
+    MicrofacetSample importance_sample;
+
+    // generate the points on the hemisphere with a fitting mapping for
+    // the distribution (e.g. lambertian uses a cosine importance)
+    if(u_distribution == DISTRIBUTION_LAMBERTIAN)
+    {
+        importance_sample = sample_lambertian(xi, roughness);
+    }
+    else if(u_distribution == DISTRIBUTION_GGX)
+    {
+        // Trowbridge-Reitz / GGX microfacet model (Walter et al)
+        // https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.html
+        importance_sample = sample_ggx(xi, roughness);
+    }
+    else
+    {
+        importance_sample = sample_charlie(xi, roughness);
+    }
+
+    // transform the hemisphere sample to the normal coordinate frame
+    // i.e. rotate the hemisphere to the normal direction
+    vec3 local_space_direction = normalize(vec3(
+        importance_sample.sin_theta * cos(importance_sample.phi),
+        importance_sample.sin_theta * sin(importance_sample.phi),
+        importance_sample.cos_theta
+    ));
+    mat3 tbn = generate_tbn(n);
+    vec3 direction = tbn * local_space_direction;
+
+    return vec4(direction, importance_sample.pdf);
+}
+
+// Mipmap Filtered Samples (GPU Gems 3, 20.4)
+// https://developer.nvidia.com/gpugems/gpugems3/part-iii-rendering/chapter-20-gpu-based-importance-sampling
+float compute_lod(float pdf)
+{
+    // https://cgg.mff.cuni.cz/~jaroslav/papers/2007-sketch-fis/Final_sap_0073.pdf
+    return 0.5 * log2(6.0 * u_width * u_width / (float(u_sample_count) * pdf));
+}
+
+vec3 filter_color(vec3 n)
+{
+    vec3 color = vec3(0.0);
+    float weight = 0.0;
+
+    for(int i = 0; i < u_sample_count; ++i)
+    {
+        vec4 importance_sample = get_importance_sample(i, n, u_roughness);
+
+        vec3 h = importance_sample.xyz;
+        float pdf = importance_sample.w;
+
+        // mipmap filtered samples (GPU Gems 3, 20.4)
+        float lod = compute_lod(pdf) + u_lod_bias;
+
+        if(u_distribution == DISTRIBUTION_LAMBERTIAN)
+        {
+            // sample lambertian at a lower resolution to avoid fireflies
+            vec3 lambertian = textureLod(u_cubemap, h, lod).rgb;
+
+            // the pdf cancels out the cos_theta / PI of the lambertian brdf
+            color += lambertian;
+        }
+        else
+        {
+            // Note: reflect takes incident vector.
+            vec3 v = n;
+            vec3 l = normalize(reflect(-v, h));
+            float n_dot_l = dot(n, l);
+
+            if (n_dot_l > 0.0)
+            {
+                if(u_roughness == 0.0)
+                {
+                    // without this the roughness=0 lod is too high
+                    lod = u_lod_bias;
+                }
+                vec3 sample_color = textureLod(u_cubemap, l, lod).rgb;
+                color += sample_color * n_dot_l;
+                weight += n_dot_l;
+            }
+        }
+    }
+
+    if(weight != 0.0)
+    {
+        color /= weight;
+    }
+    else
+    {
+        color /= float(u_sample_count);
+    }
+
+    return color;
+}
+
+// From the filament docs. Geometric Shadowing function
+// https://google.github.io/filament/Filament.html#toc4.4.2
+float v_smith_ggx_correlated(float n_dot_v, float n_dot_l, float roughness) {
+    float a2 = pow(roughness, 4.0);
+    float ggxv = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - a2) + a2);
+    float ggxl = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - a2) + a2);
+    return 0.5 / (ggxv + ggxl);
+}
+
+// https://github.com/google/filament/blob/master/shaders/src/brdf.fs#L136
+float v_ashikhmin(float n_dot_l, float n_dot_v)
+{
+    return clamp(1.0 / (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v)), 0.0, 1.0);
+}
+
+// ggx goes into rg (scale and bias for f0), charlie into b
+vec3 lut(float n_dot_v, float roughness)
+{
+    // Compute spherical view vector: (sin(phi), 0, cos(phi))
+    vec3 v = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    // The macro surface normal just points up.
+    vec3 n = vec3(0.0, 0.0, 1.0);
+
+    // To make the LUT independant from the material's F0, which is part of the Fresnel term
+    // when substituted by Schlick's approximation, we factor it out of the integral,
+    // yielding to the form: F0 * I1 + I2
+    // I1 and I2 are slighlty different in the Fresnel term, but both only depend on
+    // NoL and roughness, so they are both numerically integrated and written into two
+    // textures channels.
+    float a = 0.0;
+    float b = 0.0;
+    float c = 0.0;
+
+    for(int i = 0; i < u_sample_count; ++i)
+    {
+        // Importance sampling, depending on the distribution.
+        vec3 h = get_importance_sample(i, n, roughness).xyz;
+        vec3 l = normalize(reflect(-v, h));
+
+        float n_dot_l = saturate(l.z);
+        float n_dot_h = saturate(h.z);
+        float v_dot_h = saturate(dot(v, h));
+        if (n_dot_l > 0.0)
+        {
+            if (u_distribution == DISTRIBUTION_GGX)
+            {
+                // LUT for GGX distribution.
+
+                // Taken from: https://bruop.github.io/ibl
+                // Shadertoy: https://www.shadertoy.com/view/3lXXDB
+                // Terms besides V are from the GGX PDF we're dividing by.
+                float v_pdf = v_smith_ggx_correlated(n_dot_v, n_dot_l, roughness) * v_dot_h * n_dot_l / n_dot_h;
+                float fc = pow(1.0 - v_dot_h, 5.0);
+                a += (1.0 - fc) * v_pdf;
+                b += fc * v_pdf;
+            }
+
+            if (u_distribution == DISTRIBUTION_CHARLIE)
+            {
+                // LUT for Charlie distribution.
+                float sheen_distribution = d_charlie(roughness, n_dot_h);
+                float sheen_visibility = v_ashikhmin(n_dot_l, n_dot_v);
+                c += sheen_visibility * sheen_distribution * n_dot_l * v_dot_h;
+            }
+        }
+    }
+
+    // The PDF is simply pdf(v, h) -> NDF * <nh>.
+    // To parametrize the PDF over l, use the Jacobian transform, yielding to: pdf(v, l) -> NDF * <nh> / 4<vh>
+    // Since the BRDF divide through the PDF to be normalized, the 4 can be pulled out of the integral.
+    return vec3(4.0 * a, 4.0 * b, 4.0 * 2.0 * MATH_PI * c) / float(u_sample_count);
+}
+
+void main(void)
+{
+    vec3 color = vec3(0.0);
+
+    if(u_is_generating_lut == 0)
+    {
+        vec2 uv = tex_coord * 2.0 - 1.0;
+        vec3 direction = normalize(uv_to_xyz(u_current_face, uv));
+        // the render target is sampled by the hardware cubemap convention
+        direction.y = -direction.y;
+
+        color = filter_color(direction);
+    }
+    else
+    {
+        color = lut(tex_coord.x, tex_coord.y);
+    }
+
+    fragment_color = vec4(color, 1.0);
+}
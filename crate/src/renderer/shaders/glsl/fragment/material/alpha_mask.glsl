@@ -0,0 +1,41 @@
+// for the passes that don't run the full material (shadow depth, picking, ssao prepass)
+// masked meshes still need to cut out the same holes, see AlphaMaskKey
+#ifdef ALPHA_MASK
+    uniform vec4 u_base_color_factor;
+    uniform float u_alpha_cutoff;
+
+    #ifdef BASE_COLOR_UV_MAP
+        uniform sampler2D u_base_color_sampler;
+        in vec2 v_base_color_uv;
+    #endif
+
+    #ifdef BASE_COLOR_UV_TRANSFORM
+        uniform mat3 u_base_color_uv_transform;
+    #endif
+
+    #ifdef VERTEX_COLORS
+        in vec4 v_vertex_color;
+    #endif
+
+    void alpha_mask() {
+        float alpha = u_base_color_factor.a;
+
+        #ifdef BASE_COLOR_UV_MAP
+            vec3 uv = vec3(v_base_color_uv, 1.0);
+            #ifdef BASE_COLOR_UV_TRANSFORM
+                uv = u_base_color_uv_transform * uv;
+            #endif
+            alpha *= texture(u_base_color_sampler, uv.xy).a;
+        #endif
+
+        #ifdef VERTEX_COLORS
+            alpha *= v_vertex_color.a;
+        #endif
+
+        if (alpha < u_alpha_cutoff) {
+            discard;
+        }
+    }
+#else
+    void alpha_mask() {}
+#endif
@@ -68,14 +68,6 @@ struct LightOutput {
 // KHR_lights_punctual extension.
 // see https://github.com/KhronosGroup/glTF/tree/master/extensions/2.0/Khronos/KHR_lights_punctual
 //
-struct UboLight
-{
-    vec4 direction_range;
-    vec4 color_intensity;
-    vec4 position_type;
-    vec4 extra; // inner_cone_cos, outer_cone_cos
-};
-
 struct Light
 {
     vec3 direction;
@@ -21,7 +21,7 @@ uniform mat3 u_glossiness_uv_transform;
 uniform float u_sheen_roughness_factor;
 uniform vec3 u_sheen_color_factor;
 uniform sampler2D u_sheen_color_sampler;
-uniform mat3 u_sheen_color_uv_sampler;
+uniform mat3 u_sheen_color_uv_transform;
 uniform sampler2D u_sheen_roughness_sampler;
 uniform mat3 u_sheen_roughness_uv_transform;
 
@@ -29,7 +29,7 @@ uniform mat3 u_sheen_roughness_uv_transform;
 uniform float u_clearcoat_factor;
 uniform float u_clearcoat_roughness_factor;
 uniform sampler2D u_clearcoat_sampler;
-uniform mat3 u_clearcoat_uv_sampler;
+uniform mat3 u_clearcoat_uv_transform;
 uniform sampler2D u_clearcoat_roughness_sampler;
 uniform mat3 u_clearcoat_roughness_uv_transform;
 uniform sampler2D u_clearcoat_normal_sampler;
@@ -47,17 +47,17 @@ uniform mat3 u_specular_color_uv_transform;
 // Transmission
 uniform float u_transmission_factor;
 uniform sampler2D u_transmission_sampler;
-uniform mat3 u_transmission_uv_sampler;
+uniform mat3 u_transmission_uv_transform;
 uniform sampler2D u_transmission_framebuffer_sampler;
 uniform ivec2 u_transmission_framebuffer_size;
 uniform ivec2 u_screen_size;
 
 // Volume
 uniform float u_thickness_factor;
-uniform vec3 u_attentuation_color;
-uniform float u_attenuation_distance;
-uniform sampler2D u_thickness_sampler;
-uniform mat3 u_thickness_uv_transform;
+uniform vec3 u_attenuation_color;
+uniform float u_attenuation_distance; // 0.0 is infinite
+uniform sampler2D u_volume_thickness_sampler;
+uniform mat3 u_volume_thickness_uv_transform;
 
 // Iridescence
 uniform float u_iridescence_factor;
@@ -67,7 +67,7 @@ uniform float u_iridescence_thickness_max;
 uniform sampler2D u_iridescence_sampler;
 uniform mat3 u_iridescence_uv_transform;
 uniform sampler2D u_iridescence_thickness_sampler;
-uniform mat3 u_iridedscence_thickness_uv_transform;
+uniform mat3 u_iridescence_thickness_uv_transform;
 
 
 // PBR Next IOR
@@ -101,11 +101,12 @@ uniform mat3 u_emissive_uv_transform;
 // Ambient Occlusion
 uniform sampler2D u_occlusion_sampler;
 uniform float u_occlusion_strength;
-uniform mat3 u_occlusion_transform;
-
-#ifdef MAX_LIGHTS
-layout (std140) uniform ubo_lights {
-    float active_len; // has hidden padding! 
-    UboLight light[MAX_LIGHTS];
-} u_lights;
+uniform mat3 u_occlusion_uv_transform;
+
+// Punctual lights, see light.rs and light/cluster.rs
+#ifdef LIGHTS
+uniform highp sampler2D u_light_data; // 4 texels per light, LIGHTS_PER_ROW lights per row
+uniform highp sampler2D u_light_clusters; // r: offset into u_light_indices, g: count
+uniform highp sampler2D u_light_indices;
+uniform highp vec4 u_light_cluster_params; // x, y: clusters per pixel, z: near, w: depth slices per log depth
 #endif
@@ -13,6 +13,10 @@ in vec3 v_position;
     in vec4 v_vertex_color;
 #endif
 
+#ifdef TRANSMISSION
+    in vec3 v_model_scale;
+#endif
+
 // TEXTURES
 #ifdef METALLIC_ROUGHNESS_UV_MAP
     in vec2 v_metallic_roughness_uv;
@@ -1,4 +1,4 @@
-vec4 final_color(Material material, LightOutput light_output) {
+vec4 final_color(Material material, NormalInfo normal_info, LightOutput light_output) {
     light_output.f_emissive = u_emissive_factor;
 
     #ifdef EMISSIVE_STRENGTH
@@ -15,9 +15,9 @@ vec4 final_color(Material material, LightOutput light_output) {
     vec3 clearcoat_fresnel = vec3(0);
 
     #ifdef CLEARCOAT
-        clearcoat_factor = materialInfo.clearcoat_factor;
-        clearcoat_fresnel = F_Schlick(material.clearcoat_F0, material.clearcoat_F90, clampedDot(material._clearcoat_normal, v));
-        f_clearcoat = f_clearcoat * clearcoat_factor;
+        clearcoat_factor = material.clearcoat_factor;
+        clearcoat_fresnel = F_Schlick(material.clearcoat_F0, material.clearcoat_F90, clamped_dot(material.clearcoat_normal, normal_info.view));
+        light_output.f_clearcoat *= clearcoat_factor;
     #endif
 
     #ifdef TRANSMISSION
@@ -1,4 +1,7 @@
 #ifdef IRIDESCENCE
+    float sq(float x) { return x * x; }
+    vec3 sq(vec3 x) { return x * x; }
+
     // XYZ to sRGB color space
     const mat3 XYZ_TO_REC709 = mat3(
          3.2404542, -0.9692660,  0.0556434,
@@ -27,13 +30,13 @@
     // Ref: https://belcour.github.io/blog/research/2017/05/01/brdf-thin-film.html
     // Evaluation XYZ sensitivity curves in Fourier space
     vec3 evalSensitivity(float OPD, vec3 shift) {
-        float phase = 2.0 * M_PI * OPD * 1.0e-9;
+        float phase = 2.0 * PI * OPD * 1.0e-9;
         vec3 val = vec3(5.4856e-13, 4.4201e-13, 5.2481e-13);
         vec3 pos = vec3(1.6810e+06, 1.7953e+06, 2.2084e+06);
         vec3 var = vec3(4.3278e+09, 9.3046e+09, 6.6121e+09);
 
-        vec3 xyz = val * sqrt(2.0 * M_PI * var) * cos(pos * phase + shift) * exp(-sq(phase) * var);
-        xyz.x += 9.7470e-14 * sqrt(2.0 * M_PI * 4.5282e+09) * cos(2.2399e+06 * phase + shift[0]) * exp(-4.5282e+09 * sq(phase));
+        vec3 xyz = val * sqrt(2.0 * PI * var) * cos(pos * phase + shift) * exp(-sq(phase) * var);
+        xyz.x += 9.7470e-14 * sqrt(2.0 * PI * 4.5282e+09) * cos(2.2399e+06 * phase + shift[0]) * exp(-4.5282e+09 * sq(phase));
         xyz /= 1.0685e-7;
 
         vec3 srgb = XYZ_TO_REC709 * xyz;
@@ -62,17 +65,17 @@
         float R21 = R12;
         float T121 = 1.0 - R12;
         float phi12 = 0.0;
-        if (iridescenceIor < outsideIOR) phi12 = M_PI;
-        float phi21 = M_PI - phi12;
+        if (iridescenceIor < outsideIOR) phi12 = PI;
+        float phi21 = PI - phi12;
 
         // Second interface
         vec3 baseIOR = Fresnel0ToIor(clamp(baseF0, 0.0, 0.9999)); // guard against 1.0
         vec3 R1 = IorToFresnel0(baseIOR, iridescenceIor);
         vec3 R23 = F_Schlick(R1, cosTheta2);
         vec3 phi23 = vec3(0.0);
-        if (baseIOR[0] < iridescenceIor) phi23[0] = M_PI;
-        if (baseIOR[1] < iridescenceIor) phi23[1] = M_PI;
-        if (baseIOR[2] < iridescenceIor) phi23[2] = M_PI;
+        if (baseIOR[0] < iridescenceIor) phi23[0] = PI;
+        if (baseIOR[1] < iridescenceIor) phi23[1] = PI;
+        if (baseIOR[2] < iridescenceIor) phi23[2] = PI;
 
         // Phase shift
         float OPD = 2.0 * iridescenceIor * thinFilmThickness * cosTheta2;
@@ -108,10 +111,10 @@ Iridescence get_iridescence(Material material, NormalInfo normal_info) {
         iridescence.fresnel = material.f0;
         iridescence.f0 = material.f0;
 
-        if (material.iridescenceFactor > 0.0) {
+        if (material.iridescence_factor > 0.0) {
             float NdotV = normal_info.n_dot_v;
-            iridescence.fresnel = evalIridescence(1.0, material.iridescenceIor, NdotV, material.iridescence_thickness, material.f0);
-            iridescence.f0 = Schlick_to_F0(iridescenceFresnel, NdotV);
+            iridescence.fresnel = evalIridescence(1.0, material.iridescence_ior, NdotV, material.iridescence_thickness, material.f0);
+            iridescence.f0 = Schlick_to_F0(iridescence.fresnel, NdotV);
         }
     #endif
 
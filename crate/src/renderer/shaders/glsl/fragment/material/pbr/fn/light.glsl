@@ -2,12 +2,18 @@
 LightOutput get_light_output() {
     LightOutput light_output;
 
+    light_output.f_specular = vec3(0.0);
+    light_output.f_diffuse = vec3(0.0);
+    light_output.f_emissive = vec3(0.0);
+    light_output.f_clearcoat = vec3(0.0);
+    light_output.f_sheen = vec3(0.0);
+    light_output.f_transmission = vec3(0.0);
     light_output.albedo_sheen_scaling = 1.0;
 
     return light_output;
 }
 
-#ifdef MAX_LIGHTS
+#ifdef LIGHTS
     // https://github.com/KhronosGroup/glTF/blob/master/extensions/2.0/Khronos/KHR_lights_punctual/README.md#range-property
     float getRangeAttenuation(float range, float distance)
     {
@@ -89,57 +95,31 @@ LightOutput get_light_output() {
     }
 
 
-    // Compute attenuated light as it travels through a volume.
-    vec3 applyVolumeAttenuation(vec3 radiance, float transmissionDistance, vec3 attenuationColor, float attenuationDistance)
-    {
-        if (attenuationDistance == 0.0)
-        {
-            // Attenuation distance is +∞ (which we indicate by zero), i.e. the transmitted color is not attenuated at all.
-            return radiance;
-        }
-        else
-        {
-            // Compute light attenuation using Beer's law.
-            vec3 attenuationCoefficient = -log(attenuationColor) / attenuationDistance;
-            vec3 transmittance = exp(-attenuationCoefficient * transmissionDistance); // Beer's law
-            return transmittance * radiance;
-        }
-    }
-
+    // see Lights::write_* for the layout
+    Light get_light(int index) {
+        ivec2 texel = ivec2((index % LIGHTS_PER_ROW) * 4, index / LIGHTS_PER_ROW);
+        highp vec4 direction_range = texelFetch(u_light_data, texel, 0);
+        highp vec4 color_intensity = texelFetch(u_light_data, texel + ivec2(1, 0), 0);
+        highp vec4 position_type = texelFetch(u_light_data, texel + ivec2(2, 0), 0);
+        highp vec4 extra = texelFetch(u_light_data, texel + ivec2(3, 0), 0);
 
-    vec3 getVolumeTransmissionRay(vec3 n, vec3 v, float thickness, float ior, mat4 modelMatrix)
-    {
-        // Direction of refracted light.
-        vec3 refractionVector = refract(-v, normalize(n), 1.0 / ior);
-
-        // Compute rotation-independant scaling of the model matrix.
-        vec3 modelScale;
-        modelScale.x = length(vec3(modelMatrix[0].xyz));
-        modelScale.y = length(vec3(modelMatrix[1].xyz));
-        modelScale.z = length(vec3(modelMatrix[2].xyz));
-
-        // The thickness is specified in local space.
-        return normalize(refractionVector) * thickness * modelScale;
-    }
-
-    Light convert_ubo_light(UboLight ubo_light) {
         Light light;
 
-        light.direction = ubo_light.direction_range.xyz;
-        light.range = ubo_light.direction_range.z;
+        light.direction = direction_range.xyz;
+        light.range = direction_range.w;
 
-        light.color = ubo_light.color_intensity.rgb;
-        light.intensity = ubo_light.color_intensity.a;
+        light.color = color_intensity.rgb;
+        light.intensity = color_intensity.a;
 
-        light.position = ubo_light.position_type.xyz;
-        light.type = int(ubo_light.position_type.w);
+        light.position = position_type.xyz;
+        light.type = int(position_type.w);
 
-        light.inner_cone_cos = ubo_light.extra[0];
-        light.outer_cone_cos = ubo_light.extra[1];
+        light.inner_cone_cos = extra[0];
+        light.outer_cone_cos = extra[1];
         return light;
     }
 
-    void apply_light_output(Material material, NormalInfo normal_info, Light light, inout LightOutput light_output, float enabled) {
+    void apply_light_output(Material material, NormalInfo normal_info, Iridescence iridescence, Light light, inout LightOutput light_output, float enabled) {
         vec3 v = normal_info.view;
         vec3 n = normal_info.normal;
 
@@ -167,39 +147,37 @@ LightOutput get_light_output() {
         // https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#acknowledgments AppendixB
         vec3 intensity = getLightIntensity(light, point_to_light);
         #ifdef IRIDESCENCE
-            light_output.f_diffuse += gate * (intensity * NdotL *  BRDF_lambertianIridescence(material.f0, material.f90, iridescenceFresnel, material.iridescenceFactor, material.c_diff, material.specular_weight, VdotH));
-            light_output.f_specular += gate * (intensity * NdotL * BRDF_specularGGXIridescence(material.f0, material.f90, iridescenceFresnel, material.alpha_roughness, material.iridescenceFactor, material.specular_weight, VdotH, NdotL, NdotV, NdotH));
+            light_output.f_diffuse += gate * (intensity * NdotL *  BRDF_lambertianIridescence(material.f0, material.f90, iridescence.fresnel, material.iridescence_factor, material.c_diff, material.specular_weight, VdotH));
+            light_output.f_specular += gate * (intensity * NdotL * BRDF_specularGGXIridescence(material.f0, material.f90, iridescence.fresnel, material.alpha_roughness, material.iridescence_factor, material.specular_weight, VdotH, NdotL, NdotV, NdotH));
         #else
             light_output.f_diffuse += gate * (intensity * NdotL *  BRDF_lambertian(material.f0, material.f90, material.c_diff, material.specular_weight, VdotH));
             light_output.f_specular += gate * (intensity * NdotL * BRDF_specularGGX(material.f0, material.f90, material.alpha_roughness, material.specular_weight, VdotH, NdotL, NdotV, NdotH));
         #endif
 
+        // the albedo scaling for the base layer under the sheen is only from the view angle, see set_ibl()
         #ifdef SHEEN
-            light_output.f_sheen += gate * (intensity * getPunctualRadianceSheen(material.sheenColorFactor, material.sheenRoughnessFactor, NdotL, NdotV, NdotH));
-            light_output.albedo_sheen_scaling = gate * min(
-                1.0 - max3(material.sheenColorFactor) * albedoSheenScalingLUT(NdotV, material.sheenRoughnessFactor),
-                1.0 - max3(material.sheenColorFactor) * albedoSheenScalingLUT(NdotL, material.sheenRoughnessFactor)
-            );
+            light_output.f_sheen += gate * (intensity * getPunctualRadianceSheen(material.sheen_color_factor, material.sheen_roughness_factor, NdotL, NdotV, NdotH));
         #endif
 
         #ifdef CLEARCOAT
-            light_output.f_clearcoat += gate * (intensity * getPunctualRadianceClearCoat(material.clearcoatNormal, v, l, h, VdotH, material.clearcoatF0, material.clearcoatF90, material.clearcoatRoughness));
+            light_output.f_clearcoat += gate * (intensity * getPunctualRadianceClearCoat(material.clearcoat_normal, v, l, h, VdotH, material.clearcoat_F0, material.clearcoat_F90, material.clearcoat_roughness));
         #endif
 
         gate = vec3(enabled);
         // BDTF
+        // the volume functions are in transmission.glsl
         #ifdef TRANSMISSION
             // If the light ray travels through the geometry, use the point it exits the geometry again.
             // That will change the angle to the light source, if the material refracts the light ray.
-            vec3 transmissionRay = getVolumeTransmissionRay(n, v, material.thickness, material.ior, u_ModelMatrix);
+            vec3 transmissionRay = getVolumeTransmissionRay(n, v, material.volume_thickness, material.ior);
             point_to_light -= transmissionRay;
             l = normalize(point_to_light);
 
-            vec3 intensity = getLightIntensity(light, point_to_light);
+            intensity = getLightIntensity(light, point_to_light);
             vec3 transmittedLight = intensity * getPunctualRadianceTransmission(n, v, l, material.alpha_roughness, material.f0, material.f90, material.c_diff, material.ior);
 
             #ifdef VOLUME
-                transmittedLight = applyVolumeAttenuation(transmittedLight, length(transmissionRay), material.attenuationColor, material.attenuationDistance);
+                transmittedLight = applyVolumeAttenuation(transmittedLight, length(transmissionRay), material.attenuation_color, material.attenuation_distance);
             #endif
 
             light_output.f_transmission += gate * transmittedLight;
@@ -207,3 +185,119 @@ LightOutput get_light_output() {
 
     }
 #endif
+
+// Shadow maps, see light/shadow.rs
+// the samplers themselves are declared in fragment.rs (one per map, since they can't be dynamically indexed)
+#ifdef SHADOWS
+    uniform mat4 u_shadow_view_projection[MAX_SHADOW_MAPS];
+    uniform vec4 u_shadow_params[MAX_SHADOW_MAPS]; // x: light index, y: view depth start, z: view depth end, w: normal bias
+    uniform float u_shadow_bias;
+    uniform float u_shadow_texel_size;
+    uniform float u_shadow_receiver;
+    uniform vec4 u_point_shadow_params[MAX_POINT_SHADOW_MAPS]; // x: light index, y: near, z: far, w: normal bias
+    uniform vec3 u_point_shadow_positions[MAX_POINT_SHADOW_MAPS];
+
+    // 3x3 taps, each of which is already bilinear-filtered by the hardware comparison
+    float sample_shadow_pcf(sampler2DShadow map, highp vec3 coords) {
+        float sum = 0.0;
+        for(int x = -1; x <= 1; x++) {
+            for(int y = -1; y <= 1; y++) {
+                vec2 offset = vec2(float(x), float(y)) * u_shadow_texel_size;
+                sum += texture(map, vec3(coords.xy + offset, coords.z));
+            }
+        }
+        return sum / 9.0;
+    }
+
+    // 1.0 is fully lit
+    float get_shadow_map_factor(sampler2DShadow map, int map_index, int light_index, float view_depth, NormalInfo normal_info) {
+        vec4 params = u_shadow_params[map_index];
+
+        if(int(params.x) != light_index || view_depth < params.y || view_depth >= params.z) {
+            return 1.0;
+        }
+
+        vec3 world_position = v_position + (normal_info.geom_normal * params.w);
+        highp vec4 light_position = u_shadow_view_projection[map_index] * vec4(world_position, 1.0);
+        highp vec3 coords = ((light_position.xyz / light_position.w) * 0.5) + 0.5;
+
+        if(coords.x < 0.0 || coords.x > 1.0 || coords.y < 0.0 || coords.y > 1.0 || coords.z > 1.0) {
+            return 1.0;
+        }
+
+        coords.z -= u_shadow_bias;
+
+        return sample_shadow_pcf(map, coords);
+    }
+
+    // the cubemap faces are all 90 degree perspective projections
+    // so the depth to compare against is from whichever axis dominates
+    float get_point_shadow_map_factor(samplerCubeShadow map, int map_index, int light_index, NormalInfo normal_info) {
+        vec4 params = u_point_shadow_params[map_index];
+
+        if(int(params.x) != light_index) {
+            return 1.0;
+        }
+
+        vec3 world_position = v_position + (normal_info.geom_normal * params.w);
+        vec3 light_to_surface = world_position - u_point_shadow_positions[map_index];
+        vec3 abs_light_to_surface = abs(light_to_surface);
+        float z = max(abs_light_to_surface.x, max(abs_light_to_surface.y, abs_light_to_surface.z));
+
+        float near = params.y;
+        float far = params.z;
+
+        if(z >= far) {
+            return 1.0;
+        }
+
+        highp float depth = (((far + near) / (far - near)) - ((2.0 * far * near) / ((far - near) * z))) * 0.5 + 0.5;
+
+        return texture(map, vec4(light_to_surface, depth - u_shadow_bias));
+    }
+
+    float get_shadow(int light_index, NormalInfo normal_info) {
+        float view_depth = -(camera.view * vec4(v_position, 1.0)).z;
+        float shadow = 1.0;
+
+        % INCLUDES_SHADOW_MAPS %
+
+        return mix(1.0, shadow, u_shadow_receiver);
+    }
+#endif
+
+// The lights in this fragment's cluster, see light/cluster.rs
+// after the shadows, since it needs get_shadow()
+#ifdef LIGHTS
+    ivec3 get_light_cluster() {
+        highp float view_depth = -(camera.view * vec4(v_position, 1.0)).z;
+        highp float near = u_light_cluster_params.z;
+
+        ivec3 cluster = ivec3(
+            int(gl_FragCoord.x * u_light_cluster_params.x),
+            int(gl_FragCoord.y * u_light_cluster_params.y),
+            int(log(max(view_depth, near) / near) * u_light_cluster_params.w)
+        );
+
+        return clamp(cluster, ivec3(0), ivec3(LIGHT_CLUSTERS_X - 1, LIGHT_CLUSTERS_Y - 1, LIGHT_CLUSTERS_Z - 1));
+    }
+
+    void apply_lights(Material material, NormalInfo normal_info, Iridescence iridescence, inout LightOutput light_output) {
+        ivec3 cluster = get_light_cluster();
+        highp vec4 cluster_lights = texelFetch(u_light_clusters, ivec2(cluster.x + (cluster.y * LIGHT_CLUSTERS_X), cluster.z), 0);
+        int offset = int(cluster_lights.r);
+        int count = int(cluster_lights.g);
+
+        for(int i = 0; i < count; i++) {
+            int index = offset + i;
+            int light_index = int(texelFetch(u_light_indices, ivec2(index % LIGHT_INDICES_WIDTH, index / LIGHT_INDICES_WIDTH), 0).r);
+
+            float shadow = 1.0;
+            #ifdef SHADOWS
+                shadow = get_shadow(light_index, normal_info);
+            #endif
+
+            apply_light_output(material, normal_info, iridescence, get_light(light_index), light_output, shadow);
+        }
+    }
+#endif
@@ -5,6 +5,11 @@ void set_material_defaults(inout Material material) {
     // Anything less than 2% is physically impossible and is instead considered to be shadowing. Compare to "Real-Time-Rendering" 4th editon on page 325.
     material.f90 = vec3(1.0);
     material.specular_weight = 1.0;
+    // thin-walled, and no attenuation, unless there's a volume
+    material.transmission_factor = 0.0;
+    material.volume_thickness = 0.0;
+    material.attenuation_color = vec3(1.0);
+    material.attenuation_distance = 0.0;
 }
 
 void set_material_base_color(inout Material material) {
@@ -32,6 +37,13 @@ void set_material_base_color(inout Material material) {
     #endif
 }
 
+#ifdef IOR
+void set_material_ior(inout Material material) {
+    material.ior = u_ior;
+    material.f0 = vec3(pow((u_ior - 1.0) / (u_ior + 1.0), 2.0));
+}
+#endif
+
 #ifdef METALLIC_ROUGHNESS
 void set_material_metallic_roughness(inout Material material) {
     material.metallic = u_metallic_roughness_factors.x;
@@ -51,19 +63,105 @@ void set_material_metallic_roughness(inout Material material) {
 }
 #endif
 
+#ifdef SHEEN
+void set_material_sheen(inout Material material) {
+    material.sheen_color_factor = u_sheen_color_factor;
+    material.sheen_roughness_factor = u_sheen_roughness_factor;
+
+    #ifdef SHEEN_COLOR_UV_MAP
+        material.sheen_color_factor *= texture(u_sheen_color_sampler, get_sheen_color_uv()).rgb;
+    #endif
+
+    #ifdef SHEEN_ROUGHNESS_UV_MAP
+        material.sheen_roughness_factor *= texture(u_sheen_roughness_sampler, get_sheen_roughness_uv()).a;
+    #endif
+}
+#endif
+
+#ifdef CLEARCOAT
+void set_material_clearcoat(inout Material material, NormalInfo normal_info) {
+    material.clearcoat_factor = u_clearcoat_factor;
+    material.clearcoat_roughness = u_clearcoat_roughness_factor;
+    material.clearcoat_F0 = vec3(pow((material.ior - 1.0) / (material.ior + 1.0), 2.0));
+    material.clearcoat_F90 = vec3(1.0);
+
+    #ifdef CLEARCOAT_UV_MAP
+        material.clearcoat_factor *= texture(u_clearcoat_sampler, get_clearcoat_uv()).r;
+    #endif
+
+    #ifdef CLEARCOAT_ROUGHNESS_UV_MAP
+        material.clearcoat_roughness *= texture(u_clearcoat_roughness_sampler, get_clearcoat_roughness_uv()).g;
+    #endif
+
+    // the clearcoat has its own normal map, not the base layer's
+    #ifdef CLEARCOAT_NORMAL_UV_MAP
+        vec3 n = texture(u_clearcoat_normal_sampler, get_clearcoat_normal_uv()).rgb * 2.0 - vec3(1.0);
+        n *= vec3(u_clearcoat_normal_scale, u_clearcoat_normal_scale, 1.0);
+        material.clearcoat_normal = normalize(mat3(normal_info.tangent, normal_info.bitangent, normal_info.geom_normal) * normalize(n));
+    #else
+        material.clearcoat_normal = normal_info.geom_normal;
+    #endif
+
+    material.clearcoat_roughness = clamp(material.clearcoat_roughness, 0.0, 1.0);
+}
+#endif
+
+#ifdef SPECULAR
+void set_material_specular(inout Material material) {
+    vec4 specular_texture = vec4(1.0);
+
+    #ifdef SPECULAR_UV_MAP
+        specular_texture.a = texture(u_specular_sampler, get_specular_uv()).a;
+    #endif
+
+    #ifdef SPECULAR_COLOR_UV_MAP
+        specular_texture.rgb = texture(u_specular_color_sampler, get_specular_color_uv()).rgb;
+    #endif
+
+    // only tints the dielectric part, metals still reflect their base color
+    vec3 dielectric_f0 = vec3(pow((material.ior - 1.0) / (material.ior + 1.0), 2.0));
+    dielectric_f0 = min(dielectric_f0 * u_khr_specular_color_factor * specular_texture.rgb, vec3(1.0));
+
+    material.f0 = mix(dielectric_f0, material.base_color.rgb, material.metallic);
+    material.specular_weight = u_khr_specular_factor * specular_texture.a;
+}
+#endif
+
+#ifdef TRANSMISSION
+void set_material_transmission(inout Material material) {
+    material.transmission_factor = u_transmission_factor;
+
+    #ifdef TRANSMISSION_UV_MAP
+        material.transmission_factor *= texture(u_transmission_sampler, get_transmission_uv()).r;
+    #endif
+}
+#endif
+
+#ifdef VOLUME
+void set_material_volume(inout Material material) {
+    material.volume_thickness = u_thickness_factor;
+    material.attenuation_color = u_attenuation_color;
+    material.attenuation_distance = u_attenuation_distance;
+
+    #ifdef VOLUME_THICKNESS_UV_MAP
+        material.volume_thickness *= texture(u_volume_thickness_sampler, get_volume_thickness_uv()).g;
+    #endif
+}
+#endif
+
 #ifdef IRIDESCENCE
 void set_material_iridescence(inout Material material) {
     material.iridescence_factor = u_iridescence_factor;
     material.iridescence_ior = u_iridescence_ior;
-    material.iridescence_thickness = u_iridescence_thickness_maximum;
+    material.iridescence_thickness = u_iridescence_thickness_max;
 
     #ifdef IRIDESCENCE_UV_MAP
-        info.iridescence_factor *= texture(u_iridescence_sampler, get_iridescence_uv()).r;
+        material.iridescence_factor *= texture(u_iridescence_sampler, get_iridescence_uv()).r;
     #endif
 
     #ifdef IRIDESCENCE_THICKNESS_UV_MAP
         float thickness_sampled = texture(u_iridescence_thickness_sampler, get_iridescence_thickness_uv()).g;
-        float thickness = mix(u_iridescence_thickness_minimum, u_iridescence_thickness_maximum, thickness_sampled);
+        float thickness = mix(u_iridescence_thickness_min, u_iridescence_thickness_max, thickness_sampled);
         material.iridescence_thickness = thickness;
     #endif
 
@@ -0,0 +1,12 @@
+uniform sampler2D u_ssao_sampler;
+
+// screen-space ambient occlusion, see renderer/ssao.rs
+// only for the ambient terms, so must be called before any lights are applied
+void set_ssao(inout LightOutput light_output) {
+    float ao = texture(u_ssao_sampler, gl_FragCoord.xy / vec2(textureSize(u_ssao_sampler, 0))).r;
+
+    light_output.f_diffuse *= ao;
+    light_output.f_specular *= ao;
+    light_output.f_sheen *= ao;
+    light_output.f_clearcoat *= ao;
+}
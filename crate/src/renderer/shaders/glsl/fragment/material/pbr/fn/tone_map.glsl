@@ -61,10 +61,48 @@ vec3 toneMapACES_Hill(vec3 color)
 }
 
 
+// see: https://www.cs.utah.edu/docs/techreports/2002/pdf/UUCS-02-001.pdf
+vec3 toneMapReinhard(vec3 color)
+{
+    return color / (color + vec3(1.0));
+}
+
+
+// Khronos PBR Neutral
+// see: https://github.com/KhronosGroup/ToneMapping/tree/main/PBR_Neutral
+vec3 toneMapKhronosPbrNeutral(vec3 color)
+{
+    const float startCompression = 0.8 - 0.04;
+    const float desaturation = 0.15;
+
+    float x = min(color.r, min(color.g, color.b));
+    float offset = x < 0.08 ? x - 6.25 * x * x : 0.04;
+    color -= offset;
+
+    float peak = max(color.r, max(color.g, color.b));
+    if (peak < startCompression) return color;
+
+    const float d = 1.0 - startCompression;
+    float newPeak = 1.0 - d * d / (peak + d - startCompression);
+    color *= newPeak / peak;
+
+    float g = 1.0 - 1.0 / (desaturation * (peak - newPeak) + 1.0);
+    return mix(color, newPeak * vec3(1.0), g);
+}
+
+
 vec3 tone_map(vec3 color)
 {
     color *= u_Exposure;
 
+    #ifdef TONEMAP_REINHARD
+        color = toneMapReinhard(color);
+    #endif
+
+    #ifdef TONEMAP_KHRONOS_PBR_NEUTRAL
+        color = toneMapKhronosPbrNeutral(color);
+    #endif
+
     #ifdef TONEMAP_ACES_NARKOWICZ
         color = toneMapACES_Narkowicz(color);
     #endif
@@ -81,5 +119,6 @@ vec3 tone_map(vec3 color)
         color = toneMapACES_Hill(color);
     #endif
 
-    return linear_to_srgb(color);
+    // no-op for the curves that already clamp, but TONEMAP_NONE doesn't
+    return linear_to_srgb(clamp(color, 0.0, 1.0));
 }
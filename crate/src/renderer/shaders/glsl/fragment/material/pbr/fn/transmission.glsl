@@ -0,0 +1,80 @@
+// KHR_materials_transmission and KHR_materials_volume
+// only included with TRANSMISSION, the opaque scene is in u_transmission_framebuffer_sampler (see renderer/transmission.rs)
+
+// Compute attenuated light as it travels through a volume.
+vec3 applyVolumeAttenuation(vec3 radiance, float transmissionDistance, vec3 attenuationColor, float attenuationDistance)
+{
+    if (attenuationDistance == 0.0)
+    {
+        // Attenuation distance is +∞ (which we indicate by zero), i.e. the transmitted color is not attenuated at all.
+        return radiance;
+    }
+    else
+    {
+        // Compute light attenuation using Beer's law.
+        vec3 attenuationCoefficient = -log(attenuationColor) / attenuationDistance;
+        vec3 transmittance = exp(-attenuationCoefficient * transmissionDistance); // Beer's law
+        return transmittance * radiance;
+    }
+}
+
+
+// v_model_scale is the rotation-independent scale of the model matrix, from the vertex shader
+vec3 getVolumeTransmissionRay(vec3 n, vec3 v, float thickness, float ior)
+{
+    // Direction of refracted light.
+    vec3 refractionVector = refract(-v, normalize(n), 1.0 / ior);
+
+    // The thickness is specified in local space.
+    return normalize(refractionVector) * thickness * v_model_scale;
+}
+
+
+vec3 getTransmissionSample(vec2 fragCoord, float roughness, float ior)
+{
+    float framebufferLod = log2(float(u_transmission_framebuffer_size.x)) * apply_ior_to_roughness(roughness, ior);
+    vec3 transmittedLight = textureLod(u_transmission_framebuffer_sampler, fragCoord.xy, framebufferLod).rgb;
+    return transmittedLight;
+}
+
+
+vec3 getVolumeRefraction(vec3 n, vec3 v, float perceptualRoughness, vec3 baseColor, vec3 f0, vec3 f90,
+    vec3 position, float ior, float thickness, vec3 attenuationColor, float attenuationDistance)
+{
+    vec3 transmissionRay = getVolumeTransmissionRay(n, v, thickness, ior);
+    vec3 refractedRayExit = position + transmissionRay;
+
+    // Project refracted vector on the framebuffer, while mapping to normalized device coordinates.
+    vec4 ndcPos = camera.projection * camera.view * vec4(refractedRayExit, 1.0);
+    vec2 refractionCoords = ndcPos.xy / ndcPos.w;
+    refractionCoords += 1.0;
+    refractionCoords /= 2.0;
+
+    // Sample framebuffer to get pixel the refracted ray hits.
+    vec3 transmittedLight = getTransmissionSample(refractionCoords, perceptualRoughness, ior);
+
+    vec3 attenuatedColor = applyVolumeAttenuation(transmittedLight, length(transmissionRay), attenuationColor, attenuationDistance);
+
+    // the part that's reflected instead
+    // the GGX LUT is only bound along with an environment
+    float NdotV = clamped_dot(n, v);
+    #ifdef IBL
+        vec2 brdfSamplePoint = clamp(vec2(NdotV, perceptualRoughness), vec2(0.0, 0.0), vec2(1.0, 1.0));
+        vec2 brdf = texture(u_ggx_lut, brdfSamplePoint).rg;
+        vec3 specularColor = f0 * brdf.x + f90 * brdf.y;
+    #else
+        vec3 specularColor = F_Schlick(f0, f90, NdotV);
+    #endif
+
+    return (1.0 - specularColor) * attenuatedColor * baseColor;
+}
+
+
+void set_transmission(Material material, NormalInfo normal_info, inout LightOutput light_output) {
+    light_output.f_transmission += getVolumeRefraction(
+        normal_info.normal, normal_info.view,
+        material.perceptual_roughness,
+        material.c_diff, material.f0, material.f90,
+        v_position,
+        material.ior, material.volume_thickness, material.attenuation_color, material.attenuation_distance);
+}
@@ -204,7 +204,7 @@ vec2 get_specular_color_uv()
         vec3 uv = vec3(0.0);
     #endif
 
-    #ifdef SHEEN_COLOR_UV_TRANSFORM
+    #ifdef SPECULAR_COLOR_UV_TRANSFORM
         uv = u_specular_color_uv_transform * uv;
     #endif
 
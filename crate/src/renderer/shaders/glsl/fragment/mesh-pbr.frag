@@ -8,7 +8,18 @@ precision highp int;
 % INCLUDES_COMMON_COLOR_SPACE %
 % INCLUDES_MATERIAL_DEPS %
 
-out vec4 fragment_color; 
+#ifdef DEFERRED
+    // the ambient and emissive light, punctual lights are added later from the rest
+    // see renderer/deferred.rs
+    layout(location = 0) out vec4 fragment_color;
+    layout(location = 1) out vec4 gbuffer_albedo;
+    layout(location = 2) out vec4 gbuffer_normal;
+    layout(location = 3) out vec4 gbuffer_orm;
+
+    uniform float u_shadow_receiver;
+#else
+    out vec4 fragment_color; 
+#endif
 
 void main() {
     NormalInfo normal_info = get_normal_info();
@@ -17,20 +28,37 @@ void main() {
     LightOutput light_output = get_light_output();
 
     #ifdef IBL
-        set_ibl(material, iridescence, light_output);
+        set_ibl(material, normal_info, iridescence, light_output);
+    #endif
+
+    // the opaque scene behind, see renderer/transmission.rs
+    #ifdef TRANSMISSION
+        set_transmission(material, normal_info, light_output);
+    #endif
+
+    // quick ambient hack, only when there's no environment to light with
+    #ifndef IBL
+        light_output.f_diffuse = vec3(0.3) * material.c_diff;
+    #endif
+
+    // after the ambient is set, but before the punctual lights
+    float ao = set_ambient_occlusion(light_output);
+
+    #ifdef SSAO
+        set_ssao(light_output);
     #endif
 
-    #ifdef OCCLUSION_UV_MAP
-        float ao = set_ambient_occlusion(light_output);
+    #ifdef LIGHTS
+        apply_lights(material, normal_info, iridescence, light_output);
     #endif
 
-    // quick ambient hack
-    light_output.f_diffuse = vec3(0.3) * material.c_diff;
-    #ifdef MAX_LIGHTS
-        % INCLUDES_LIGHT_MAIN %
+    #ifdef DEFERRED
+        gbuffer_albedo = vec4(material.base_color.rgb, material.specular_weight);
+        gbuffer_normal = vec4(normal_info.normal, u_shadow_receiver);
+        gbuffer_orm = vec4(ao, material.perceptual_roughness, material.metallic, material.ior);
     #endif
 
-    fragment_color = final_color(material, light_output);
+    fragment_color = final_color(material, normal_info, light_output);
 
     //TODO: get rid of this
     //fragment_color = vec4(1.0, 1.0, 1.0, 1.0); 
@@ -41,7 +69,7 @@ void main() {
         fragment_color = vec4(normal_info.normal, 1.0); 
     #endif
     #ifdef DEBUG_OCCLUSION
-        fragment_color = vec(linear_to_srgb(vec3(ao)), 1.0); 
+        fragment_color = vec4(linear_to_srgb(vec3(ao)), 1.0); 
     #endif
 }
 
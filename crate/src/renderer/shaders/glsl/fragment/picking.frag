@@ -0,0 +1,18 @@
+#version 300 es
+
+precision highp float;
+precision highp int;
+
+// index+1 into the picker's entity list, 0 is nothing
+uniform highp uint u_pick_id;
+
+% INCLUDES_ALPHA_MASK %
+
+out highp uint out_pick_id;
+
+void main() {
+    // can't pick through the holes of a masked mesh
+    alpha_mask();
+
+    out_pick_id = u_pick_id;
+}
@@ -0,0 +1,11 @@
+#version 300 es
+
+precision mediump float;
+
+% INCLUDES_ALPHA_MASK %
+
+// depth-only, nothing to write
+// other than masked meshes discarding their cutouts
+void main() {
+    alpha_mask();
+}
@@ -14,6 +14,6 @@ out vec4 outColor;
  
 void main() {
     vec4 t = camera.view_projection_direction_inverse * vec4(v_uv, 0.0, 1.0);
-    outColor = texture(u_sampler, normalize(t.xyz / t.w));
-    outColor = vec4(linear_to_srgb(outColor.rgb), 1.0);
+    // stays linear, it's tonemapped and encoded along with everything else
+    outColor = vec4(texture(u_sampler, normalize(t.xyz / t.w)).rgb, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+#version 300 es
+precision highp float;
+
+// hemisphere SSAO in view space
+// see: https://learnopengl.com/Advanced-Lighting/SSAO
+//
+// instead of a noise texture, the kernel is rotated per-pixel by interleaved gradient noise
+// and the blur pass smooths that out
+
+#define MAX_KERNEL_SIZE 64
+
+uniform sampler2D u_depth_sampler;
+uniform sampler2D u_normal_sampler;
+uniform vec3 u_kernel[MAX_KERNEL_SIZE];
+uniform int u_kernel_size;
+uniform float u_radius;
+uniform float u_bias;
+uniform float u_intensity;
+uniform mat4 u_projection;
+uniform mat4 u_projection_inverse;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+vec3 get_view_position(vec2 uv) {
+    float depth = texture(u_depth_sampler, uv).r;
+    vec4 position = u_projection_inverse * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return position.xyz / position.w;
+}
+
+void main() {
+    // nothing was drawn here
+    if(texture(u_depth_sampler, tex_coord).r >= 1.0) {
+        fragment_color = vec4(1.0);
+        return;
+    }
+
+    vec3 position = get_view_position(tex_coord);
+    vec3 normal = normalize(texture(u_normal_sampler, tex_coord).xyz);
+
+    float angle = 6.28318530718 * fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    vec3 random = vec3(cos(angle), sin(angle), 0.0);
+
+    // gram-schmidt, tangent space around the normal with a random rotation
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+
+    for(int i = 0; i < MAX_KERNEL_SIZE; i++) {
+        if(i >= u_kernel_size) {
+            break;
+        }
+
+        vec3 sample_position = position + (tbn * u_kernel[i]) * u_radius;
+
+        vec4 offset = u_projection * vec4(sample_position, 1.0);
+        vec2 sample_uv = (offset.xy / offset.w) * 0.5 + 0.5;
+
+        float sample_depth = get_view_position(sample_uv).z;
+
+        // fade out occluders that are far outside the radius
+        float range_check = smoothstep(0.0, 1.0, u_radius / abs(position.z - sample_depth));
+        occlusion += (sample_depth >= sample_position.z + u_bias ? 1.0 : 0.0) * range_check;
+    }
+
+    float ao = 1.0 - (occlusion / float(u_kernel_size));
+
+    fragment_color = vec4(vec3(pow(ao, u_intensity)), 1.0);
+}
@@ -0,0 +1,22 @@
+#version 300 es
+precision highp float;
+
+// 4x4 box blur, matches the period of the kernel rotation noise closely enough
+
+uniform sampler2D u_color_sampler;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(u_color_sampler, 0));
+    float result = 0.0;
+
+    for(int x = -2; x < 2; x++) {
+        for(int y = -2; y < 2; y++) {
+            result += texture(u_color_sampler, tex_coord + vec2(float(x), float(y)) * texel).r;
+        }
+    }
+
+    fragment_color = vec4(vec3(result / 16.0), 1.0);
+}
@@ -0,0 +1,32 @@
+#version 300 es
+
+precision highp float;
+
+% INCLUDES_COMMON_CAMERA %
+% INCLUDES_DEFINES %
+% INCLUDES_ALPHA_MASK %
+
+// view-space normals for the SSAO pass, depth comes along for free
+in vec3 v_position;
+#ifdef VARYING_NORMAL
+    in vec3 v_normal;
+#endif
+
+out vec4 fragment_normal;
+
+void main() {
+    alpha_mask();
+
+    #ifdef VARYING_NORMAL
+        vec3 normal = normalize(v_normal);
+        // double-sided, so it should face the camera
+        if(!gl_FrontFacing) {
+            normal = -normal;
+        }
+    #else
+        // flat shading, same as the mesh shader without normals
+        vec3 normal = normalize(cross(dFdx(v_position), dFdy(v_position)));
+    #endif
+
+    fragment_normal = vec4(normalize(mat3(camera.view) * normal), 1.0);
+}
@@ -0,0 +1,17 @@
+#version 300 es
+precision highp float;
+
+// the final resolve from the HDR target to the screen, see renderer/tonemap.rs
+
+% INCLUDES_COMMON_COLOR_SPACE %
+% INCLUDES_TONE_MAP %
+
+uniform sampler2D u_color_sampler;
+
+in vec2 tex_coord;
+out vec4 fragment_color;
+
+void main() {
+    vec4 color = texture(u_color_sampler, tex_coord);
+    fragment_color = vec4(tone_map(color.rgb), clamp(color.a, 0.0, 1.0));
+}
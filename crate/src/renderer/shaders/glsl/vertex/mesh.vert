@@ -16,6 +16,10 @@ precision mediump float;
 
 % INCLUDES_TEXTURE_VARS %
 
+% INCLUDES_INSTANCING_VARS %
+
+% INCLUDES_MODEL_SCALE_VARS %
+
 uniform mat4 u_model;
 
 
@@ -32,22 +36,36 @@ void main() {
 
     % INCLUDES_VERTEX_COLOR_FN %
 
-    % INCLUDES_SKIN_FN %
+    // may be replaced by skinning
+    #ifdef INSTANCING
+        mat4 model = a_instance_model;
+    #else
+        mat4 model = u_model;
+    #endif
 
+    // morph first, then skin
     % INCLUDES_MORPH_FN %
 
+    % INCLUDES_SKIN_FN %
+
     Camera camera = getCamera();
 
-    mat4 mvp = (camera.projection * (camera.view * u_model));
+    mat4 mvp = (camera.projection * (camera.view * model));
 
+    // lighting happens in world space
     #ifdef VARYING_NORMAL 
-        v_normal = normal;
+        v_normal = normalize(transpose(inverse(mat3(model))) * normal);
     #endif
 
     % INCLUDES_ASSIGN_TEXTURE_VARS %
 
     #ifdef VARYING_POSITION
-        v_position = position;
+        v_position = (model * vec4(position, 1.0)).xyz;
+    #endif
+
+    // rotation-independent
+    #ifdef VARYING_MODEL_SCALE
+        v_model_scale = vec3(length(model[0].xyz), length(model[1].xyz), length(model[2].xyz));
     #endif
 
 
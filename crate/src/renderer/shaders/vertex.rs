@@ -4,6 +4,7 @@ use beach_map::{BeachMap, DefaultVersion};
 use rustc_hash::FxHashMap;
 use std::collections::hash_map::Entry;
 use super::{COMMON_CAMERA, COMMON_MATH, ShaderKey};
+use crate::renderer::skin_texture::MAX_SKIN_JOINT_UNIFORMS;
 
 const ENTRY_MESH:&'static str = include_str!("./glsl/vertex/mesh.vert");
 const ENTRY_QUAD_UNIT:&'static str = include_str!("./glsl/vertex/quad-unit.vert");
@@ -67,6 +68,12 @@ pub enum VertexColorSize {
 }
 
 impl ShaderKey {
+    // too many joints for a uniform array are read from the skin texture instead
+    pub(crate) fn set_skin_joints(&mut self, n_joints: usize) {
+        self.n_skin_joints = n_joints as u32;
+        self.skin_texture = n_joints > MAX_SKIN_JOINT_UNIFORMS;
+    }
+
     fn into_vertex_code(&self) -> Result<String> {
         let mut res = ENTRY_MESH
             .replace("% INCLUDES_COMMON_MATH %", COMMON_MATH)
@@ -152,6 +159,26 @@ impl ShaderKey {
             s
         });
 
+        // the volume thickness is in local space, see transmission.glsl
+        res = res.replace("% INCLUDES_MODEL_SCALE_VARS %", &{
+            let mut s = "".to_string();
+            if self.transmission {
+                s.push_str("out vec3 v_model_scale;\n");
+                s.push_str("#define VARYING_MODEL_SCALE\n");
+            }
+            s
+        });
+
+        res = res.replace("% INCLUDES_INSTANCING_VARS %", &{
+            let mut s = "".to_string();
+            // a mat4 attribute takes up 4 locations, one per column
+            if let Some(loc) = self.instance_model_loc {
+                s.push_str(&format!("layout(location={loc}) in mat4 a_instance_model;\n"));
+                s.push_str("#define INSTANCING\n");
+            }
+            s
+        });
+
         res = res.replace("% INCLUDES_MORPH_VARS %", &{
             let weight_len = self.n_morph_target_weights;
             let attribute_len = self.morph_targets.len();
@@ -177,8 +204,31 @@ impl ShaderKey {
         res = res.replace("% INCLUDES_SKIN_VARS %", &{
             let mut s = "".to_string();
 
-            if self.n_skin_joints > 0 {
-                s.push_str(&format!("uniform mat4 u_skin_joint[{}];\n", self.n_skin_joints));
+            if self.skin_texture || self.n_skin_joints > 0 {
+                if self.skin_texture {
+                    s.push_str(r#"
+                        uniform highp sampler2D u_skin_joint_sampler;
+                        uniform int u_skin_joint_row;
+
+                        // 4 texels per joint, one for each column
+                        mat4 get_skin_joint(int index) {
+                            return mat4(
+                                texelFetch(u_skin_joint_sampler, ivec2((index * 4), u_skin_joint_row), 0),
+                                texelFetch(u_skin_joint_sampler, ivec2((index * 4) + 1, u_skin_joint_row), 0),
+                                texelFetch(u_skin_joint_sampler, ivec2((index * 4) + 2, u_skin_joint_row), 0),
+                                texelFetch(u_skin_joint_sampler, ivec2((index * 4) + 3, u_skin_joint_row), 0)
+                            );
+                        }
+                    "#);
+                } else {
+                    s.push_str(&format!(r#"
+                        uniform mat4 u_skin_joint[{}];
+
+                        mat4 get_skin_joint(int index) {{
+                            return u_skin_joint[index];
+                        }}
+                    "#, self.n_skin_joints));
+                }
             }
             for SkinTarget {joint_loc, weight_loc} in self.skin_targets.iter() {
                 s.push_str(&format!("layout(location={joint_loc}) in vec4 a_skin_joint_{joint_loc};\n"));
@@ -218,20 +268,32 @@ impl ShaderKey {
         });
 
         res = res.replace("% INCLUDES_SKIN_FN %", &{
-            // TODO - notice in pdf it's 12
-            //let mut s = format!("uniform float u_joint_mat[{}];\n", u_joint_mat_len);
-            let mut s = "mat4 skin_mat;".to_string();
+            let mut s = "".to_string();
 
-            for SkinTarget {joint_loc, weight_loc} in self.skin_targets.iter() {
-                s.push_str(&format!(r#"
-                    skin_mat = a_skin_weight_{weight_loc}[0] * u_skin_joint[int(a_skin_joint_{joint_loc}[0])]
-                        + a_skin_weight_{weight_loc}[1] * u_skin_joint[int(a_skin_joint_{joint_loc}[1])]
-                        + a_skin_weight_{weight_loc}[2] * u_skin_joint[int(a_skin_joint_{joint_loc}[2])]
-                        + a_skin_weight_{weight_loc}[3] * u_skin_joint[int(a_skin_joint_{joint_loc}[3])];
+            // each JOINTS_n/WEIGHTS_n set adds up to 4 more influences
+            if !self.skin_targets.is_empty() {
+                s.push_str("mat4 skin_mat = mat4(0.0);\n");
+
+                for SkinTarget {joint_loc, weight_loc} in self.skin_targets.iter() {
+                    s.push_str(&format!(r#"
+                        skin_mat += a_skin_weight_{weight_loc}[0] * get_skin_joint(int(a_skin_joint_{joint_loc}[0]))
+                            + a_skin_weight_{weight_loc}[1] * get_skin_joint(int(a_skin_joint_{joint_loc}[1]))
+                            + a_skin_weight_{weight_loc}[2] * get_skin_joint(int(a_skin_joint_{joint_loc}[2]))
+                            + a_skin_weight_{weight_loc}[3] * get_skin_joint(int(a_skin_joint_{joint_loc}[3]));
+                    "#));
+                }
 
+                s.push_str(r#"
                     position = (skin_mat * vec4(position, 1)).xyz;
-                "#));
-                
+                    #ifdef VARYING_NORMAL
+                        normal = mat3(skin_mat) * normal;
+                    #endif
+                "#);
+
+                // the texture joints are already in world space
+                if self.skin_texture {
+                    s.push_str("model = mat4(1.0);\n");
+                }
             }
 
             s
@@ -246,26 +308,10 @@ impl ShaderKey {
                 }
             }
 
-            if self.normal_texture_uv_index.is_some() {
-                s.push_str(r#"
-                    out vec2 v_normal_uv;
-                "#);
-            }
-
-            if self.metallic_roughness_texture_uv_index.is_some() {
-                s.push_str(r#"
-                    out vec2 v_metallic_roughness_uv;
-                "#);
-            }
-            if self.base_color_texture_uv_index.is_some() {
-                s.push_str(r#"
-                    out vec2 v_base_color_uv;
-                "#);
-            }
-            if self.emissive_texture_uv_index.is_some() {
-                s.push_str(r#"
-                    out vec2 v_emissive_uv;
-                "#);
+            for uv in self.texture_uvs() {
+                if uv.uv_index.is_some() {
+                    s.push_str(&format!("out vec2 v_{}_uv;\n", uv.name));
+                }
             }
             
             s
@@ -274,18 +320,10 @@ impl ShaderKey {
         res = res.replace("% INCLUDES_ASSIGN_TEXTURE_VARS %", &{
             let mut s = "".to_string();
 
-            if let Some(index) = self.normal_texture_uv_index {
-                s.push_str(&format!("v_normal_uv = a_tex_coord_{index};\n"));
-            }
-
-            if let Some(index) = self.metallic_roughness_texture_uv_index {
-                s.push_str(&format!("v_metallic_roughness_uv = a_tex_coord_{index};\n"));
-            }
-            if let Some(index) = self.base_color_texture_uv_index {
-                s.push_str(&format!("v_base_color_uv = a_tex_coord_{index};\n"));
-            }
-            if let Some(index) = self.emissive_texture_uv_index {
-                s.push_str(&format!("v_emissive_uv = a_tex_coord_{index};\n"));
+            for uv in self.texture_uvs() {
+                if let Some(index) = uv.uv_index {
+                    s.push_str(&format!("v_{}_uv = a_tex_coord_{index};\n", uv.name));
+                }
             }
 
             s
@@ -293,3 +331,43 @@ impl ShaderKey {
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skinned_key(n_joints: usize) -> ShaderKey {
+        let mut key = ShaderKey {
+            position_attribute_loc: Some(0),
+            skin_targets: vec![SkinTarget { joint_loc: 1, weight_loc: 2 }],
+            ..ShaderKey::default()
+        };
+        key.set_skin_joints(n_joints);
+        key
+    }
+
+    #[test]
+    fn skin_uniform_array() {
+        let key = skinned_key(20);
+        assert!(!key.skin_texture);
+
+        let code = key.into_vertex_code().unwrap();
+        assert!(code.contains("uniform mat4 u_skin_joint[20];"));
+        assert!(code.contains("get_skin_joint(int(a_skin_joint_1[0]))"));
+    }
+
+    #[test]
+    fn skin_texture_over_255_joints() {
+        for n_joints in [256, 300, 1024] {
+            let key = skinned_key(n_joints);
+            assert_eq!(key.n_skin_joints as usize, n_joints);
+            assert!(key.skin_texture);
+
+            let code = key.into_vertex_code().unwrap();
+            assert!(code.contains("uniform highp sampler2D u_skin_joint_sampler;"));
+            assert!(code.contains("mat4 get_skin_joint(int index)"));
+            assert!(code.contains("get_skin_joint(int(a_skin_joint_1[0]))"));
+            assert!(!code.contains("uniform mat4 u_skin_joint["));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Joint matrices for large skeletons
+ *
+ * Uniform arrays run out quickly (a mat4 is 4 of the ~256 guaranteed vertex uniform vectors)
+ * so skins with lots of joints are packed into a float texture instead, one row per skin
+ * and 4 texels (columns) per joint, read back with texelFetch in the vertex shader
+ *
+ * Unlike the uniform palette, these are in world space (not relative to the mesh)
+ * since the row is shared by every mesh using that skin.
+ * The shader then skips u_model for those meshes, see INCLUDES_SKIN_FN in vertex.rs
+ */
+use crate::prelude::*;
+use awsm_web::{
+    data::TypedData,
+    webgl::{
+        WebGl2Renderer,
+        TextureTarget,
+        TextureOptions,
+        PixelInternalFormat,
+        PixelDataFormat,
+        DataType,
+        TextureWrapTarget,
+        TextureWrapMode,
+        TextureMinFilter,
+        TextureMagFilter,
+        WebGlTextureSource,
+        PartialWebGlTextures,
+    }
+};
+use js_sys::Float32Array;
+use nalgebra_glm::Mat4;
+use web_sys::WebGl2RenderingContext;
+
+// past this many joints, the skin is read from the texture
+pub(crate) const MAX_SKIN_JOINT_UNIFORMS:usize = 48;
+
+pub struct SkinTexture {
+    pub(crate) texture_id: Option<Id>,
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+    // rebuilt every frame, one per skin (i.e. joint entities and their inverse bind matrices)
+    // a handful of skins at most, so these are just compared in order
+    rows: Vec<(Vec<EntityId>, Vec<Mat4>)>,
+}
+
+impl SkinTexture {
+    pub fn new() -> Self {
+        Self {
+            texture_id: None,
+            width: 0,
+            height: 0,
+            data: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub(crate) fn row(&self, mesh: &Mesh) -> Option<u32> {
+        self.rows
+            .iter()
+            .position(|(joints, inverse_bind_mats)| *joints == mesh.skin_joints && *inverse_bind_mats == mesh.skin_inverse_bind_mats)
+            .map(|row| row as u32)
+    }
+
+    // expects the mesh program to already be active
+    pub(crate) fn upload_uniforms(&self, gl: &mut WebGl2Renderer, mesh: &Mesh) -> Result<()> {
+        match (self.texture_id, self.row(mesh)) {
+            (Some(texture_id), Some(row)) => {
+                gl.activate_texture_sampler_name(texture_id, "u_skin_joint_sampler")?;
+                gl.upload_uniform_ival_name("u_skin_joint_row", row as i32)?;
+                Ok(())
+            },
+            _ => bail!("skin texture wasn't updated for this mesh")
+        }
+    }
+}
+
+impl AwsmRenderer {
+    // must be called after update_skin_joints_sys and before any drawing
+    pub(crate) fn update_skin_texture(&mut self, meshes: &View<Mesh>, mesh_skin_joints: &View<MeshSkinJoint>) -> Result<()> {
+        let skin_texture = &mut self.skin_texture;
+
+        skin_texture.rows.clear();
+        let mut max_joints = 0;
+
+        for mesh in meshes.iter() {
+            if mesh.shader_key.skin_texture && !mesh.skin_joints.is_empty() && skin_texture.row(mesh).is_none() {
+                skin_texture.rows.push((mesh.skin_joints.clone(), mesh.skin_inverse_bind_mats.clone()));
+                max_joints = max_joints.max(mesh.skin_joints.len());
+            }
+        }
+
+        if skin_texture.rows.is_empty() {
+            return Ok(());
+        }
+
+        skin_texture.width = (max_joints * 4) as u32;
+        skin_texture.height = skin_texture.rows.len() as u32;
+        let row_len = skin_texture.width as usize * 4;
+        skin_texture.data.resize(row_len * skin_texture.height as usize, 0.0);
+
+        for (row, (skin_joints, inverse_bind_mats)) in skin_texture.rows.iter().enumerate() {
+            let row_data = &mut skin_texture.data[row * row_len..(row + 1) * row_len];
+
+            for (index, (entity, inverse_bind_mat)) in skin_joints.iter().zip(inverse_bind_mats.iter()).enumerate() {
+                let target = &mut row_data[index * 16..(index + 1) * 16];
+                match mesh_skin_joints.get(*entity) {
+                    Ok(skin_joint) => target.copy_from_slice((skin_joint.joint_mat * inverse_bind_mat).as_slice()),
+                    Err(_) => target.copy_from_slice(Mat4::identity().as_slice())
+                }
+            }
+        }
+
+        let gl = &mut self.gl;
+
+        let texture_id = match skin_texture.texture_id {
+            Some(texture_id) => texture_id,
+            None => {
+                let texture_id = gl.create_texture()?;
+                skin_texture.texture_id = Some(texture_id);
+                texture_id
+            }
+        };
+
+        let data:Float32Array = TypedData::new(&skin_texture.data).into();
+
+        gl.assign_texture(
+            texture_id,
+            TextureTarget::Texture2d,
+            &TextureOptions{
+                internal_format: PixelInternalFormat::Rgba32f,
+                data_format: PixelDataFormat::Rgba,
+                data_type: DataType::Float,
+                cube_face: None,
+            },
+            Some(|gl:&WebGl2RenderingContext| {
+                gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::S, TextureWrapMode::ClampToEdge);
+                gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::T, TextureWrapMode::ClampToEdge);
+                gl.awsm_texture_set_min_filter(TextureTarget::Texture2d, TextureMinFilter::Nearest);
+                gl.awsm_texture_set_mag_filter(TextureTarget::Texture2d, TextureMagFilter::Nearest);
+            }),
+            &WebGlTextureSource::ArrayBufferView(&data, skin_texture.width, skin_texture.height, 1)
+        )?;
+
+        gl.release_texture_target(TextureTarget::Texture2d);
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for SkinTexture {
+    fn destroy(&mut self, gl:&mut WebGl2Renderer) -> Result<()> {
+        if let Some(texture_id) = self.texture_id.take() {
+            gl.delete_texture(texture_id)?;
+        }
+        Ok(())
+    }
+}
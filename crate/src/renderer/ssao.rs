@@ -0,0 +1,369 @@
+/*
+ * Screen-space ambient occlusion
+ *
+ * The occlusion has to exist before the lit pass reads it, so the main fbo's depth can't be used
+ * instead there's a cheap prepass of the opaque and mask queues into its own target:
+ * view-space normals (mesh vertex shader + ssao_prepass.frag) and a sampleable depth texture
+ * (masked meshes discard below their alpha cutoff, so their holes don't occlude)
+ *
+ * Then two fullscreen passes: the hemisphere sampling itself, and a 4x4 box blur
+ * to clean up the per-pixel kernel rotation
+ *
+ * The mesh shader multiplies only the ambient terms (IBL or the ambient hack) by the result
+ * so punctual lights are unaffected
+ *
+ * Like shadows, whether it's enabled is baked into the mesh shaders
+ * so it's toggled rarely via renderer.set_ssao()
+ */
+use crate::prelude::*;
+use super::{
+    draw_buffers::{FrameBuffer, FrameBufferId, FrameBufferIdKind, make_hdr_texture, make_depth_texture},
+    post_process::draw_fullscreen_triangle,
+    queue::DrawItem,
+    systems::{upload_mesh_vertex_uniforms, upload_alpha_mask_uniforms},
+};
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    TextureTarget,
+    FrameBufferTarget,
+    FrameBufferAttachment,
+    FrameBufferTextureTarget,
+    GlToggle,
+    CmpFunction,
+    Buffer,
+    UniformType,
+};
+use nalgebra_glm::{Vec3, Mat4};
+
+// must match MAX_KERNEL_SIZE in ssao.frag
+pub(crate) const MAX_SSAO_KERNEL_SIZE:usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct SsaoConfig {
+    // sample hemisphere radius, in world units
+    pub radius: f32,
+    // depth difference below which a sample doesn't count, avoids self-occlusion acne
+    pub bias: f32,
+    // exponent applied to the final term, higher is darker
+    pub intensity: f32,
+    // samples per pixel (up to MAX_SSAO_KERNEL_SIZE)
+    pub kernel_size: u32,
+}
+
+impl Default for SsaoConfig {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.0,
+            kernel_size: 32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SsaoPrograms {
+    pub occlusion: Id,
+    pub blur: Id,
+}
+
+pub struct Ssao {
+    pub(crate) config: Option<SsaoConfig>,
+    // vec3 per sample, in tangent space
+    kernel: Vec<f32>,
+    targets: Option<SsaoTargets>,
+}
+
+struct SsaoTargets {
+    // view-space normals and depth
+    prepass: FrameBuffer,
+    occlusion: FrameBuffer,
+    blurred: FrameBuffer,
+    width: u32,
+    height: u32,
+}
+
+impl Ssao {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            kernel: Vec::new(),
+            targets: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    // expects the mesh program to already be active
+    pub(crate) fn upload_uniforms(&self, gl: &mut WebGl2Renderer) -> Result<()> {
+        if let Some(color) = self.targets.as_ref().and_then(|targets| targets.blurred.color) {
+            gl.activate_texture_sampler_name(color.id, "u_ssao_sampler")?;
+        }
+        Ok(())
+    }
+
+    fn update_targets(&mut self, gl: &mut WebGl2Renderer, width: u32, height: u32) -> Result<()> {
+        match &self.targets {
+            Some(targets) if targets.width == width && targets.height == height => {
+                return Ok(());
+            },
+            _ => {}
+        }
+
+        if let Some(mut targets) = self.targets.take() {
+            targets.destroy(gl)?;
+        }
+
+        self.targets = Some(SsaoTargets {
+            prepass: make_target(gl, width, height, true)?,
+            occlusion: make_target(gl, width, height, false)?,
+            blurred: make_target(gl, width, height, false)?,
+            width,
+            height,
+        });
+
+        Ok(())
+    }
+}
+
+impl AwsmRenderer {
+    pub fn set_ssao(&mut self, world: &World, config: Option<SsaoConfig>) -> Result<()> {
+        let changed = self.ssao.enabled() != config.is_some();
+
+        self.ssao.destroy(&mut self.gl)?;
+
+        if let Some(config) = config.as_ref() {
+            self.ssao.kernel = hemisphere_kernel((config.kernel_size as usize).clamp(1, MAX_SSAO_KERNEL_SIZE));
+        }
+
+        self.ssao.config = config;
+
+        if changed {
+            self.recompile_mesh_programs(world)?;
+        }
+
+        Ok(())
+    }
+
+    // must be after update_camera_ubo() and update_render_queues()
+    // and leaves the result in the blurred target for the mesh shaders to sample
+    pub(crate) fn render_ssao(
+        &mut self,
+        meshes: &View<Mesh>,
+        materials: &View<Material>,
+        mesh_morph_weights: &View<MeshMorphWeights>,
+        mesh_skin_joints: &View<MeshSkinJoint>,
+        world_transforms: &View<WorldTransform>,
+    ) -> Result<()> {
+        let config = match self.ssao.config.clone() {
+            Some(config) => config,
+            None => return Ok(())
+        };
+
+        let (projection, projection_inverse) = match self.camera.get_active_dyn() {
+            Some(camera) => {
+                let projection:Mat4 = camera.projection().cast();
+                let projection_inverse = projection.try_inverse().unwrap_or_else(Mat4::identity);
+                (projection, projection_inverse)
+            },
+            None => return Ok(())
+        };
+
+        let (_, _, width, height) = self.gl.get_viewport();
+        self.ssao.update_targets(&mut self.gl, width, height)?;
+
+        // prepass
+        let (prepass_fbo, normals_id, depth_id) = {
+            let prepass = &self.ssao.targets.as_ref().ok_or_else(|| anyhow!("no ssao targets"))?.prepass;
+            let normals = prepass.color.ok_or_else(|| anyhow!("ssao prepass has no color"))?;
+            let depth = prepass.depth.ok_or_else(|| anyhow!("ssao prepass has no depth"))?;
+            (prepass.id, normals.id, depth.id)
+        };
+
+        {
+            let gl = &mut self.gl;
+            gl.bind_framebuffer(prepass_fbo, FrameBufferTarget::DrawFrameBuffer)?;
+            gl.reset_depth_stencil_draw_buffer();
+            gl.clear_draw_buffer_vf32_values(Buffer::Color, 0, &[0.0, 0.0, 1.0, 1.0]);
+
+            gl.set_depth_mask(true);
+            gl.toggle(GlToggle::Blend, false);
+            gl.toggle(GlToggle::DepthTest, true);
+            gl.set_depth_func(CmpFunction::Less);
+        }
+
+        let mut mat4_buf:[f32;16] = [0.0;16];
+        let mut skin_buf:Vec<f32> = Vec::new();
+
+        // blended meshes don't write depth, so they don't occlude either
+        let items:Vec<DrawItem> = self.render_queues.opaque.iter()
+            .chain(self.render_queues.mask.iter())
+            .map(|queue_item| queue_item.item)
+            .collect();
+
+        for item in items {
+            match item {
+                DrawItem::Entity(entity) => {
+                    let mesh = meshes.get(entity)?;
+                    let program_id = self.ssao_prepass_program(&mesh.shader_key)?;
+                    let gl = &mut self.gl;
+
+                    gl.activate_program(program_id)?;
+                    gl.activate_vertex_array(mesh.vao_id)?;
+                    upload_mesh_vertex_uniforms(gl, entity, mesh, world_transforms.get(entity)?, mesh_morph_weights, mesh_skin_joints, &self.skin_texture, &mut mat4_buf, &mut skin_buf)?;
+
+                    if let Ok(Material::Pbr(pbr)) = materials.get(entity) {
+                        gl.toggle(GlToggle::CullFace, !pbr.double_sided);
+                    }
+
+                    if mesh.shader_key.alpha_mask_key().is_some() {
+                        if let Ok(material) = materials.get(entity) {
+                            upload_alpha_mask_uniforms(gl, material)?;
+                        }
+                    }
+
+                    mesh.draw(gl);
+                },
+                DrawItem::Batch(index) => {
+                    let first = self.instancing.batches[index].entities[0];
+                    let mesh = meshes.get(first)?;
+
+                    let mut shader_key = mesh.shader_key.clone();
+                    shader_key.instance_model_loc = mesh.instance_attribute_loc;
+                    let program_id = self.ssao_prepass_program(&shader_key)?;
+
+                    let batch = &self.instancing.batches[index];
+                    let gl = &mut self.gl;
+
+                    gl.activate_program(program_id)?;
+                    gl.activate_vertex_array(batch.vao_id)?;
+                    self.instancing.buffer.upload(gl, mesh, &batch.entities, world_transforms)?;
+
+                    if let Ok(Material::Pbr(pbr)) = materials.get(first) {
+                        gl.toggle(GlToggle::CullFace, !pbr.double_sided);
+                    }
+
+                    if mesh.shader_key.alpha_mask_key().is_some() {
+                        if let Ok(material) = materials.get(first) {
+                            upload_alpha_mask_uniforms(gl, material)?;
+                        }
+                    }
+
+                    mesh.draw_instanced(gl, batch.entities.len() as u32)?;
+                }
+            }
+        }
+
+        let targets = self.ssao.targets.as_ref().ok_or_else(|| anyhow!("no ssao targets"))?;
+        let occlusion_id = targets.occlusion.color.ok_or_else(|| anyhow!("ssao target has no color"))?.id;
+        let gl = &mut self.gl;
+
+        gl.toggle(GlToggle::DepthTest, false);
+        gl.toggle(GlToggle::CullFace, false);
+
+        // occlusion
+        gl.bind_framebuffer(targets.occlusion.id, FrameBufferTarget::DrawFrameBuffer)?;
+        gl.activate_program(self.shaders.programs.ssao.occlusion)?;
+        gl.activate_texture_sampler_name(depth_id, "u_depth_sampler")?;
+        gl.activate_texture_sampler_name(normals_id, "u_normal_sampler")?;
+        gl.upload_uniform_fvec_name("u_kernel", UniformType::Vector3, &self.ssao.kernel)?;
+        gl.upload_uniform_ival_name("u_kernel_size", (self.ssao.kernel.len() / 3) as i32)?;
+        gl.upload_uniform_fval_name("u_radius", config.radius)?;
+        gl.upload_uniform_fval_name("u_bias", config.bias)?;
+        gl.upload_uniform_fval_name("u_intensity", config.intensity)?;
+        gl.upload_uniform_mat_4_name("u_projection", projection.as_slice())?;
+        gl.upload_uniform_mat_4_name("u_projection_inverse", projection_inverse.as_slice())?;
+        draw_fullscreen_triangle(gl);
+
+        // blur
+        gl.bind_framebuffer(targets.blurred.id, FrameBufferTarget::DrawFrameBuffer)?;
+        gl.activate_program(self.shaders.programs.ssao.blur)?;
+        gl.activate_texture_sampler_name(occlusion_id, "u_color_sampler")?;
+        draw_fullscreen_triangle(gl);
+
+        gl.toggle(GlToggle::DepthTest, true);
+        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+        Ok(())
+    }
+}
+
+// samples in the +z hemisphere, more of them closer to the origin
+// halton sequences instead of random so it's the same every time
+fn hemisphere_kernel(size: usize) -> Vec<f32> {
+    let mut kernel = Vec::with_capacity(size * 3);
+
+    for i in 0..size {
+        let sample = Vec3::new(
+            halton(i + 1, 2) * 2.0 - 1.0,
+            halton(i + 1, 3) * 2.0 - 1.0,
+            halton(i + 1, 5).max(0.05),
+        ).normalize() * halton(i + 1, 7);
+
+        let scale = i as f32 / size as f32;
+        let scale = 0.1 + (scale * scale) * 0.9;
+
+        kernel.extend_from_slice((sample * scale).as_slice());
+    }
+
+    kernel
+}
+
+fn halton(mut index: usize, base: usize) -> f32 {
+    let mut result = 0.0;
+    let mut f = 1.0;
+
+    while index > 0 {
+        f /= base as f32;
+        result += f * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
+fn make_target(gl: &mut WebGl2Renderer, width: u32, height: u32, with_depth: bool) -> Result<FrameBuffer> {
+    let id = gl.create_framebuffer()?;
+    let color_id = make_hdr_texture(gl, width, height)?;
+    gl.assign_framebuffer_texture_2d(id, color_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0, FrameBufferTextureTarget::Texture2d)?;
+
+    let depth = if with_depth {
+        let depth_id = make_depth_texture(gl, width, height)?;
+        gl.assign_framebuffer_texture_2d(id, depth_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Depth, FrameBufferTextureTarget::Texture2d)?;
+        Some(FrameBufferId { kind: FrameBufferIdKind::Texture, multisample: false, id: depth_id })
+    } else {
+        None
+    };
+
+    gl.check_framebuffer_status(FrameBufferTarget::DrawFrameBuffer)?;
+
+    gl.release_texture_target(TextureTarget::Texture2d);
+    gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+    Ok(FrameBuffer {
+        id,
+        depth,
+        color: Some(FrameBufferId { kind: FrameBufferIdKind::Texture, multisample: false, id: color_id }),
+    })
+}
+
+impl DestroyWithGl for SsaoTargets {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        self.prepass.destroy(gl)?;
+        self.occlusion.destroy(gl)?;
+        self.blurred.destroy(gl)?;
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for Ssao {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        if let Some(mut targets) = self.targets.take() {
+            targets.destroy(gl)?;
+        }
+        self.kernel.clear();
+        Ok(())
+    }
+}
@@ -5,18 +5,24 @@ use awsm_web::webgl::{
     BeginMode, 
     BufferTarget, 
     UniformType, TextureTarget, CmpFunction,
+    WebGl2Renderer,
 };
 use nalgebra::Matrix4;
+use std::cell::Cell;
+use nalgebra_glm::Mat4;
 use super::draw_buffers::DrawBuffers;
 use super::cleanup::DestroyWithGl;
+use super::skin_texture::SkinTexture;
 use crate::{
     prelude::*,
     camera::{
         screen_static::ScreenStatic,
         arc_ball::ArcBall
     },
-    light::Light
+    light::{Light, shadow::{ShadowCaster, ShadowReceiver}},
+    bounds::{Aabb, BoundingSphere, WorldBounds},
 };
+use super::queue::DrawItem;
 
 pub fn render_sys(
     renderer: &mut AwsmRenderer,
@@ -26,14 +32,32 @@ pub fn render_sys(
     mesh_skin_joints: View<MeshSkinJoint>, 
     material:View<Material>, 
     world_transforms: View<WorldTransform>,
+    shadow_casters: View<ShadowCaster>,
+    shadow_receivers: View<ShadowReceiver>,
+    world_bounds: View<WorldBounds>,
 ) -> Result<()> {
     let renderer:&mut AwsmRenderer = &mut *renderer;
 
-    renderer.update_lights_ubo((&world_transforms, &lights).iter())?;
+    // before anything reads the camera
+    renderer.update_scene_camera(&world_transforms)?;
+
+    let frustum = renderer.camera_frustum();
+
+    renderer.update_lights((&world_transforms, &lights).iter())?;
+    renderer.update_skin_texture(&meshes, &mesh_skin_joints)?;
+    // must be before update_camera_ubo, since it borrows the camera ubo
+    renderer.render_shadow_maps(&meshes, &material, &lights, &shadow_casters, &mesh_morph_weights, &mesh_skin_joints, &world_transforms, &world_bounds)?;
+    renderer.update_instance_batches(&meshes, &material, &world_transforms, &mesh_morph_weights, &shadow_receivers, frustum.as_ref(), &world_bounds)?;
     if !renderer.update_camera_ubo()? {
         return Ok(());
     }
 
+    renderer.update_render_queues(&meshes, &material, &world_transforms, &world_bounds, frustum.as_ref())?;
+    renderer.update_transmission()?;
+    renderer.update_post_process()?;
+    renderer.render_ssao(&meshes, &material, &mesh_morph_weights, &mesh_skin_joints, &world_transforms)?;
+    let deferred_lighting = renderer.update_deferred(&meshes, &lights, &world_transforms)?;
+
     let gl = &mut renderer.gl;
     match (renderer.draw_buffers.as_mut(), renderer.camera.active.as_mut()) {
         
@@ -41,16 +65,123 @@ pub fn render_sys(
 
 
             let mut mat4_buf:[f32;16] = [0.0;16];
-            // forward vs. deferred is not totally right yet
-            // but the buffers are sorta kinda setup ish
-            // (probably just get rid of deferred and rely on culling)
+            let mut skin_buf:Vec<f32> = Vec::new();
+
+            // the IBL uniforms and samplers stay put until the program changes
+            // reset to None whenever another pass might have taken over the texture units
+            let environment_program:Cell<Option<Id>> = Cell::new(None);
+            let upload_environment_uniforms = |gl: &mut WebGl2Renderer, program_id: Id| -> Result<()> {
+                if let Some(environment) = renderer.environment.as_ref() {
+                    if environment_program.get() != Some(program_id) {
+                        environment.upload_uniforms(gl)?;
+                        environment_program.set(Some(program_id));
+                    }
+                }
+                Ok(())
+            };
+
+            // program_id is from the queue, since it might be the g-buffer variant
+            let mut draw_item = |gl: &mut WebGl2Renderer, item: DrawItem, program_id: Id, deferred: bool| -> Result<()> {
+                match item {
+                    DrawItem::Entity(entity) => {
+                        let mesh = meshes.get(entity)?;
+
+                        gl.activate_program(program_id)?;
+                        gl.activate_vertex_array(mesh.vao_id)?;
+                        upload_mesh_vertex_uniforms(gl, entity, mesh, world_transforms.get(entity)?, &mesh_morph_weights, &mesh_skin_joints, &renderer.skin_texture, &mut mat4_buf, &mut skin_buf)?;
+
+                        upload_environment_uniforms(gl, program_id)?;
+
+                        // the g-buffer just gets the receiver flag, lights are applied afterwards
+                        if deferred {
+                            gl.upload_uniform_fval_name("u_shadow_receiver", if shadow_receivers.contains(entity) { 1.0 } else { 0.0 })?;
+                        } else {
+                            renderer.lights.upload_uniforms(gl, true)?;
+                            renderer.shadows.upload_uniforms(gl, shadow_receivers.contains(entity))?;
+                        }
+
+                        if renderer.ssao.enabled() {
+                            renderer.ssao.upload_uniforms(gl)?;
+                        }
+
+                        upload_material_uniforms(gl, material.get(entity)?)?;
+
+                        if mesh.shader_key.transmission {
+                            if let Some(transmission) = draw_buffers.transmission.as_ref() {
+                                transmission.upload_uniforms(gl)?;
+                            }
+                        }
+
+                        mesh.draw(gl);
+                    },
+                    // the model matrix is an attribute so there's no u_model to upload
+                    DrawItem::Batch(index) => {
+                        let batch = &renderer.instancing.batches[index];
+                        let first = batch.entities[0];
+                        let mesh = meshes.get(first)?;
+
+                        gl.activate_program(program_id)?;
+                        gl.activate_vertex_array(batch.vao_id)?;
+                        renderer.instancing.buffer.upload(gl, mesh, &batch.entities, &world_transforms)?;
+
+                        upload_environment_uniforms(gl, program_id)?;
+
+                        if deferred {
+                            gl.upload_uniform_fval_name("u_shadow_receiver", if batch.shadow_receiver { 1.0 } else { 0.0 })?;
+                        } else {
+                            renderer.lights.upload_uniforms(gl, true)?;
+                            renderer.shadows.upload_uniforms(gl, batch.shadow_receiver)?;
+                        }
+
+                        if renderer.ssao.enabled() {
+                            renderer.ssao.upload_uniforms(gl)?;
+                        }
+
+                        upload_material_uniforms(gl, material.get(first)?)?;
+
+                        if mesh.shader_key.transmission {
+                            if let Some(transmission) = draw_buffers.transmission.as_ref() {
+                                transmission.upload_uniforms(gl)?;
+                            }
+                        }
+
+                        mesh.draw_instanced(gl, batch.entities.len() as u32)?;
+                    }
+                }
+
+                Ok(())
+            };
+
+            // in DrawBufferMode::Deferred this binds the g-buffer for the opaques
+            // and the lights are applied right after them (see renderer/deferred.rs)
             draw_buffers.pre_draw(gl)?;
 
-            // TODO - move to end, just here for debugging
+            // opaque and mask, front-to-back with depth writes
+            gl.set_depth_mask(true);
+            gl.toggle(GlToggle::Blend, false);
+            gl.toggle(GlToggle::DepthTest, true);
+            gl.set_depth_func(CmpFunction::Less);
+
+            for queue_item in renderer.render_queues.opaque.iter().chain(renderer.render_queues.mask.iter()) {
+                draw_item(gl, queue_item.item, queue_item.program_id, deferred_lighting.is_some())?;
+            }
+
+            if let Some(deferred_lighting) = deferred_lighting.as_ref() {
+                draw_buffers.render_deferred_lighting(gl, deferred_lighting, &renderer.lights, &renderer.shadows)?;
+
+                // whatever the g-buffer couldn't hold, lit as usual on top
+                if !deferred_lighting.forward.is_empty() {
+                    environment_program.set(None);
+                    for queue_item in deferred_lighting.forward.iter() {
+                        draw_item(gl, queue_item.item, queue_item.program_id, false)?;
+                    }
+                }
+            }
+
+            // after the opaques, so it only fills in whatever is left at the far plane
             if let Some(skybox) = renderer.skybox.as_ref() {
-                gl.set_depth_mask(true);
-                gl.toggle(GlToggle::Blend, false);
-                gl.toggle(GlToggle::DepthTest, true);
+                gl.set_depth_mask(false);
+                gl.toggle(GlToggle::CullFace, false);
                 gl.set_depth_func(CmpFunction::Lequal);
                 gl.activate_program(renderer.shaders.programs.skybox)?;
                 gl.activate_vertex_array(draw_buffers.quad.vao_id)?;
@@ -58,117 +189,287 @@ pub fn render_sys(
                 gl.draw_arrays(BeginMode::TriangleStrip, 0, 4); 
             }
 
+            // like the opaques, but sampling a copy of everything drawn so far
+            if !renderer.render_queues.transmission.is_empty() {
+                draw_buffers.render_transmission_background(gl)?;
+                environment_program.set(None);
+
+                gl.set_depth_mask(true);
+                gl.set_depth_func(CmpFunction::Less);
+
+                // always forward
+                for queue_item in renderer.render_queues.transmission.iter() {
+                    draw_item(gl, queue_item.item, queue_item.program_id, false)?;
+                }
+            }
+
+            // blend, back-to-front, tested against the opaques but not written
+            if !renderer.render_queues.blend.is_empty() {
+                gl.set_depth_mask(false);
+                gl.toggle(GlToggle::Blend, true);
+                gl.set_depth_func(CmpFunction::Less);
+                gl.set_blend_func(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha);
+                environment_program.set(None);
+
+                // always forward
+                for queue_item in renderer.render_queues.blend.iter() {
+                    draw_item(gl, queue_item.item, queue_item.program_id, false)?;
+                }
+            }
+
             gl.set_depth_mask(true);
-            gl.toggle(GlToggle::Blend, true);
-            gl.toggle(GlToggle::DepthTest, true);
-            gl.set_depth_func(CmpFunction::Less);
-            gl.set_blend_func(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha);
 
-            for (entity, (mesh, material, world_transform,))
-                in 
-                (&meshes, &material, &world_transforms)
-                .iter()
-                .with_id()
-                {
+            draw_buffers.post_draw(gl, &mut renderer.post_process)?;
+        },
 
+        _ => {}
+    }
 
-                    // let mut mat = WorldTransform::new(Matrix4::identity());
-                    // mat.write_to_vf32(&mut world_transform_buf);
-                    world_transform.write_to_vf32(&mut mat4_buf);
-                    gl.activate_program(mesh.program_id)?;
-                    gl.activate_vertex_array(mesh.vao_id)?;
-                    gl.upload_uniform_mat_4_name("u_model", &mat4_buf)?;
+    Ok(())
+}
 
-                    if let Ok(morph_weights) = mesh_morph_weights.get(entity) {
-                        gl.upload_uniform_fvec_name("u_morph_weight", UniformType::Vector1, &morph_weights.0)?;
-                    }
+pub(crate) fn upload_material_uniforms(gl: &mut WebGl2Renderer, material: &Material) -> Result<()> {
+    match material {
+        Material::Pbr(pbr) => {
+            gl.toggle(GlToggle::CullFace, !pbr.double_sided);
 
-                    // skins exist, conceptually, in a separate hierarchy
-                    // so need to get their transform via querying (it's not on this entity)
-                    // TODO - can significantly improve by writing all to one large buffer and then uploading 
-                    // since the uniforms will just flow into the next
-                    // to know the size of our upload slice, maybe allocate in renderer, or locally
-                    // resize as needed
-                    for (i, skin_joint_entity) in mesh.skin_joints.iter().enumerate() {
-                        if let Ok(skin_joint) = mesh_skin_joints.get(*skin_joint_entity) {
-                            skin_joint.world_transform.write_to_vf32(&mut mat4_buf);
-                            gl.upload_uniform_mat_4_name(&format!("u_skin_joint[{}]", i), &mat4_buf)?;
-                        }
-                    }
+            if let Some(alpha_mode) = pbr.alpha_mode {
+                if let AlphaMode::Mask { cutoff } = alpha_mode {
+                    gl.upload_uniform_fval_name("u_alpha_cutoff", cutoff);
+                }
+            }
+            gl.upload_uniform_fvec_name("u_base_color_factor", UniformType::Vector4, &pbr.base_color_factor.as_slice());
+            gl.upload_uniform_fvec_name("u_emissive_factor", UniformType::Vector3, &pbr.emissive_factor.as_slice());
 
+            let metallic_roughness:[f32;2] = [pbr.metallic_factor, pbr.roughness_factor];
 
-                    match material {
-                        Material::Pbr(pbr) => {
-                            gl.toggle(GlToggle::CullFace, !pbr.double_sided);
+            gl.upload_uniform_fvec_name("u_metallic_roughness_factors", UniformType::Vector2, &metallic_roughness);
 
-                            if let Some(alpha_mode) = pbr.alpha_mode {
-                                if let AlphaMode::Mask { cutoff } = alpha_mode {
-                                    gl.upload_uniform_fval_name("u_alpha_cutoff", cutoff);
-                                }
-                            }
-                            gl.upload_uniform_fvec_name("u_base_color_factor", UniformType::Vector4, &pbr.base_color_factor.as_slice());
-                            gl.upload_uniform_fvec_name("u_emissive_factor", UniformType::Vector3, &pbr.emissive_factor.as_slice());
+            if let Some(tex) = &pbr.base_color_texture {
+                gl.activate_texture_sampler_name(tex.id, "u_base_color_sampler");
+                upload_uv_transform(gl, tex, "u_base_color_uv_transform")?;
+            }
+            if let Some(tex) = &pbr.metallic_roughness_texture {
+                gl.activate_texture_sampler_name(tex.id, "u_metallic_roughness_sampler");
+                upload_uv_transform(gl, tex, "u_metallic_roughness_uv_transform")?;
+            }
+            if let Some(tex) = &pbr.emissive_texture {
+                gl.activate_texture_sampler_name(tex.id, "u_emissive_sampler");
+                upload_uv_transform(gl, tex, "u_emissive_uv_transform")?;
+            }
+            if let Some(tex) = &pbr.normal_texture {
+                gl.activate_texture_sampler_name(tex.id, "u_normal_sampler");
+                upload_uv_transform(gl, tex, "u_normal_uv_transform")?;
+                gl.upload_uniform_fval_name("u_normal_texture_scale", pbr.normal_texture_scale.unwrap_or(1.0));
+            }
+            if let Some(tex) = &pbr.occlusion_texture {
+                gl.activate_texture_sampler_name(tex.id, "u_occlusion_sampler")?;
+                upload_uv_transform(gl, tex, "u_occlusion_uv_transform")?;
+                gl.upload_uniform_fval_name("u_occlusion_strength", pbr.occlusion_strength.unwrap_or(1.0))?;
+            }
 
-                            let metallic_roughness:[f32;2] = [pbr.metallic_factor, pbr.roughness_factor];
+            // clearcoat, sheen, specular and iridescence are only read by the lighting (LIGHTS and IBL)
+            // so the variant might have compiled out any of these, same as the post-process inputs
+            if let Some(clearcoat) = &pbr.clearcoat {
+                let _ = gl.upload_uniform_fval_name("u_clearcoat_factor", clearcoat.factor);
+                let _ = gl.upload_uniform_fval_name("u_clearcoat_roughness_factor", clearcoat.roughness_factor);
 
-                            gl.upload_uniform_fvec_name("u_metallic_roughness_factors", UniformType::Vector2, &metallic_roughness);
+                if let Some(tex) = &clearcoat.texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_clearcoat_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_clearcoat_uv_transform");
+                }
+                if let Some(tex) = &clearcoat.roughness_texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_clearcoat_roughness_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_clearcoat_roughness_uv_transform");
+                }
+                if let Some(tex) = &clearcoat.normal_texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_clearcoat_normal_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_clearcoat_normal_uv_transform");
+                    let _ = gl.upload_uniform_fval_name("u_clearcoat_normal_scale", clearcoat.normal_texture_scale.unwrap_or(1.0));
+                }
+            }
 
-                            if let Some(tex) = &pbr.base_color_texture {
-                                gl.activate_texture_sampler_name(tex.id, "u_base_color_sampler");
-                            }
-                            if let Some(tex) = &pbr.metallic_roughness_texture {
-                                gl.activate_texture_sampler_name(tex.id, "u_metallic_roughness_sampler");
-                            }
-                            if let Some(tex) = &pbr.emissive_texture {
-                                gl.activate_texture_sampler_name(tex.id, "u_emissive_sampler");
-                            }
-                            if let Some(tex) = &pbr.normal_texture {
-                                gl.activate_texture_sampler_name(tex.id, "u_normal_sampler");
-                                gl.upload_uniform_fval_name("u_normal_texture_scale", pbr.normal_texture_scale.unwrap_or(1.0));
-                            }
-                        }
-                    }
+            if let Some(sheen) = &pbr.sheen {
+                let _ = gl.upload_uniform_fvec_name("u_sheen_color_factor", UniformType::Vector3, &sheen.color_factor.as_slice());
+                let _ = gl.upload_uniform_fval_name("u_sheen_roughness_factor", sheen.roughness_factor);
 
+                if let Some(tex) = &sheen.color_texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_sheen_color_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_sheen_color_uv_transform");
+                }
+                if let Some(tex) = &sheen.roughness_texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_sheen_roughness_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_sheen_roughness_uv_transform");
+                }
+            }
 
-                    match mesh.draw_strategy {
-                        DrawStrategy::Arrays { mode, first, count } => {
-                            //log::info!("drawing arrays: {:?} {} {}", mode, first, count);
-                            gl.draw_arrays(mode, first, count);
-                        },
-                        DrawStrategy::Elements { mode, count, data_type, offset} => {
-                            //log::info!("drawing elements: {:?} {} {:?}, {}", mode, count, data_type, offset);
-                            gl.draw_elements(mode, count, data_type, offset);
-                        }
+            if let Some(specular) = &pbr.specular {
+                let _ = gl.upload_uniform_fval_name("u_khr_specular_factor", specular.factor);
+                let _ = gl.upload_uniform_fvec_name("u_khr_specular_color_factor", UniformType::Vector3, &specular.color_factor.as_slice());
+
+                if let Some(tex) = &specular.texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_specular_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_specular_uv_transform");
+                }
+                if let Some(tex) = &specular.color_texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_specular_color_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_specular_color_uv_transform");
+                }
+            }
+
+            if let Some(transmission) = &pbr.transmission {
+                gl.upload_uniform_fval_name("u_transmission_factor", transmission.factor)?;
+
+                if let Some(tex) = &transmission.texture {
+                    gl.activate_texture_sampler_name(tex.id, "u_transmission_sampler")?;
+                    upload_uv_transform(gl, tex, "u_transmission_uv_transform")?;
+                }
+
+                if let Some(volume) = &pbr.volume {
+                    gl.upload_uniform_fval_name("u_thickness_factor", volume.thickness_factor)?;
+                    gl.upload_uniform_fvec_name("u_attenuation_color", UniformType::Vector3, &volume.attenuation_color.as_slice())?;
+                    // the shader takes 0.0 as infinite
+                    gl.upload_uniform_fval_name("u_attenuation_distance", if volume.attenuation_distance.is_finite() { volume.attenuation_distance } else { 0.0 })?;
+
+                    if let Some(tex) = &volume.thickness_texture {
+                        gl.activate_texture_sampler_name(tex.id, "u_volume_thickness_sampler")?;
+                        upload_uv_transform(gl, tex, "u_volume_thickness_uv_transform")?;
                     }
-                    //forward::render(&mut gl, mesh, material, &world_transform_buf).unwrap_throw();
                 }
+            }
 
-            draw_buffers.post_draw(gl)?;
-        },
+            // see clearcoat above, and the thickness range is only read with a thickness texture
+            if let Some(iridescence) = &pbr.iridescence {
+                let _ = gl.upload_uniform_fval_name("u_iridescence_factor", iridescence.factor);
+                let _ = gl.upload_uniform_fval_name("u_iridescence_ior", iridescence.ior);
+                let _ = gl.upload_uniform_fval_name("u_iridescence_thickness_min", iridescence.thickness_minimum);
+                let _ = gl.upload_uniform_fval_name("u_iridescence_thickness_max", iridescence.thickness_maximum);
 
-        _ => {}
+                if let Some(tex) = &iridescence.texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_iridescence_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_iridescence_uv_transform");
+                }
+                if let Some(tex) = &iridescence.thickness_texture {
+                    let _ = gl.activate_texture_sampler_name(tex.id, "u_iridescence_thickness_sampler");
+                    let _ = upload_uv_transform(gl, tex, "u_iridescence_thickness_uv_transform");
+                }
+            }
+
+            if let Some(ior) = pbr.ior {
+                gl.upload_uniform_fval_name("u_ior", ior)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// KHR_texture_transform, only declared in the shader if the key has the slot's flag
+fn upload_uv_transform(gl: &mut WebGl2Renderer, tex: &TextureInfo, name: &str) -> Result<()> {
+    if let Some(transform) = &tex.transform {
+        gl.upload_uniform_mat_3_name(name, transform.matrix().as_slice())?;
+    }
+
+    Ok(())
+}
+
+// just what the alpha_mask() of the depth-only passes needs, see AlphaMaskKey
+pub(crate) fn upload_alpha_mask_uniforms(gl: &mut WebGl2Renderer, material: &Material) -> Result<()> {
+    match material {
+        Material::Pbr(pbr) => {
+            if let Some(AlphaMode::Mask { cutoff }) = pbr.alpha_mode {
+                gl.upload_uniform_fval_name("u_alpha_cutoff", cutoff)?;
+            }
+            gl.upload_uniform_fvec_name("u_base_color_factor", UniformType::Vector4, &pbr.base_color_factor.as_slice())?;
+
+            if let Some(tex) = &pbr.base_color_texture {
+                gl.activate_texture_sampler_name(tex.id, "u_base_color_sampler")?;
+                upload_uv_transform(gl, tex, "u_base_color_uv_transform")?;
+            }
+        }
     }
 
     Ok(())
 }
 
+// uniforms that only affect the vertex shader
+// shared with the depth-only passes
+pub(crate) fn upload_mesh_vertex_uniforms(
+    gl: &mut WebGl2Renderer,
+    entity: EntityId,
+    mesh: &Mesh,
+    world_transform: &WorldTransform,
+    mesh_morph_weights: &View<MeshMorphWeights>, 
+    mesh_skin_joints: &View<MeshSkinJoint>, 
+    skin_texture: &SkinTexture,
+    mat4_buf: &mut [f32;16],
+    skin_buf: &mut Vec<f32>,
+) -> Result<()> {
+    world_transform.write_to_vf32(mat4_buf);
+    gl.upload_uniform_mat_4_name("u_model", &*mat4_buf)?;
+
+    if let Ok(morph_weights) = mesh_morph_weights.get(entity) {
+        gl.upload_uniform_fvec_name("u_morph_weight", UniformType::Vector1, &morph_weights.0)?;
+    }
+
+    // skins exist, conceptually, in a separate hierarchy
+    // so need to get their transform via querying (it's not on this entity)
+    // and then make it relative to the mesh, since u_model is applied after skinning
+    // all joints are uploaded as one array
+    if mesh.shader_key.skin_texture {
+        skin_texture.upload_uniforms(gl, mesh)?;
+    } else if !mesh.skin_joints.is_empty() {
+        let world_transform:&Mat4 = &world_transform;
+        let mesh_inverse = world_transform.try_inverse().unwrap_or_else(Mat4::identity);
+
+        skin_buf.clear();
+        for (skin_joint_entity, inverse_bind_mat) in mesh.skin_joints.iter().zip(mesh.skin_inverse_bind_mats.iter()) {
+            let joint_mat = match mesh_skin_joints.get(*skin_joint_entity) {
+                Ok(skin_joint) => mesh_inverse * skin_joint.joint_mat * inverse_bind_mat,
+                Err(_) => Mat4::identity()
+            };
+            skin_buf.extend_from_slice(joint_mat.as_slice());
+        }
+        gl.upload_uniform_mat_4_name("u_skin_joint", &*skin_buf)?;
+    }
+
+    Ok(())
+}
+
+// WorldTransform modification tracking is never cleared (see update_skin_joints_sys)
+// so this compares against the transform the bounds were last computed from instead
+pub fn update_world_bounds_sys(
+    entities: EntitiesViewMut,
+    aabbs: View<Aabb>,
+    bounding_spheres: View<BoundingSphere>,
+    world_transforms: View<WorldTransform>,
+    mut world_bounds: ViewMut<WorldBounds>,
+) {
+    for (entity, (aabb, bounding_sphere, world_transform)) in (&aabbs, &bounding_spheres, &world_transforms).iter().with_id() {
+        let world_transform:&Mat4 = &world_transform;
+
+        match (&mut world_bounds).get(entity) {
+            Ok(bounds) => {
+                if bounds.transform != *world_transform {
+                    *bounds = WorldBounds::new(aabb, bounding_sphere, world_transform);
+                }
+            },
+            Err(_) => {
+                entities.add_component(entity, &mut world_bounds, WorldBounds::new(aabb, bounding_sphere, world_transform));
+            }
+        }
+    }
+}
+
 pub fn update_skin_joints_sys(
     mut mesh_skin_joints: ViewMut<MeshSkinJoint>, 
     world_transforms: View<WorldTransform>,
 ) {
 
-    // works
-    for (entity, (mut mesh_skin_joint, world_transform)) in (&mut mesh_skin_joints, &world_transforms)
-        .iter()
-        .with_id() 
-        .filter(|(entity, _)| {
-            world_transforms.is_modified(*entity)
-        })
-        {
-            if crate::debug::gate::only_once() {
-                log::warn!("DOUBLE CHECK THIS LOGIC TO USE INVERSE BIND ETC.");
-            }
-            mesh_skin_joint.world_transform.copy_from(world_transform);
-        }
+    // WorldTransform modification tracking is never cleared, so filtering on it wouldn't save anything
+    for (mut mesh_skin_joint, world_transform) in (&mut mesh_skin_joints, &world_transforms).iter() {
+        let world_transform:&Mat4 = &world_transform;
+        mesh_skin_joint.joint_mat = world_transform.clone();
+    }
 
 }
@@ -0,0 +1,95 @@
+/*
+ * Tonemapping
+ *
+ * The main fbo is RGBA16F and meshes write linear HDR color (see LINEAR_OUTPUT in the mesh shader)
+ * so this is always the last thing in the post-processing chain:
+ * exposure, then the Config::tonemap curve, then sRGB encoding
+ *
+ * Each curve is its own program (selected via a define in tone_map.glsl) compiled on first use
+ */
+use crate::prelude::*;
+use super::post_process::{PostProcessPass, PostProcessInput, draw_fullscreen_triangle};
+use awsm_web::webgl::WebGl2Renderer;
+use std::collections::hash_map::Entry;
+
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+    // just exposure and clamping
+    None,
+    Reinhard,
+    AcesNarkowicz,
+    AcesHill,
+    AcesHillExposureBoost,
+    KhronosPbrNeutral,
+}
+
+impl Default for Tonemap {
+    fn default() -> Self {
+        Self::KhronosPbrNeutral
+    }
+}
+
+impl Tonemap {
+    pub(crate) fn define(&self) -> &'static str {
+        match self {
+            Self::None => "#define TONEMAP_NONE\n",
+            Self::Reinhard => "#define TONEMAP_REINHARD\n",
+            Self::AcesNarkowicz => "#define TONEMAP_ACES_NARKOWICZ\n",
+            Self::AcesHill => "#define TONEMAP_ACES_HILL\n",
+            Self::AcesHillExposureBoost => "#define TONEMAP_ACES_HILL_EXPOSURE_BOOST\n",
+            Self::KhronosPbrNeutral => "#define TONEMAP_KHRONOS_PBR_NEUTRAL\n",
+        }
+    }
+}
+
+pub(crate) struct TonemapPass {
+    // set every frame from the config, see AwsmRenderer::update_post_process
+    pub program_id: Option<Id>,
+    pub exposure: f32,
+}
+
+impl TonemapPass {
+    pub fn new() -> Self {
+        Self {
+            program_id: None,
+            exposure: 1.0,
+        }
+    }
+}
+
+impl PostProcessPass for TonemapPass {
+    fn render(&mut self, gl: &mut WebGl2Renderer, input: &PostProcessInput) -> Result<()> {
+        let program_id = self.program_id.ok_or_else(|| anyhow!("tonemap program isn't set"))?;
+
+        gl.activate_program(program_id)?;
+        input.upload_uniforms(gl)?;
+        gl.upload_uniform_fval_name("u_Exposure", self.exposure)?;
+
+        draw_fullscreen_triangle(gl);
+
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for TonemapPass {
+    fn destroy(&mut self, _gl: &mut WebGl2Renderer) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl AwsmRenderer {
+    pub fn tonemap_program(&mut self, tonemap: Tonemap) -> Result<Id> {
+        let shaders = &mut self.shaders;
+        let gl = &mut self.gl;
+
+        match shaders.programs.tonemap.entry(tonemap) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let fragment_id = shaders.fragments.tonemap_shader(gl, tonemap)?;
+                let program_id = gl.compile_program(&vec![shaders.vertices.fullscreen_triangle, fragment_id])?;
+
+                Ok(entry.insert(program_id).clone())
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+/*
+ * KHR_materials_transmission (and KHR_materials_volume)
+ *
+ * Transmissive meshes get their own queue, drawn after the opaques and skybox but before blending
+ * Right before that, the main color is blitted into a mipmapped copy (which also resolves msaa)
+ * and the transmission shader samples it behind the surface, at a mip level from the roughness
+ *
+ * So transmissive meshes only see the opaque scene through them, not each other or anything blended
+ *
+ * Created on the first frame that has something transmissive, and recreated with the draw buffers on resize
+ */
+use crate::prelude::*;
+use awsm_web::webgl::{
+    WebGl2Renderer,
+    TextureTarget,
+    FrameBufferTarget,
+    FrameBufferAttachment,
+    FrameBufferTextureTarget,
+    BufferMask,
+    BlitFilter,
+    UniformType,
+    PartialWebGlTextures,
+    TextureMinFilter,
+    TextureMagFilter,
+    TextureWrapTarget,
+    TextureWrapMode,
+};
+use super::draw_buffers::make_hdr_texture;
+
+pub struct TransmissionBuffer {
+    fbo_id: Id,
+    texture_id: Id,
+    width: u32,
+    height: u32,
+}
+
+impl TransmissionBuffer {
+    pub fn new(gl: &mut WebGl2Renderer, width: u32, height: u32) -> Result<Self> {
+        let fbo_id = gl.create_framebuffer()?;
+        let texture_id = make_hdr_texture(gl, width, height)?;
+
+        // the mips are filled in after each copy
+        gl.gl.awsm_texture_set_min_filter(TextureTarget::Texture2d, TextureMinFilter::LinearMipMapLinear);
+        gl.gl.awsm_texture_set_mag_filter(TextureTarget::Texture2d, TextureMagFilter::Linear);
+        gl.gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::S, TextureWrapMode::ClampToEdge);
+        gl.gl.awsm_texture_set_wrap(TextureTarget::Texture2d, TextureWrapTarget::T, TextureWrapMode::ClampToEdge);
+
+        gl.assign_framebuffer_texture_2d(fbo_id, texture_id, FrameBufferTarget::DrawFrameBuffer, FrameBufferAttachment::Color0, FrameBufferTextureTarget::Texture2d)?;
+        gl.check_framebuffer_status(FrameBufferTarget::DrawFrameBuffer)?;
+
+        gl.release_texture_target(TextureTarget::Texture2d);
+        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+        Ok(Self {
+            fbo_id,
+            texture_id,
+            width,
+            height,
+        })
+    }
+
+    // copies whatever has been drawn into the main fbo so far
+    // leaves nothing bound, the caller needs to rebind the main fbo
+    pub(crate) fn copy_from(&self, gl: &mut WebGl2Renderer, fbo_id: Id) -> Result<()> {
+        gl.bind_framebuffer(fbo_id, FrameBufferTarget::ReadFrameBuffer)?;
+        gl.bind_framebuffer(self.fbo_id, FrameBufferTarget::DrawFrameBuffer)?;
+        gl.blit_framebuffer(
+            0,0, self.width, self.height,
+            0,0, self.width, self.height,
+            BufferMask::ColorBufferBit,
+            BlitFilter::Nearest
+        );
+        gl.release_framebuffer(FrameBufferTarget::ReadFrameBuffer);
+        gl.release_framebuffer(FrameBufferTarget::DrawFrameBuffer);
+
+        let texture = gl.get_texture(self.texture_id)?;
+        gl.gl.awsm_bind_texture(TextureTarget::Texture2d, texture);
+        gl.gl.generate_mipmap(TextureTarget::Texture2d as u32);
+        gl.release_texture_target(TextureTarget::Texture2d);
+
+        Ok(())
+    }
+
+    pub(crate) fn upload_uniforms(&self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.activate_texture_sampler_name(self.texture_id, "u_transmission_framebuffer_sampler")?;
+        gl.upload_uniform_ivec_name("u_transmission_framebuffer_size", UniformType::Vector2, &[self.width as i32, self.height as i32])?;
+        Ok(())
+    }
+}
+
+impl DestroyWithGl for TransmissionBuffer {
+    fn destroy(&mut self, gl: &mut WebGl2Renderer) -> Result<()> {
+        gl.delete_framebuffer(self.fbo_id)?;
+        gl.delete_texture(self.texture_id)?;
+        Ok(())
+    }
+}
+
+impl AwsmRenderer {
+    // must be after update_render_queues()
+    pub(crate) fn update_transmission(&mut self) -> Result<()> {
+        if self.render_queues.transmission.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(draw_buffers) = self.draw_buffers.as_mut() {
+            if draw_buffers.transmission.is_none() {
+                draw_buffers.transmission = Some(TransmissionBuffer::new(&mut self.gl, draw_buffers.width, draw_buffers.height)?);
+            }
+        }
+
+        Ok(())
+    }
+}
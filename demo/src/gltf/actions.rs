@@ -1,5 +1,5 @@
 use crate::{prelude::*, world::TRANSFORMS, gltf::component::{GltfResourceWrapper, GltfResourceWrapperView}};
-use awsm_renderer::{gltf::{loader::{load_gltf, GltfResource}, component::GltfPrimitive}, light::Light};
+use awsm_renderer::{gltf::{loader::{load_gltf, GltfResource}, component::GltfPrimitive}, light::{Light, shadow::{ShadowCaster, ShadowReceiver}}, bounds::WorldBounds};
 use nalgebra_glm::Vec3;
 use crate::light::add_demo_lights;
 
@@ -15,6 +15,18 @@ pub async fn switch_gltf(renderer: Rc<RefCell<AwsmRenderer>>, world: Rc<RefCell<
 
     renderer.borrow_mut().populate_gltf(&mut *world.borrow_mut(), &res, None)?;
 
+    // every mesh both casts and receives shadows
+    world.borrow_mut().run(|
+        entities: EntitiesViewMut,
+        meshes: View<Mesh>,
+        mut shadow_casters: ViewMut<ShadowCaster>,
+        mut shadow_receivers: ViewMut<ShadowReceiver>,
+    | {
+        for (entity, _) in meshes.iter().with_id() {
+            entities.add_component(entity, (&mut shadow_casters, &mut shadow_receivers), (ShadowCaster, ShadowReceiver));
+        }
+    });
+
     world.borrow_mut().run(move |mut wrapper: GltfResourceWrapperViewMut| {
         wrapper.0 = Some(res);
     });
@@ -106,52 +118,28 @@ impl Bounds {
 pub fn calculate_gltf_bounds(world: &mut World) -> Result<Option<Bounds>> {
     let mut bounds:Option<Bounds> = None;
 
+    // also updates the world bounds
     world.run_workload(TRANSFORMS)?;
 
-
-    let (res, transforms, gltf_primitives) 
+    let (res, world_bounds) 
         = world.borrow::<(
             GltfResourceWrapperView,
-            View<WorldTransform>,
-            View<GltfPrimitive>,
+            View<WorldBounds>,
         )>()?;
 
     match res.0.as_ref() {
         None => Ok(None),
-        Some(res) => {
-            for (transform, primitive) in (&transforms, &gltf_primitives).iter() {
-
-                let x = transform[0];
-                let y = transform[1];
-                let z = transform[2];
-
-                if let Some(mesh) = res.gltf.meshes().nth(primitive.mesh_index) {
-                    if let Some(primitive) = mesh.primitives().nth(primitive.index) {
-                        if bounds.is_none() {
-                            bounds = Some(Bounds::default());
-                        }
-                        let b = bounds.as_mut().unwrap_ext();
-                        let bounding_box = primitive.bounding_box();
-
-                        b.update_min([
-                            bounding_box.min[0],
-                            bounding_box.min[1],
-                            bounding_box.min[2],
-                        ]);
-                        b.update_max([
-                            bounding_box.max[0],
-                            bounding_box.max[1],
-                            bounding_box.max[2]
-                        ]);
-                    } else {
-                        log::warn!("no primitive #{} on mesh!", primitive.index);
-                    }
-                
-                } else {
-                    log::warn!("no mesh #{}!", primitive.mesh_index);
+        Some(_) => {
+            for world_bounds in world_bounds.iter() {
+                if bounds.is_none() {
+                    bounds = Some(Bounds::default());
                 }
-            }
+                let b = bounds.as_mut().unwrap_ext();
+                let aabb = &world_bounds.aabb;
 
+                b.update_min([aabb.min.x, aabb.min.y, aabb.min.z]);
+                b.update_max([aabb.max.x, aabb.max.y, aabb.max.z]);
+            }
 
             Ok(bounds)
         }
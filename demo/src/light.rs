@@ -1,5 +1,5 @@
 use crate::prelude::*;
-use awsm_renderer::light::Light;
+use awsm_renderer::light::{Light, shadow::ShadowCaster};
 use nalgebra_glm::Vec3;
 
 impl GltfId {
@@ -40,7 +40,7 @@ impl GltfId {
                     (
                         Vec3::default(),
                         Light::Directional { 
-                            direction: Vec3::new(1.0, -1.0, -1.0), 
+                            direction: Some(Vec3::new(1.0, -1.0, -1.0)), 
                             color: Vec3::new(1.0, 1.0, 1.0), 
                             intensity: 3.0 
                         }
@@ -48,7 +48,7 @@ impl GltfId {
                     (
                         Vec3::default(),
                         Light::Directional { 
-                            direction: Vec3::new(-1.0, 1.0, 1.0), 
+                            direction: Some(Vec3::new(-1.0, 1.0, 1.0)), 
                             color: Vec3::new(1.0, 1.0, 1.0), 
                             intensity: 3.0 
                         }
@@ -63,19 +63,25 @@ pub fn add_demo_lights(world: &mut World, id: GltfId) -> Result<()> {
 
     let lights = id.default_lights();
 
-    for (translation, light) in lights {
+    for (index, (translation, light)) in lights.into_iter().enumerate() {
         let entity = world.borrow::<SceneGraphStoragesMut>()?.spawn_child_trs(None, Some(translation), None, None);
 
         world.run(|
             entities: EntitiesViewMut,
             mut awsm_items: ViewMut<AwsmRendererItem>,
-            mut lights: ViewMut<Light>
+            mut lights: ViewMut<Light>,
+            mut shadow_casters: ViewMut<ShadowCaster>,
         | {
             entities.add_component(
                 entity, 
                 (&mut awsm_items, &mut lights), 
                 (AwsmRendererItem {}, light)
             );
+
+            // just the key light casts shadows
+            if index == 0 {
+                entities.add_component(entity, &mut shadow_casters, ShadowCaster);
+            }
         });
     }
 
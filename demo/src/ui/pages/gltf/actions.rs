@@ -80,6 +80,8 @@ impl GltfPage {
                         camera.y += evt.movement_y() as f64;
                         camera.update_projection();
                     }
+                    // follows its entity, not the mouse
+                    CameraKind::Scene(_) => {}
                 }
             },
             _ => {}
@@ -105,6 +107,7 @@ impl GltfPage {
                     camera.zoom -= (evt.delta_y() / 100.0);
                     camera.update_projection();
                 }
+                CameraKind::Scene(_) => {}
             }
         }
     }
@@ -1,6 +1,6 @@
 use std::sync::atomic::Ordering;
 
-use awsm_renderer::{image::ImageLoader, cubemap::{cubemap::CubeMap, skybox::Skybox}};
+use awsm_renderer::{image::ImageLoader, cubemap::{cubemap::CubeMap, skybox::Skybox, environment::Environment}};
 use awsm_web::loaders::helpers::{FutureHandle, spawn_handle};
 
 use crate::{prelude::*, config::CONFIG};
@@ -15,7 +15,7 @@ impl Sidebar {
             None => {
                 if state.skybox_selected.load(Ordering::SeqCst) {
                     let fut = clone!(state => async move { 
-                        let skybox = {
+                        let (skybox, environment) = {
                             log::info!("loading...");
                             let image = ImageLoader::load_url(&format!("{}/skybox/{}", CONFIG.image_url, CONFIG.skybox_image)).await.unwrap_ext();
                             let renderer = state.page.renderer_cell();
@@ -23,10 +23,11 @@ impl Sidebar {
                             let img_texture_id = image.to_texture(renderer).unwrap_ext();
                             let (img_width, img_height) = image.size();
                             let cubemap = CubeMap::new_panorama(renderer, img_texture_id, img_width, img_height).unwrap_ext();
-                            Skybox::new(renderer, cubemap).unwrap_ext()
+                            let environment = Environment::new(renderer, &cubemap).unwrap_ext();
+                            (Skybox::new(renderer, cubemap).unwrap_ext(), environment)
                         };
 
-                        *state.skybox_loader.borrow_mut() = Some(SkyboxLoader::Loaded(skybox));
+                        *state.skybox_loader.borrow_mut() = Some(SkyboxLoader::Loaded(skybox, environment));
                         state.do_skybox();
                     });
 
@@ -40,17 +41,21 @@ impl Sidebar {
                 //do nothing, it will be dealt with when loading finishes
             }
 
-            Some(SkyboxLoader::Loaded(skybox)) => {
+            Some(SkyboxLoader::Loaded(skybox, environment)) => {
                 let skybox_selected = state.skybox_selected.load(Ordering::SeqCst);
                 log::info!("skybox selected: {}", skybox_selected);
                 let renderer = state.page.renderer_cell();
                 let renderer = &mut *renderer.borrow_mut();
+                let world = state.page.world_cell();
+                let world = &*world.borrow();
                 match skybox_selected { 
                     true => {
                         renderer.skybox = Some(skybox.clone());
+                        renderer.set_environment(world, Some(environment.clone())).unwrap_ext();
                     }
                     false => {
                         renderer.skybox = None;
+                        renderer.set_environment(world, None).unwrap_ext();
                     }
                 }
             }
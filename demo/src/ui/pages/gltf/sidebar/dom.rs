@@ -121,7 +121,7 @@ impl Sidebar {
         Checkbox::new("Multisample Renderer".to_string(), crate::config::DEFAULT_MULTISAMPLE_RENDERER, clone!(state => move |value| {
             let renderer = state.page.renderer_cell();
             let mut renderer = renderer.borrow_mut();
-            renderer.config.multisample = value;
+            renderer.config.draw_buffer_mode = if value { awsm_renderer::renderer::DrawBufferMode::Multisample } else { awsm_renderer::renderer::DrawBufferMode::Regular };
 
             let (_, _, width, height) = renderer.gl.get_viewport();
             renderer.resize(awsm_web::webgl::ResizeStrategy::All(width, height)).unwrap_ext();
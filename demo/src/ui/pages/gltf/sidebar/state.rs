@@ -1,6 +1,6 @@
 use std::sync::atomic::AtomicBool;
 
-use awsm_renderer::cubemap::skybox::Skybox;
+use awsm_renderer::cubemap::{skybox::Skybox, environment::Environment};
 use awsm_web::loaders::helpers::FutureHandle;
 use futures::Future;
 
@@ -16,7 +16,7 @@ pub struct Sidebar {
 #[derive(Clone)]
 pub enum SkyboxLoader {
     Loading(Rc<FutureHandle>),
-    Loaded(Skybox),
+    Loaded(Skybox, Environment),
 }
 
 impl Sidebar {
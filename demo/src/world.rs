@@ -8,10 +8,12 @@ use awsm_renderer::{
     renderer::{
         systems::{
             render_sys,
-            update_skin_joints_sys
+            update_skin_joints_sys,
+            update_world_bounds_sys,
         },
         CanvasOrGl,
-        Config
+        Config,
+        DrawBufferMode,
     }, 
     animation::systems::{
         animation_clock_sys,
@@ -20,6 +22,7 @@ use awsm_renderer::{
         animation_update_scale_sys,
         animation_update_morph_sys,
     }, cubemap::skybox::Skybox,
+    light::shadow::ShadowConfig,
 };
 
 pub const TRANSFORMS:&'static str = "TRANSFORMS";
@@ -39,15 +42,22 @@ pub fn init_world(canvas:HtmlCanvasElement) -> Result<(Rc<RefCell<World>>, Rc<Re
         ),
         Config {
             clear_color: [0.5, 0.5, 0.5, 1.0],
-            multisample: crate::config::DEFAULT_MULTISAMPLE_RENDERER
+            draw_buffer_mode: if crate::config::DEFAULT_MULTISAMPLE_RENDERER { DrawBufferMode::Multisample } else { DrawBufferMode::Regular },
+            retain_mesh_geometry: false,
+            tonemap: Default::default(),
+            exposure: 1.0,
+            bloom: None,
         }
     )?));
 
+    renderer.borrow_mut().set_shadows(&*world.borrow(), Some(ShadowConfig::default()))?;
+
 
     Workload::new(TRANSFORMS)
         .with_system(local_transform_sys)
         .with_system(world_transform_sys)
         .with_system(update_skin_joints_sys)
+        .with_system(update_world_bounds_sys)
         .add_to_world(&*world.borrow())
         .unwrap_ext();
 